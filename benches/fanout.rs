@@ -0,0 +1,77 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Sends a 1 MB Map to many recipients.
+//!
+//! `deep_copy` reproduces the cost of the old payload representation, where
+//! every recipient received its own copy of the whole tree; `shared` is the
+//! current one, where recipients share the same reference-counted tree.
+
+#[macro_use]
+extern crate criterion;
+extern crate mecha;
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+
+use criterion::{BenchmarkId, Criterion};
+use mecha::{ActorAddress, Message, MessageDatum};
+
+const FANOUT: &str = ":fanout";
+
+fn big_map() -> MessageDatum {
+    let m: HashMap<String, MessageDatum> = (0..1024)
+        .map(|i| (format!("key{}", i), MessageDatum::from("x".repeat(1024))))
+        .collect();
+    MessageDatum::from(m)
+}
+
+fn deep_copy(d: &MessageDatum) -> MessageDatum {
+    match *d {
+        MessageDatum::Str(ref s) => MessageDatum::from(s.to_string()),
+        MessageDatum::Map(ref m) => {
+            let m: HashMap<String, MessageDatum> = m.iter()
+                .map(|(k, v)| (k.clone(), deep_copy(v)))
+                .collect();
+            MessageDatum::from(m)
+        },
+        ref other => other.clone()
+    }
+}
+
+fn fanout(c: &mut Criterion) {
+    let datum = big_map();
+    let mut group = c.benchmark_group("fanout_1mb_map");
+    group.sample_size(20);
+    for recipients in [1usize, 8, 64].iter() {
+        let (tx, rx) = mpsc::channel();
+        let addresses: Vec<ActorAddress> = (0..*recipients)
+            .map(|_| ActorAddress::new(tx.clone()))
+            .collect();
+        group.bench_with_input(BenchmarkId::new("deep_copy", recipients), &addresses, |b, to| {
+            b.iter(|| {
+                for a in to {
+                    Message::custom(FANOUT).with_datum(deep_copy(&datum)).send_to(a);
+                }
+                rx.try_iter().count()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("shared", recipients), &addresses, |b, to| {
+            b.iter(|| {
+                for a in to {
+                    Message::custom(FANOUT).with_datum(datum.clone()).send_to(a);
+                }
+                rx.try_iter().count()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, fanout);
+criterion_main!(benches);
@@ -0,0 +1,240 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! This crate provides `#[derive(MechaMessage)]` for enums describing the
+//! messages accepted by a mecha actor. It is re-exported by mecha when its
+//! "derive" feature is enabled.
+//!
+//! Each variant becomes a Custom message type, named after the variant in
+//! snake case with a leading colon (`SayHello` is sent as `:say_hello`), unless
+//! a name is given with `#[mecha(name = ":something")]`. The fields of the
+//! variant become the datum of the message:
+//!
+//! - a unit variant is sent with a Void datum;
+//! - a variant with a single unnamed field is sent with the datum of the field;
+//! - a variant with many unnamed fields is sent with a Tuple;
+//! - a variant with named fields is sent with a Map from the field names.
+//!
+//! Fields are converted with `MessageDatum::from` and back with
+//! `mecha::FromDatum`, so their types must support both.
+//!
+//! The derive implements `mecha::TypedMessage`, including `message_types`,
+//! which lists the names of all the variants. It also generates:
+//!
+//! - an associated constant with the name of each message type, in screaming
+//!   snake case (`Greeting::SAY_HELLO`), which can be used in patterns such as
+//!   `MessageType::Custom(Greeting::SAY_HELLO)`;
+//! - an associated function `matches`, which can be passed to `with_match` to
+//!   match any of the messages.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate syn;
+#[macro_use]
+extern crate quote;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as Tokens};
+use syn::{Data, DeriveInput, Fields, Ident, LitStr, Variant};
+
+/// Derives `mecha::TypedMessage` for an enum. See the crate documentation for
+/// how variants are turned into messages.
+#[proc_macro_derive(MechaMessage, attributes(mecha))]
+pub fn derive_mecha_message(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into()
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<Tokens> {
+    let data = match input.data {
+        Data::Enum(ref data) => data,
+        _ => {
+            return Err(syn::Error::new_spanned(&input.ident,
+                "MechaMessage can only be derived for enums"));
+        }
+    };
+    if data.variants.is_empty() {
+        return Err(syn::Error::new_spanned(&input.ident,
+            "MechaMessage needs at least one variant"));
+    }
+
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut consts = Vec::new();
+    let mut names = Vec::new();
+    let mut type_arms = Vec::new();
+    let mut to_arms = Vec::new();
+    let mut from_arms = Vec::new();
+    for v in data.variants.iter() {
+        let name = message_name(v)?;
+        let konst = Ident::new(&screaming_snake_case(&v.ident.to_string()), v.ident.span());
+        let doc = format!("The name of the message type of `{}::{}`.", ty, v.ident);
+        consts.push(quote! {
+            #[doc = #doc]
+            pub const #konst: &'static str = #name;
+        });
+        names.push(quote! { #ty::#konst });
+        let (pattern, to, from) = convert(ty, v);
+        type_arms.push(quote! { #pattern => #ty::#konst });
+        to_arms.push(quote! { #pattern => #to });
+        from_arms.push(quote! { #name => #from });
+    }
+
+    Ok(quote! {
+        impl #impl_generics #ty #ty_generics #where_clause {
+            #(#consts)*
+
+            /// Matches the messages which convert to this type. This can be
+            /// passed to `Actor::with_match` directly.
+            pub fn matches<S>(msg: &::mecha::Message, _state: &S) -> bool {
+                <Self as ::mecha::TypedMessage>::from_message(msg).is_some()
+            }
+        }
+
+        impl #impl_generics ::mecha::TypedMessage for #ty #ty_generics #where_clause {
+            fn message_type(&self) -> &'static str {
+                match *self { #(#type_arms,)* }
+            }
+
+            fn to_datum(&self) -> ::mecha::MessageDatum {
+                match *self { #(#to_arms,)* }
+            }
+
+            fn message_types() -> &'static [&'static str] {
+                &[#(#names),*]
+            }
+
+            fn from_datum(mt: &str, datum: &::mecha::MessageDatum) -> Option<Self> {
+                match mt {
+                    #(#from_arms,)*
+                    _ => None
+                }
+            }
+        }
+    })
+}
+
+/// Gets the name of the message type of a variant, from its attributes or from
+/// the name of the variant.
+fn message_name(v: &Variant) -> syn::Result<LitStr> {
+    let mut name = None;
+    for attr in v.attrs.iter().filter(|a| a.path().is_ident("mecha")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown mecha attribute, expected `name`"))
+            }
+        })?;
+    }
+    Ok(name.unwrap_or_else(|| {
+        LitStr::new(&format!(":{}", snake_case(&v.ident.to_string())), Span::call_site())
+    }))
+}
+
+/// Builds the pattern binding the fields of a variant by reference, and the
+/// expressions converting them to a datum and back.
+fn convert(ty: &Ident, v: &Variant) -> (Tokens, Tokens, Tokens) {
+    let var = &v.ident;
+    match v.fields {
+        Fields::Unit => (
+            quote! { #ty::#var },
+            quote! { ::mecha::MessageDatum::Void },
+            quote! {
+                match *datum {
+                    ::mecha::MessageDatum::Void => Some(#ty::#var),
+                    _ => None
+                }
+            }
+        ),
+        Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 => {
+            let fty = &fields.unnamed[0].ty;
+            (
+                quote! { #ty::#var(ref f0) },
+                quote! { ::mecha::MessageDatum::from(::std::clone::Clone::clone(f0)) },
+                quote! { Some(#ty::#var(<#fty as ::mecha::FromDatum>::from_datum(datum)?)) }
+            )
+        },
+        Fields::Unnamed(ref fields) => {
+            let count = fields.unnamed.len();
+            let binds: Vec<Ident> = (0..count).map(|i| format_ident!("f{}", i)).collect();
+            let values = fields.unnamed.iter().enumerate().map(|(i, f)| {
+                let fty = &f.ty;
+                quote! { <#fty as ::mecha::FromDatum>::from_datum(&items[#i])? }
+            });
+            (
+                quote! { #ty::#var(#(ref #binds),*) },
+                quote! {
+                    ::mecha::MessageDatum::Tuple(vec![
+                        #(::mecha::MessageDatum::from(::std::clone::Clone::clone(#binds))),*
+                    ].into())
+                },
+                quote! {{
+                    let items = datum.as_tuple_ref()?;
+                    if items.len() != #count {
+                        return None;
+                    }
+                    Some(#ty::#var(#(#values),*))
+                }}
+            )
+        },
+        Fields::Named(ref fields) => {
+            let binds: Vec<&Ident> = fields.named.iter()
+                .map(|f| f.ident.as_ref().unwrap())
+                .collect();
+            let keys: Vec<String> = binds.iter().map(|b| b.to_string()).collect();
+            let values = fields.named.iter().zip(keys.iter()).map(|(f, key)| {
+                let fty = &f.ty;
+                let bind = &f.ident;
+                quote! { #bind: <#fty as ::mecha::FromDatum>::from_datum(map.get(#key)?)? }
+            });
+            (
+                quote! { #ty::#var { #(ref #binds),* } },
+                quote! {{
+                    let mut map = ::std::collections::HashMap::new();
+                    #(map.insert(#keys.to_string(),
+                                 ::mecha::MessageDatum::from(::std::clone::Clone::clone(#binds)));)*
+                    ::mecha::MessageDatum::from(map)
+                }},
+                quote! {{
+                    let map = datum.as_map_ref()?;
+                    Some(#ty::#var { #(#values),* })
+                }}
+            )
+        }
+    }
+}
+
+/// Converts a CamelCase identifier to snake_case. Words start at an uppercase
+/// letter which follows a lowercase letter or a digit, and at the last letter
+/// of a run of uppercase ones followed by a lowercase letter, so that
+/// `HTTPGet` becomes `http_get`.
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_lower) {
+                out.push('_');
+            }
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
+}
+
+/// Converts a CamelCase identifier to SCREAMING_SNAKE_CASE.
+fn screaming_snake_case(name: &str) -> String {
+    snake_case(name).to_uppercase()
+}
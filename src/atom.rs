@@ -0,0 +1,170 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Atoms are interned names, which allow the names of Call and Custom message
+//! types to be created at runtime (for example, from a configuration file).
+//!
+//! The atom table holds a single copy of each distinct name, which lives as
+//! long as the program: creating an atom from a name which is already in the
+//! table returns the same `&'static str`. Therefore, two atoms are equal if
+//! and only if they point to the same string, which makes comparing them
+//! cheap.
+//!
+//! Atoms are never freed, so the table is bounded by `MAX_ATOMS` to prevent an
+//! untrusted source of names from exhausting memory. For the same reason,
+//! names received from other nodes are only looked up, and a message whose
+//! type is not in the table is dropped. The table holds the names of the
+//! messages built with `Message::call` and `Message::custom`, the message
+//! types of the crate itself (declared when a node starts), and those of the
+//! typed messages an actor handles. A process which only receives other
+//! messages of a type, without building any, declares it with
+//! `Atom::from_static`.
+//!
+//! ```text
+//! let mt = mecha::Atom::new(&name_from_config);
+//! mecha::Message::custom(mt.as_str()).send_to(&actor);
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ptr;
+use std::sync::{OnceLock, RwLock};
+
+use event::{EVENT_COMMAND, EVENT_HANDLER_EXITED, WHICH_HANDLERS};
+use fsm::FSM_TIMEOUT;
+use global::GLOBAL_NAME_CONFLICT;
+use membership::{NODE_DOWN, NODE_UP};
+use reliable::{RELIABLE_ACK, RELIABLE_DATA, RELIABLE_TICK};
+use router::{ROUTER_RESIZE, ROUTER_TICK, ROUTER_WORKERS};
+use sharding::{SHARDING_DELIVER, SHARDING_ENTITIES, SHARDING_TICK};
+use singleton::SINGLETON_TICK;
+
+/// The maximum number of atoms in the atom table.
+pub const MAX_ATOMS: usize = 1 << 20;
+
+/// The message types the crate sends on its own, which a node must be able to
+/// receive even if its process never built one.
+const INTERNAL_TYPES: &[&str] = &[
+    EVENT_COMMAND, EVENT_HANDLER_EXITED, WHICH_HANDLERS,
+    FSM_TIMEOUT,
+    GLOBAL_NAME_CONFLICT,
+    NODE_DOWN, NODE_UP,
+    RELIABLE_ACK, RELIABLE_DATA, RELIABLE_TICK,
+    ROUTER_RESIZE, ROUTER_TICK, ROUTER_WORKERS,
+    SHARDING_DELIVER, SHARDING_ENTITIES, SHARDING_TICK,
+    SINGLETON_TICK,
+];
+
+fn table() -> &'static RwLock<HashSet<&'static str>> {
+    static ATOMS: OnceLock<RwLock<HashSet<&'static str>>> = OnceLock::new();
+    ATOMS.get_or_init(|| RwLock::new(HashSet::new()))
+}
+
+/// An interned name. Atoms can be cheaply copied, compared and hashed.
+#[derive(Clone, Copy)]
+pub struct Atom(&'static str);
+
+impl Atom {
+    /// Gets the atom with the given name, adding it to the atom table if
+    /// needed. Panics if the atom table is full.
+    pub fn new(name: &str) -> Atom {
+        match Atom::try_new(name) {
+            Some(atom) => atom,
+            None => panic!("The atom table is full ({} atoms)", MAX_ATOMS)
+        }
+    }
+
+    /// Gets the atom with the given name, adding it to the atom table if
+    /// needed. Returns None if the atom table is full.
+    pub fn try_new(name: &str) -> Option<Atom> {
+        if let Some(existing) = Atom::lookup(name) {
+            return Some(existing);
+        }
+        let mut atoms = table().write().unwrap_or_else(|e| e.into_inner());
+        if let Some(existing) = atoms.get(name) {
+            return Some(Atom(existing));
+        }
+        if atoms.len() >= MAX_ATOMS {
+            return None;
+        }
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        atoms.insert(name);
+        Some(Atom(name))
+    }
+
+    /// Gets the atom with the given static name, adding it to the atom table
+    /// without copying it if needed. Panics if the atom table is full.
+    pub fn from_static(name: &'static str) -> Atom {
+        if let Some(existing) = Atom::lookup(name) {
+            return existing;
+        }
+        let mut atoms = table().write().unwrap_or_else(|e| e.into_inner());
+        if let Some(existing) = atoms.get(name) {
+            return Atom(existing);
+        }
+        if atoms.len() >= MAX_ATOMS {
+            panic!("The atom table is full ({} atoms)", MAX_ATOMS);
+        }
+        atoms.insert(name);
+        Atom(name)
+    }
+
+    /// Gets the atom with the given name if it is already in the atom table.
+    pub fn lookup(name: &str) -> Option<Atom> {
+        let atoms = table().read().unwrap_or_else(|e| e.into_inner());
+        atoms.get(name).map(|existing| Atom(existing))
+    }
+
+    /// Gets the name of the atom, which can be used as a MessageType name.
+    pub fn as_str(&self) -> &'static str { self.0 }
+}
+
+/// Adds the message types of the crate to the atom table.
+pub(crate) fn declare_internal_types() {
+    for mt in INTERNAL_TYPES {
+        Atom::from_static(mt);
+    }
+}
+
+impl PartialEq for Atom {
+    fn eq(&self, other: &Atom) -> bool { ptr::eq(self.0, other.0) }
+}
+
+impl Eq for Atom {}
+
+impl Hash for Atom {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.0.as_ptr() as usize).hash(state);
+    }
+}
+
+impl PartialOrd for Atom {
+    fn partial_cmp(&self, other: &Atom) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Atom {
+    /// Atoms are ordered by name, so that the order does not depend on when
+    /// they were created.
+    fn cmp(&self, other: &Atom) -> Ordering {
+        if self == other { Ordering::Equal } else { self.0.cmp(other.0) }
+    }
+}
+
+impl fmt::Debug for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Atom({:?})", self.0)
+    }
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Authentication of the connections between nodes, with a shared secret in
+//! the style of Erlang's cookies.
+//!
+//! After exchanging Hello frames, both nodes send a Challenge frame with a
+//! random nonce (or an empty one if they have no cookie), and answer the
+//! challenge of the other node with the HMAC-SHA256 of its nonce, both names
+//! and their role (whether they opened the connection), keyed with the cookie.
+//! An answer is only valid in one direction of one connection, so it cannot be
+//! reflected back to the node which made the challenge. A node which cannot
+//! prove it knows the cookie is rejected, and so is a node which does not use a
+//! cookie when the other does, or which claims the name of the node itself.
+//!
+//! Once both nodes are authenticated, each of them derives a key from the
+//! cookie, its role, its name and both nonces for the frames it sends, and
+//! every frame carries the HMAC of its kind, its payload and its sequence
+//! number in the direction it is sent. Frames which have been forged, altered,
+//! replayed or reordered do not verify, and close the connection.
+
+use std::io;
+
+use rand::{OsRng, Rng};
+use sha2::{Digest, Sha256};
+
+/// The length of nonces, keys and tags.
+pub const TAG_LEN: usize = 32;
+
+pub type Tag = [u8; TAG_LEN];
+
+const BLOCK_LEN: usize = 64;
+
+/// Computes the HMAC-SHA256 of the concatenation of the parts.
+pub fn hmac(key: &[u8], parts: &[&[u8]]) -> Tag {
+    let mut block = [0u8; BLOCK_LEN];
+    if key.len() > BLOCK_LEN {
+        block[..TAG_LEN].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.iter().map(|b| b ^ 0x36).collect::<Vec<u8>>());
+    for p in parts {
+        inner.update(p);
+    }
+    let mut outer = Sha256::new();
+    outer.update(block.iter().map(|b| b ^ 0x5c).collect::<Vec<u8>>());
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+/// Compares two byte strings in a time which does not depend on where they
+/// differ.
+pub fn equal(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Generates a nonce for a challenge.
+pub fn nonce() -> io::Result<Tag> {
+    let mut nonce = [0u8; TAG_LEN];
+    OsRng::new()?.fill_bytes(&mut nonce);
+    Ok(nonce)
+}
+
+fn role(initiator: bool) -> &'static [u8] {
+    if initiator { b"initiator" } else { b"responder" }
+}
+
+/// Computes the answer of a node to the challenge of another node, on a
+/// connection the answering node opened if `initiator` is true.
+pub fn answer(cookie: &[u8], challenge: &[u8], name: &str, challenger: &str, initiator: bool) -> Tag {
+    hmac(cookie, &[b"mecha challenge", role(initiator), challenge,
+                   &(name.len() as u64).to_be_bytes(), name.as_bytes(), challenger.as_bytes()])
+}
+
+/// Derives the keys of a connection between two authenticated nodes, for the
+/// frames the node sends and for the frames it receives. `initiator` tells
+/// whether the node opened the connection.
+pub fn session(cookie: &[u8], me: &str, my_nonce: &Tag, peer: &str, peer_nonce: &Tag,
+               initiator: bool) -> (Signer, Verifier) {
+    let key = |initiator: bool, name: &str, first: &Tag, second: &Tag| {
+        hmac(cookie, &[b"mecha session", role(initiator), first, second, name.as_bytes()])
+    };
+    (Signer { key: key(initiator, me, my_nonce, peer_nonce), sequence: 0 },
+     Verifier { key: key(!initiator, peer, peer_nonce, my_nonce), sequence: 0 })
+}
+
+/// Signs the frames sent on an authenticated connection.
+pub struct Signer {
+    key: Tag,
+    sequence: u64,
+}
+
+impl Signer {
+    /// Computes the tag of the next frame to send.
+    pub fn sign(&mut self, kind: u8, parts: &[&[u8]]) -> Tag {
+        let tag = tag(&self.key, self.sequence, kind, parts);
+        self.sequence += 1;
+        tag
+    }
+}
+
+/// Verifies the frames received on an authenticated connection.
+pub struct Verifier {
+    key: Tag,
+    sequence: u64,
+}
+
+impl Verifier {
+    /// Checks the tag at the end of the payload of the next frame received,
+    /// returning the payload without it.
+    pub fn verify<'a>(&mut self, kind: u8, payload: &'a [u8]) -> Option<&'a [u8]> {
+        if payload.len() < TAG_LEN {
+            return None;
+        }
+        let (payload, received) = payload.split_at(payload.len() - TAG_LEN);
+        let expected = tag(&self.key, self.sequence, kind, &[payload]);
+        self.sequence += 1;
+        if equal(&expected, received) { Some(payload) } else { None }
+    }
+}
+
+fn tag(key: &Tag, sequence: u64, kind: u8, parts: &[&[u8]]) -> Tag {
+    let mut all: Vec<&[u8]> = Vec::with_capacity(parts.len() + 2);
+    let sequence = sequence.to_be_bytes();
+    let kind = [kind];
+    all.push(&sequence);
+    all.push(&kind);
+    all.extend_from_slice(parts);
+    hmac(key, &all)
+}
@@ -0,0 +1,118 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Autoscaling policies for router pools.
+//!
+//! A router with an autoscaling policy periodically looks at the mailboxes of
+//! its workers. It adds a worker when they are too busy (the average mailbox
+//! length or the average mailbox latency is above a threshold), and removes one
+//! when they are all idle (every mailbox is empty), always staying within the
+//! minimum and maximum pool sizes. After each resize, the router waits for a
+//! cool-down period before resizing again, so that the new pool has time to
+//! settle.
+//!
+//! ```text
+//! let router = mecha::Router::new(1, make_worker)
+//!     .with_autoscaling(mecha::Autoscaling::new(1, 8)
+//!                           .with_mailbox_threshold(4.0)
+//!                           .with_cooldown(Duration::from_secs(1)))
+//!     .spawn();
+//! ```
+
+use std::time::{Duration, Instant};
+
+use ActorAddress;
+
+/// An autoscaling policy for a router pool. It uses a consuming builder
+/// pattern.
+#[derive(Clone, Debug)]
+pub struct Autoscaling {
+    min: usize,
+    max: usize,
+    mailbox_threshold: f64,
+    latency_threshold: Option<Duration>,
+    cooldown: Duration,
+    interval: Duration,
+}
+
+impl Autoscaling {
+    /// Initializes a policy which keeps the pool between `min` and `max`
+    /// workers. By default, the pool grows when workers have more than one
+    /// message waiting on average, latency is not considered, the pool is
+    /// checked every 100ms and the cool-down period is one second.
+    pub fn new(min: usize, max: usize) -> Self {
+        Autoscaling {
+            min,
+            max: if max < min { min } else { max },
+            mailbox_threshold: 1.0,
+            latency_threshold: None,
+            cooldown: Duration::from_secs(1),
+            interval: Duration::from_millis(100),
+        }
+    }
+
+    /// Sets the average mailbox length above which the pool grows.
+    pub fn with_mailbox_threshold(mut self, threshold: f64) -> Self {
+        self.mailbox_threshold = threshold;
+        self
+    }
+
+    /// Sets the average mailbox latency above which the pool grows.
+    pub fn with_latency_threshold(mut self, threshold: Duration) -> Self {
+        self.latency_threshold = Some(threshold);
+        self
+    }
+
+    /// Sets how long to wait after a resize before resizing again.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Sets how often the pool is checked.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Gets how often the pool is checked.
+    pub fn interval(&self) -> Duration { self.interval }
+
+    /// Clamps a pool size within the bounds of the policy.
+    pub(crate) fn clamp(&self, size: usize) -> usize {
+        size.max(self.min).min(self.max)
+    }
+
+    /// Decides the new size of a pool with the given workers, given the time of
+    /// the last resize. Returns None if the pool should stay as it is.
+    pub(crate) fn decide(&self, workers: &[ActorAddress], last_resize: Option<Instant>)
+        -> Option<usize> {
+        let size = workers.len();
+        if size != self.clamp(size) {
+            return Some(self.clamp(size));
+        }
+        if let Some(last) = last_resize {
+            if last.elapsed() < self.cooldown {
+                return None;
+            }
+        }
+        if size == 0 {
+            return None;
+        }
+        let total: usize = workers.iter().map(|w| w.mailbox_len()).sum();
+        if total == 0 {
+            return if size > self.min { Some(size - 1) } else { None };
+        }
+        let average = total as f64 / size as f64;
+        let latency = workers.iter().map(|w| w.mailbox_latency()).sum::<Duration>() /
+                      size as u32;
+        let busy = average > self.mailbox_threshold ||
+                   self.latency_threshold.is_some_and(|t| latency > t);
+        if busy && size < self.max { Some(size + 1) } else { None }
+    }
+}
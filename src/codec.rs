@@ -0,0 +1,420 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A compact, versioned binary encoding for Message and MessageDatum.
+//!
+//! Every encoding starts with a version byte (currently `CODEC_VERSION`).
+//! Integers and lengths are LEB128 varints (zig-zag encoded for I64), F64s are
+//! 8 little-endian bytes and strings are a length followed by UTF-8 bytes.
+//!
+//! - A Message is its type, its sender's address and its datum.
+//! - A MessageType is a tag byte, followed by the name for Call and Custom.
+//! - A MessageDatum is a tag byte followed by its contents; a Map is the number
+//!   of entries followed by each key and value, a List or a Tuple is the number
+//!   of items followed by each item, Bytes are a length followed by the bytes
+//!   and a Bool is a zero or one byte.
+//! - An ActorAddress is its ActorId: an optional node id (a zero byte, or a
+//!   one byte and a string), the creation number and the number of the actor.
+//!   The node id is the node of the actor if it runs on another node, or the
+//!   node id of the Codec otherwise.
+//!
+//! Decoding cannot recreate the channel behind an ActorAddress. Addresses of
+//! actors registered with `Codec::with_actor` are resolved to the registered
+//! ActorAddress; other addresses are passed to the resolver set with
+//! `Codec::with_resolver`, if any. Anything else is decoded as a detached
+//! address, which compares equal to the original but drops every message sent
+//! to it.
+//!
+//! The names of Call and Custom message types are decoded as the atoms with
+//! the same name (see `Atom`). Decoding never adds names to the atom table, so
+//! that a peer cannot fill it: the name of a type no atom has fails to decode.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+
+use ActorAddress;
+use ActorId;
+use Atom;
+use Message;
+use MessageDatum;
+use MessageType;
+
+/// The version of the encoding produced by this module.
+pub const CODEC_VERSION: u8 = 2;
+
+/// How deeply nested Maps, Lists and Tuples can be before decoding gives up.
+const MAX_DEPTH: usize = 64;
+
+const TYPE_EXITED: u8 = 0;
+const TYPE_LINK: u8 = 1;
+const TYPE_UNLINK: u8 = 2;
+const TYPE_SHUTDOWN: u8 = 3;
+const TYPE_CALL: u8 = 4;
+const TYPE_REPLY: u8 = 5;
+const TYPE_CUSTOM: u8 = 6;
+
+const DATUM_VOID: u8 = 0;
+const DATUM_I64: u8 = 1;
+const DATUM_U64: u8 = 2;
+const DATUM_F64: u8 = 3;
+const DATUM_STR: u8 = 4;
+const DATUM_MAP: u8 = 5;
+const DATUM_ACT: u8 = 6;
+const DATUM_BOOL: u8 = 7;
+const DATUM_BYTES: u8 = 8;
+const DATUM_LIST: u8 = 9;
+const DATUM_TUPLE: u8 = 10;
+const DATUM_NIL: u8 = 11;
+
+/// The reasons why decoding can fail.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CodecError {
+    /// The encoding was produced by an unsupported version of the codec.
+    UnsupportedVersion(u8),
+    /// The input ended in the middle of a value.
+    UnexpectedEnd,
+    /// The input continues after the end of the value.
+    TrailingBytes,
+    /// A tag byte does not denote any known MessageType or MessageDatum.
+    InvalidTag(u8),
+    /// A string is not valid UTF-8.
+    InvalidUtf8,
+    /// A varint does not fit in 64 bits.
+    InvalidVarint,
+    /// Maps, Lists or Tuples are nested too deeply.
+    TooDeep,
+    /// The name of a Call or Custom message type is not in the atom table.
+    UnknownAtom(String),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CodecError::UnsupportedVersion(v) => write!(f, "unsupported codec version {}", v),
+            CodecError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            CodecError::TrailingBytes => write!(f, "trailing bytes after the value"),
+            CodecError::InvalidTag(t) => write!(f, "invalid tag {}", t),
+            CodecError::InvalidUtf8 => write!(f, "invalid UTF-8 in string"),
+            CodecError::InvalidVarint => write!(f, "invalid varint"),
+            CodecError::TooDeep => write!(f, "values are nested too deeply"),
+            CodecError::UnknownAtom(ref t) => write!(f, "unknown message type {}", t),
+        }
+    }
+}
+
+impl Error for CodecError {}
+
+type ResolverFn = Arc<dyn Fn(&ActorAddress) -> Option<ActorAddress> + Send + Sync>;
+
+/// A Codec encodes and decodes Messages and MessageDatums. It uses a consuming
+/// builder pattern to register the actors it knows.
+#[derive(Default)]
+pub struct Codec {
+    node: Option<String>,
+    actors: HashMap<ActorId, ActorAddress>,
+    resolver: Option<ResolverFn>,
+}
+
+impl Codec {
+    /// Creates a Codec with no node id, and no known actors.
+    pub fn new() -> Codec { Codec::default() }
+
+    /// Sets the node id written along with the addresses of actors.
+    pub fn with_node(mut self, node: &str) -> Self {
+        self.node = Some(node.to_string());
+        self
+    }
+
+    /// Registers an actor, so that decoded addresses referring to it resolve to
+    /// the provided ActorAddress.
+    pub fn with_actor(mut self, actor: &ActorAddress) -> Self {
+        self.actors.insert(actor.id.clone(), actor.clone());
+        self
+    }
+
+    /// Sets a resolver for the decoded addresses of actors which have not been
+    /// registered. The resolver is a function or closure which takes the
+    /// detached address (whose id has no node if it refers to an actor of this
+    /// process) and returns the address to decode it as, or None to keep the
+    /// detached address.
+    pub fn with_resolver<T>(mut self, resolver: T) -> Self
+        where T: 'static + Fn(&ActorAddress) -> Option<ActorAddress> + Send + Sync {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Encodes a Message.
+    pub fn encode_message(&self, msg: &Message) -> Vec<u8> {
+        let mut out = vec![CODEC_VERSION];
+        self.write_type(&mut out, msg.get_type());
+        self.write_address(&mut out, msg.get_sender());
+        self.write_datum(&mut out, msg.get_datum());
+        out
+    }
+
+    /// Encodes a MessageDatum.
+    pub fn encode_datum(&self, datum: &MessageDatum) -> Vec<u8> {
+        let mut out = vec![CODEC_VERSION];
+        self.write_datum(&mut out, datum);
+        out
+    }
+
+    /// Decodes a Message.
+    pub fn decode_message(&self, bytes: &[u8]) -> Result<Message, CodecError> {
+        let mut r = Reader::new(bytes)?;
+        let mt = self.read_type(&mut r)?;
+        let sender = self.read_address(&mut r)?;
+        let datum = self.read_datum(&mut r, 0)?;
+        r.finish()?;
+        Ok(Message { mt, sender, datum, sent_at: Instant::now() })
+    }
+
+    /// Decodes a MessageDatum.
+    pub fn decode_datum(&self, bytes: &[u8]) -> Result<MessageDatum, CodecError> {
+        let mut r = Reader::new(bytes)?;
+        let datum = self.read_datum(&mut r, 0)?;
+        r.finish()?;
+        Ok(datum)
+    }
+
+    fn write_type(&self, out: &mut Vec<u8>, mt: &MessageType) {
+        match *mt {
+            MessageType::Exited => out.push(TYPE_EXITED),
+            MessageType::Link => out.push(TYPE_LINK),
+            MessageType::Unlink => out.push(TYPE_UNLINK),
+            MessageType::Shutdown => out.push(TYPE_SHUTDOWN),
+            MessageType::Call(name) => { out.push(TYPE_CALL); write_str(out, name); },
+            MessageType::Reply => out.push(TYPE_REPLY),
+            MessageType::Custom(name) => { out.push(TYPE_CUSTOM); write_str(out, name); },
+        }
+    }
+
+    fn write_address(&self, out: &mut Vec<u8>, address: &ActorAddress) {
+        match address.node().or(self.node.as_ref().map(|n| &n[..])) {
+            Some(node) => { out.push(1); write_str(out, node); },
+            None => out.push(0)
+        }
+        write_varint(out, u64::from(address.id.creation()));
+        write_varint(out, address.id.number());
+    }
+
+    fn write_datum(&self, out: &mut Vec<u8>, datum: &MessageDatum) {
+        match *datum {
+            MessageDatum::Void => out.push(DATUM_VOID),
+            MessageDatum::I64(x) => {
+                out.push(DATUM_I64);
+                write_varint(out, ((x << 1) ^ (x >> 63)) as u64);
+            },
+            MessageDatum::U64(x) => { out.push(DATUM_U64); write_varint(out, x); },
+            MessageDatum::F64(x) => {
+                out.push(DATUM_F64);
+                out.extend_from_slice(&x.to_bits().to_le_bytes());
+            },
+            MessageDatum::Str(ref s) => { out.push(DATUM_STR); write_str(out, s); },
+            MessageDatum::Map(ref m) => {
+                out.push(DATUM_MAP);
+                write_varint(out, m.len() as u64);
+                // Sort the keys so that equal maps have equal encodings.
+                let mut keys: Vec<&String> = m.keys().collect();
+                keys.sort();
+                for k in keys {
+                    write_str(out, k);
+                    self.write_datum(out, &m[k]);
+                }
+            },
+            MessageDatum::Act(ref a) => { out.push(DATUM_ACT); self.write_address(out, a); },
+            MessageDatum::Bool(x) => { out.push(DATUM_BOOL); out.push(x as u8); },
+            MessageDatum::Bytes(ref x) => {
+                out.push(DATUM_BYTES);
+                write_varint(out, x.len() as u64);
+                out.extend_from_slice(x);
+            },
+            MessageDatum::List(ref l) => { out.push(DATUM_LIST); self.write_items(out, l); },
+            MessageDatum::Tuple(ref t) => { out.push(DATUM_TUPLE); self.write_items(out, t); },
+            MessageDatum::Nil => out.push(DATUM_NIL),
+        }
+    }
+
+    fn write_items(&self, out: &mut Vec<u8>, items: &[MessageDatum]) {
+        write_varint(out, items.len() as u64);
+        for d in items {
+            self.write_datum(out, d);
+        }
+    }
+
+    fn read_type(&self, r: &mut Reader) -> Result<MessageType, CodecError> {
+        match r.byte()? {
+            TYPE_EXITED => Ok(MessageType::Exited),
+            TYPE_LINK => Ok(MessageType::Link),
+            TYPE_UNLINK => Ok(MessageType::Unlink),
+            TYPE_SHUTDOWN => Ok(MessageType::Shutdown),
+            TYPE_CALL => Ok(MessageType::Call(self.read_type_name(r)?)),
+            TYPE_REPLY => Ok(MessageType::Reply),
+            TYPE_CUSTOM => Ok(MessageType::Custom(self.read_type_name(r)?)),
+            t => Err(CodecError::InvalidTag(t))
+        }
+    }
+
+    fn read_type_name(&self, r: &mut Reader) -> Result<&'static str, CodecError> {
+        let name = r.string()?;
+        match Atom::lookup(&name) {
+            Some(atom) => Ok(atom.as_str()),
+            None => Err(CodecError::UnknownAtom(name))
+        }
+    }
+
+    fn read_address(&self, r: &mut Reader) -> Result<ActorAddress, CodecError> {
+        let node = match r.byte()? {
+            0 => None,
+            1 => Some(r.string()?),
+            t => { return Err(CodecError::InvalidTag(t)); }
+        };
+        let creation = r.varint()?;
+        if creation > u64::from(u32::MAX) {
+            return Err(CodecError::InvalidVarint);
+        }
+        let id = ActorId::from_parts(node.as_ref().map(|n| &n[..]), self.node.as_ref().map(|n| &n[..]),
+                                     creation as u32, r.varint()?);
+        if let Some(a) = self.actors.get(&id) {
+            return Ok(a.clone());
+        }
+        let detached = ActorAddress::detached(id);
+        Ok(match self.resolver {
+            Some(ref resolver) => resolver(&detached).unwrap_or(detached),
+            None => detached
+        })
+    }
+
+    fn read_datum(&self, r: &mut Reader, depth: usize) -> Result<MessageDatum, CodecError> {
+        if depth > MAX_DEPTH {
+            return Err(CodecError::TooDeep);
+        }
+        match r.byte()? {
+            DATUM_VOID => Ok(MessageDatum::Void),
+            DATUM_I64 => {
+                let x = r.varint()?;
+                Ok(MessageDatum::I64(((x >> 1) as i64) ^ -((x & 1) as i64)))
+            },
+            DATUM_U64 => Ok(MessageDatum::U64(r.varint()?)),
+            DATUM_F64 => {
+                let mut bits = [0u8; 8];
+                bits.copy_from_slice(r.bytes(8)?);
+                Ok(MessageDatum::F64(f64::from_bits(u64::from_le_bytes(bits))))
+            },
+            DATUM_STR => Ok(MessageDatum::Str(r.string()?.into())),
+            DATUM_MAP => {
+                let len = r.varint()?;
+                let mut m = HashMap::new();
+                for _ in 0..len {
+                    let k = r.string()?;
+                    let v = self.read_datum(r, depth + 1)?;
+                    m.insert(k, v);
+                }
+                Ok(MessageDatum::from(m))
+            },
+            DATUM_ACT => Ok(MessageDatum::Act(self.read_address(r)?)),
+            DATUM_BOOL => match r.byte()? {
+                0 => Ok(MessageDatum::Bool(false)),
+                1 => Ok(MessageDatum::Bool(true)),
+                t => Err(CodecError::InvalidTag(t))
+            },
+            DATUM_BYTES => {
+                let len = r.varint()?;
+                if len > r.bytes.len() as u64 {
+                    return Err(CodecError::UnexpectedEnd);
+                }
+                Ok(MessageDatum::from(r.bytes(len as usize)?))
+            },
+            DATUM_LIST => Ok(MessageDatum::List(self.read_items(r, depth)?.into())),
+            DATUM_TUPLE => Ok(MessageDatum::Tuple(self.read_items(r, depth)?.into())),
+            DATUM_NIL => Ok(MessageDatum::Nil),
+            t => Err(CodecError::InvalidTag(t))
+        }
+    }
+
+    fn read_items(&self, r: &mut Reader, depth: usize) -> Result<Vec<MessageDatum>, CodecError> {
+        let len = r.varint()?;
+        // Every item takes at least one byte, so do not trust larger lengths.
+        let mut items = Vec::with_capacity(len.min(r.bytes.len() as u64) as usize);
+        for _ in 0..len {
+            items.push(self.read_datum(r, depth + 1)?);
+        }
+        Ok(items)
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut x: u64) {
+    while x >= 0x80 {
+        out.push((x as u8) | 0x80);
+        x >>= 7;
+    }
+    out.push(x as u8);
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_varint(out, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Reader<'a>, CodecError> {
+        let mut r = Reader { bytes };
+        match r.byte()? {
+            CODEC_VERSION => Ok(r),
+            v => Err(CodecError::UnsupportedVersion(v))
+        }
+    }
+
+    fn finish(&self) -> Result<(), CodecError> {
+        if self.bytes.is_empty() { Ok(()) } else { Err(CodecError::TrailingBytes) }
+    }
+
+    fn byte(&mut self) -> Result<u8, CodecError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], CodecError> {
+        if self.bytes.len() < len {
+            return Err(CodecError::UnexpectedEnd);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn varint(&mut self) -> Result<u64, CodecError> {
+        let mut x = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            if shift == 63 && b > 1 {
+                return Err(CodecError::InvalidVarint);
+            }
+            x |= u64::from(b & 0x7f) << shift;
+            if b & 0x80 == 0 {
+                return Ok(x);
+            }
+        }
+        Err(CodecError::InvalidVarint)
+    }
+
+    fn string(&mut self) -> Result<String, CodecError> {
+        let len = self.varint()?;
+        if len > self.bytes.len() as u64 {
+            return Err(CodecError::UnexpectedEnd);
+        }
+        let bytes = self.bytes(len as usize)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| CodecError::InvalidUtf8)
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An event manager is an actor that owns a dynamic list of event handlers, in
+//! the spirit of Erlang's gen_event. Every Custom message sent to the manager
+//! is an event, and it is passed to every installed handler in turn.
+//!
+//! Handlers can be added, removed and swapped at runtime. Since handlers are
+//! Rust objects and cannot travel inside a MessageDatum, the EventManager
+//! passes them to the actor process through a side channel, and sends the
+//! actor process a message for each of them so that they are installed in
+//! order with the events.
+//!
+//! A handler that fails (or panics) is removed and terminated, but the manager
+//! keeps running. If the handler was added with `add_sup_handler`, its supervisor is
+//! sent an `EVENT_HANDLER_EXITED` message; otherwise the failure is logged.
+//! Handler ids are unique: a handler added with the id of an installed one
+//! fails to install, like a handler whose `init` fails.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+
+use Actor;
+use ActorAddress;
+use Message;
+use MessageDatum;
+use MessageType;
+use ActionResult;
+
+/// The Custom message type sent to the supervisor of a handler when the handler
+/// is removed because of an error. The datum is a Map with the "handler" id
+/// and the "reason" of the failure.
+pub const EVENT_HANDLER_EXITED: &str = ":mecha_event_handler_exited";
+
+pub(crate) const EVENT_COMMAND: &str = ":mecha_event_command";
+pub(crate) const WHICH_HANDLERS: &str = ":mecha_which_handlers";
+
+/// The EventHandler trait describes a handler that can be installed in an
+/// event manager.
+pub trait EventHandler: 'static + Send {
+    /// Initializes the handler when it is installed. When the handler replaces
+    /// another one through `swap_handler`, `previous` is the datum returned by
+    /// the `terminate` of the replaced handler; otherwise it is Void.
+    fn init(&mut self, _previous: &MessageDatum) -> ActionResult { Ok(()) }
+
+    /// Handles an event. Returning an error removes the handler.
+    fn handle_event(&mut self, event: &Message) -> ActionResult;
+
+    /// Cleans up when the handler is removed, swapped out, or the manager
+    /// exits. The reason is Void unless the handler failed. The returned
+    /// datum is passed to the handler replacing this one, if any.
+    fn terminate(&mut self, _reason: &MessageDatum) -> MessageDatum { MessageDatum::Void }
+}
+
+enum HandlerCommand {
+    Add(String, Box<dyn EventHandler>, Option<ActorAddress>),
+    Remove(String),
+    Swap(String, String, Box<dyn EventHandler>),
+}
+
+struct InstalledHandler {
+    id: String,
+    handler: Box<dyn EventHandler>,
+    supervisor: Option<ActorAddress>,
+}
+
+#[derive(Default)]
+struct ManagerState {
+    commands: Option<mpsc::Receiver<HandlerCommand>>,
+    handlers: Vec<InstalledHandler>,
+}
+
+impl ManagerState {
+    fn apply_next_command(&mut self, myself: &ActorAddress) {
+        let command = match self.commands.as_ref().map(|c| c.try_recv()) {
+            Some(Ok(command)) => command,
+            _ => { return; }
+        };
+        match command {
+            HandlerCommand::Add(id, handler, supervisor) => {
+                self.install(id, handler, supervisor, &MessageDatum::Void, myself);
+            },
+            HandlerCommand::Remove(id) => {
+                if let Some(mut old) = self.uninstall(&id) {
+                    old.terminate(&MessageDatum::Void);
+                }
+            },
+            HandlerCommand::Swap(old_id, id, handler) => {
+                let (previous, supervisor) = match self.uninstall(&old_id) {
+                    Some(mut old) => (old.terminate(&MessageDatum::Void), old.supervisor),
+                    None => (MessageDatum::Void, None)
+                };
+                self.install(id, handler, supervisor, &previous, myself);
+            }
+        }
+    }
+
+    fn install(&mut self, id: String, mut handler: Box<dyn EventHandler>,
+               supervisor: Option<ActorAddress>, previous: &MessageDatum,
+               myself: &ActorAddress) {
+        let result = if self.handlers.iter().any(|h| h.id == id) {
+            Err(format!("Event handler {:?} is already installed", id))
+        } else {
+            panic::catch_unwind(AssertUnwindSafe(|| handler.init(previous))).unwrap_or_else(panicked)
+        };
+        match result {
+            Ok(()) => {
+                self.handlers.push(InstalledHandler { id, handler, supervisor });
+            },
+            Err(e) => {
+                report(&id, supervisor.as_ref(), &MessageDatum::from(e), myself);
+            }
+        }
+    }
+
+    fn uninstall(&mut self, id: &str) -> Option<InstalledHandler> {
+        let idx = self.handlers.iter().position(|h| h.id == id)?;
+        Some(self.handlers.remove(idx))
+    }
+
+    fn dispatch(&mut self, event: &Message, myself: &ActorAddress) {
+        let mut failed = Vec::new();
+        for (idx, h) in self.handlers.iter_mut().enumerate() {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| h.handler.handle_event(event)));
+            if let Err(e) = result.unwrap_or_else(panicked) {
+                failed.push((idx, e));
+            }
+        }
+        // Remove the failed handlers from the last one, so that the indices
+        // of the others stay valid.
+        for (idx, e) in failed.into_iter().rev() {
+            let mut h = self.handlers.remove(idx);
+            let reason = MessageDatum::from(e);
+            h.terminate(&reason);
+            report(&h.id, h.supervisor.as_ref(), &reason, myself);
+        }
+    }
+}
+
+impl InstalledHandler {
+    /// Terminates the handler, as if it returned Void if it panics.
+    fn terminate(&mut self, reason: &MessageDatum) -> MessageDatum {
+        let handler = &mut self.handler;
+        panic::catch_unwind(AssertUnwindSafe(|| handler.terminate(reason))).unwrap_or(MessageDatum::Void)
+    }
+}
+
+/// Turns the panic of a handler into the error it fails with.
+fn panicked(payload: Box<dyn Any + Send>) -> ActionResult {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string()
+        }
+    };
+    Err(format!("Event handler panicked: {}", message))
+}
+
+fn report(id: &str, supervisor: Option<&ActorAddress>, reason: &MessageDatum,
+          myself: &ActorAddress) {
+    match supervisor {
+        Some(s) => {
+            let mut info = HashMap::new();
+            info.insert("handler".to_string(), MessageDatum::from(id));
+            info.insert("reason".to_string(), reason.clone());
+            Message::custom(EVENT_HANDLER_EXITED).with_sender(myself)
+                                                 .with_map(info)
+                                                 .send_to(s);
+        },
+        None => {
+            warn!("Event handler {:?} was removed: {}", id, reason.as_str_ref().unwrap_or_default());
+        }
+    }
+}
+
+/// EventManager is the handle to an event manager actor process. It can be
+/// cheaply cloned, and the underlying ActorAddress can be used to send events,
+/// to link to the manager or to shut it down.
+#[derive(Clone)]
+pub struct EventManager {
+    address: ActorAddress,
+    commands: mpsc::Sender<HandlerCommand>,
+}
+
+impl EventManager {
+    /// Spawns a new event manager actor process with no handlers.
+    pub fn spawn() -> EventManager {
+        EventManager::build(|actor| actor.spawn())
+    }
+
+    /// Spawns a new event manager actor process with no handlers, linking it
+    /// to the provided actor.
+    pub fn spawn_link(uplink: &ActorAddress) -> EventManager {
+        EventManager::build(|actor| actor.spawn_link(uplink))
+    }
+
+    fn build<F>(spawner: F) -> EventManager
+        where F: FnOnce(Actor<ManagerState>) -> ActorAddress {
+        let (tx, rx) = mpsc::channel();
+        let actor = Actor::new()
+            .with_state(ManagerState { commands: Some(rx), handlers: Vec::new() })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(EVENT_COMMAND)))
+            .with_action(|_, state, myself| {
+                state.apply_next_command(myself);
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Call(WHICH_HANDLERS)))
+            .with_action(|msg, state, myself| {
+                let ids = state.handlers.iter()
+                    .map(|h| (h.id.clone(), MessageDatum::Void))
+                    .collect();
+                Message::reply().with_sender(myself)
+                                .with_map(ids)
+                                .send_to(msg.get_sender());
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(_)))
+            .with_action(|msg, state, myself| {
+                state.dispatch(msg, myself);
+                Ok(())
+            })
+            .with_terminate(|state, reason| {
+                for h in state.handlers.iter_mut() {
+                    h.terminate(reason);
+                }
+            });
+        EventManager { address: spawner(actor), commands: tx }
+    }
+
+    /// Gets the address of the event manager actor process.
+    pub fn address(&self) -> &ActorAddress { &self.address }
+
+    /// Sends an event to the manager, which passes it to every handler.
+    pub fn notify(&self, mt: &'static str, datum: MessageDatum) {
+        self.address.cast(mt, datum);
+    }
+
+    /// Installs a new handler with the given id, unless a handler with the
+    /// same id is already installed.
+    pub fn add_handler<H: EventHandler>(&self, id: &str, handler: H) {
+        self.command(HandlerCommand::Add(id.to_string(), Box::new(handler), None));
+    }
+
+    /// Installs a new handler with the given id. If the handler ever fails, or
+    /// cannot be installed, the supervisor is sent an `EVENT_HANDLER_EXITED`
+    /// message.
+    pub fn add_sup_handler<H: EventHandler>(&self, id: &str, handler: H,
+                                            supervisor: &ActorAddress) {
+        self.command(HandlerCommand::Add(id.to_string(), Box::new(handler),
+                                         Some(supervisor.clone())));
+    }
+
+    /// Removes (and terminates) the handler with the given id.
+    pub fn remove_handler(&self, id: &str) {
+        self.command(HandlerCommand::Remove(id.to_string()));
+    }
+
+    /// Replaces the handler with id `old_id` with a new handler. The datum
+    /// returned by the `terminate` of the old handler is passed to the `init`
+    /// of the new one, and the supervisor (if any) is kept.
+    pub fn swap_handler<H: EventHandler>(&self, old_id: &str, id: &str, handler: H) {
+        self.command(HandlerCommand::Swap(old_id.to_string(), id.to_string(),
+                                          Box::new(handler)));
+    }
+
+    /// Gets the ids of the installed handlers, sorted.
+    pub fn which_handlers(&self) -> Result<Vec<String>, String> {
+        let ids = self.address.call(WHICH_HANDLERS, MessageDatum::Void)?;
+        let mut ids: Vec<String> = ids.as_map().unwrap_or_default().into_keys().collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn command(&self, command: HandlerCommand) {
+        self.commands.send(command).unwrap_or(());
+        Message::custom(EVENT_COMMAND).send_to(&self.address);
+    }
+}
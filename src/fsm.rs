@@ -0,0 +1,227 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Finite state machines are actors whose behaviour depends on a named state,
+//! in the spirit of Erlang's gen_statem.
+//!
+//! Handlers are registered per (state, message type) pair and return the
+//! Transition to perform. States can have entry and exit hooks, and a timeout
+//! which fires if the machine is still in that state after a given time.
+//!
+//! Messages that have no handler in the current state are postponed: they stay
+//! in the mailbox and are considered again after every state change, which is
+//! just how the selective receive of any Actor already works.
+//!
+//! ```text
+//! let door = mecha::Fsm::new(LOCKED).with_data(DoorData { ... })
+//!     .with_handler(LOCKED, MessageType::Custom(UNLOCK), |msg, data, myself| {
+//!         Ok(mecha::Transition::Next(OPEN))
+//!     })
+//!     .with_timeout(OPEN, Duration::from_secs(10), |data, myself| {
+//!         Ok(mecha::Transition::Next(LOCKED))
+//!     })
+//!     .spawn();
+//! ```
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use Actor;
+use ActorAddress;
+use Message;
+use MessageType;
+use ActionResult;
+
+/// The reserved Custom message type used for state timeouts.
+pub(crate) const FSM_TIMEOUT: &str = ":mecha_fsm_timeout";
+
+/// What a finite state machine should do after handling an event.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Transition {
+    /// Remain in the current state. Entry and exit hooks are not run, and the
+    /// state timeout keeps running.
+    Stay,
+    /// Move to the given state. Exit and entry hooks are run, and the state
+    /// timeout is restarted, even if the new state is the current one.
+    Next(&'static str),
+}
+
+type TransitionResult = Result<Transition, String>;
+
+type HandlerFn<Data> = Box<dyn Fn(&Message, &mut Data, &ActorAddress) -> TransitionResult + Send>;
+type HookFn<Data> = Box<dyn Fn(&mut Data, &ActorAddress) -> ActionResult + Send>;
+type TimeoutFn<Data> = Box<dyn Fn(&mut Data, &ActorAddress) -> TransitionResult + Send>;
+
+/// The actor state of an actor process built from a Fsm. It holds the user
+/// data together with the name of the current state and all the handlers.
+pub struct FsmState<Data: 'static + Sized + Default + Send> {
+    current: &'static str,
+    data: Data,
+    generation: u64,
+    handlers: HashMap<&'static str, HashMap<MessageType, HandlerFn<Data>>>,
+    enters: HashMap<&'static str, HookFn<Data>>,
+    exits: HashMap<&'static str, HookFn<Data>>,
+    timeouts: HashMap<&'static str, (Duration, TimeoutFn<Data>)>,
+}
+
+impl<Data: 'static + Sized + Default + Send> Default for FsmState<Data> {
+    fn default() -> Self {
+        FsmState {
+            current: "",
+            data: Data::default(),
+            generation: 0,
+            handlers: HashMap::new(),
+            enters: HashMap::new(),
+            exits: HashMap::new(),
+            timeouts: HashMap::new(),
+        }
+    }
+}
+
+impl<Data: 'static + Sized + Default + Send> FsmState<Data> {
+    /// Gets the name of the current state.
+    pub fn current_state(&self) -> &'static str { self.current }
+
+    /// Gets the user data of the state machine.
+    pub fn data(&self) -> &Data { &self.data }
+
+    fn handles(&self, msg: &Message) -> bool {
+        match *msg.get_type() {
+            MessageType::Custom(FSM_TIMEOUT) => true,
+            ref mt => self.handlers.get(self.current)
+                                   .is_some_and(|h| h.contains_key(mt))
+        }
+    }
+
+    fn handle(&mut self, msg: &Message, myself: &ActorAddress) -> ActionResult {
+        let transition = match *msg.get_type() {
+            MessageType::Custom(FSM_TIMEOUT) => {
+                // Timeouts belonging to a state we have already left are
+                // simply dropped.
+                if msg.get_sender() != myself ||
+                   msg.get_datum().as_u64() != Some(self.generation) {
+                    return Ok(());
+                }
+                match self.timeouts.get(self.current) {
+                    Some((_, t)) => t(&mut self.data, myself)?,
+                    None => Transition::Stay
+                }
+            },
+            ref mt => {
+                match self.handlers.get(self.current).and_then(|h| h.get(mt)) {
+                    Some(h) => h(msg, &mut self.data, myself)?,
+                    None => Transition::Stay
+                }
+            }
+        };
+        match transition {
+            Transition::Stay => Ok(()),
+            Transition::Next(next) => {
+                if let Some(h) = self.exits.get(self.current) {
+                    h(&mut self.data, myself)?;
+                }
+                self.enter(next, myself)
+            }
+        }
+    }
+
+    fn enter(&mut self, state: &'static str, myself: &ActorAddress) -> ActionResult {
+        self.current = state;
+        self.generation += 1;
+        if let Some(&(timeout, _)) = self.timeouts.get(state) {
+            Message::custom(FSM_TIMEOUT).with_sender(myself)
+                                        .with_u64(self.generation)
+                                        .send_after(myself, timeout);
+        }
+        match self.enters.get(state) {
+            Some(h) => h(&mut self.data, myself),
+            None => Ok(())
+        }
+    }
+}
+
+/// Fsm provides an API for creating finite state machine actor processes. Like
+/// Actor, it uses a consuming builder pattern.
+///
+/// Handlers are functions or closures that take a reference to a Message, a
+/// mutable reference to the user data and a reference to the address of the
+/// actor process itself, and return the Transition to perform.
+///
+/// Hooks and timeout handlers are similar, but they do not take a Message.
+pub struct Fsm<Data: 'static + Sized + Default + Send> {
+    initial: &'static str,
+    spec: FsmState<Data>,
+}
+
+impl<Data: 'static + Sized + Default + Send> Fsm<Data> {
+    /// Initializes the Fsm building process, specifying the initial state.
+    pub fn new(initial: &'static str) -> Self {
+        Fsm { initial, spec: FsmState::default() }
+    }
+
+    /// Sets the initial user data of the Fsm.
+    pub fn with_data(mut self, data: Data) -> Self {
+        self.spec.data = data;
+        self
+    }
+
+    /// Adds the handler for messages of the given type received in the given
+    /// state, replacing any previous one.
+    pub fn with_handler<T>(mut self, state: &'static str, mt: MessageType, h: T) -> Self
+        where T: 'static + Fn(&Message, &mut Data, &ActorAddress) -> TransitionResult + Send {
+        self.spec.handlers.entry(state).or_default().insert(mt, Box::new(h));
+        self
+    }
+
+    /// Sets the hook to run when entering the given state.
+    pub fn with_enter<T>(mut self, state: &'static str, h: T) -> Self
+        where T: 'static + Fn(&mut Data, &ActorAddress) -> ActionResult + Send {
+        self.spec.enters.insert(state, Box::new(h));
+        self
+    }
+
+    /// Sets the hook to run when leaving the given state.
+    pub fn with_exit<T>(mut self, state: &'static str, h: T) -> Self
+        where T: 'static + Fn(&mut Data, &ActorAddress) -> ActionResult + Send {
+        self.spec.exits.insert(state, Box::new(h));
+        self
+    }
+
+    /// Sets the timeout of the given state: if the Fsm is still in that state
+    /// once the timeout has elapsed since entering it, the handler is called.
+    pub fn with_timeout<T>(mut self, state: &'static str, timeout: Duration, h: T) -> Self
+        where T: 'static + Fn(&mut Data, &ActorAddress) -> TransitionResult + Send {
+        self.spec.timeouts.insert(state, (timeout, Box::new(h)));
+        self
+    }
+
+    /// Consumes the Fsm building blocks and spawns the actor process,
+    /// returning an ActorAddress for sending messages to it.
+    pub fn spawn(self) -> ActorAddress {
+        Actor::from_fsm(self).spawn()
+    }
+
+    /// Consumes the Fsm building blocks and spawns the actor process, linking
+    /// it to the provided actor.
+    pub fn spawn_link(self, uplink: &ActorAddress) -> ActorAddress {
+        Actor::from_fsm(self).spawn_link(uplink)
+    }
+}
+
+impl<Data: 'static + Sized + Default + Send> Actor<FsmState<Data>> {
+    /// Initializes the Actor building process from a Fsm. The initial state is
+    /// entered (and its entry hook run) when the actor process starts.
+    pub fn from_fsm(fsm: Fsm<Data>) -> Self {
+        let initial = fsm.initial;
+        Actor::new()
+            .with_state(fsm.spec)
+            .with_init(move |state, myself| state.enter(initial, myself))
+            .with_match(|msg, state| state.handles(msg))
+            .with_action(|msg, state, myself| state.handle(msg, myself))
+    }
+}
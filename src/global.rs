@@ -0,0 +1,152 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The global registry gives actors names which are unique across a cluster,
+//! in the spirit of Erlang's `global` module.
+//!
+//! Every node keeps a copy of the registry. A node is the authority on the
+//! names of its own actors: it sends the full list of them to each new peer,
+//! and to all its peers whenever it changes, and the peers replace what they
+//! knew about the names of that node with it. The names of a node disappear
+//! from the registry when the node is lost.
+//!
+//! A name can be registered on two nodes at once, for instance on both sides
+//! of a partition. When the nodes hear about each other, the registration made
+//! first wins (ties are broken by the name of the node), and the actor which
+//! lost its name is sent a `GLOBAL_NAME_CONFLICT` message, with the name as the
+//! datum and the winner as the sender.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+use ActorAddress;
+use gossip::{take, take_string};
+
+/// The type of the messages sent to an actor which lost its global name to
+/// an actor on another node.
+pub const GLOBAL_NAME_CONFLICT: &str = ":mecha_global_name_conflict";
+
+/// A name in the registry: its owner node, its actor, and the time it was
+/// registered, in microseconds since the Unix epoch on the owner node.
+struct Registration {
+    node: String,
+    actor: ActorAddress,
+    stamp: u64,
+}
+
+impl Registration {
+    /// Checks whether the registration wins over another one.
+    fn wins_over(&self, other: &Registration) -> bool {
+        (self.stamp, &self.node) < (other.stamp, &other.node)
+    }
+}
+
+/// The copy of the registry of a node.
+pub(crate) struct Registry {
+    me: String,
+    names: HashMap<String, Registration>,
+}
+
+impl Registry {
+    pub(crate) fn new(me: &str) -> Registry {
+        Registry { me: me.to_string(), names: HashMap::new() }
+    }
+
+    /// Registers a name for a local actor, unless it is already taken.
+    pub(crate) fn register(&mut self, name: &str, actor: &ActorAddress, stamp: u64) -> bool {
+        if self.names.contains_key(name) {
+            return false;
+        }
+        self.names.insert(name.to_string(), Registration {
+            node: self.me.clone(),
+            actor: actor.clone(),
+            stamp,
+        });
+        true
+    }
+
+    /// Removes a name registered by this node, returning whether it was.
+    pub(crate) fn unregister(&mut self, name: &str) -> bool {
+        if self.names.get(name).is_none_or(|r| r.node != self.me) {
+            return false;
+        }
+        self.names.remove(name);
+        true
+    }
+
+    pub(crate) fn whereis(&self, name: &str) -> Option<ActorAddress> {
+        self.names.get(name).map(|r| r.actor.clone())
+    }
+
+    /// Gets all the names, sorted.
+    pub(crate) fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.names.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Removes the names of a node which has been lost.
+    pub(crate) fn remove_node(&mut self, node: &str) {
+        self.names.retain(|_, r| r.node != node);
+    }
+
+    /// Replaces the names of a peer with the ones it sent, returning the names
+    /// of this node which lost a conflict, with their actor and the winner.
+    pub(crate) fn merge(&mut self, peer: &str, received: Vec<(String, ActorAddress, u64)>)
+        -> Vec<(String, ActorAddress, ActorAddress)> {
+        self.remove_node(peer);
+        let mut lost = Vec::new();
+        for (name, actor, stamp) in received {
+            let candidate = Registration { node: peer.to_string(), actor, stamp };
+            match self.names.get(&name) {
+                Some(known) if !candidate.wins_over(known) => { continue; },
+                Some(known) if known.node == self.me => {
+                    lost.push((name.clone(), known.actor.clone(), candidate.actor.clone()));
+                },
+                _ => ()
+            }
+            self.names.insert(name, candidate);
+        }
+        lost
+    }
+
+    /// Encodes the names of this node as the payload of a Global frame: a
+    /// 4-byte count, then for each name its length on 2 bytes, its UTF-8 bytes,
+    /// the creation and number of its actor on 4 and 8 bytes, and its stamp on
+    /// 8 bytes.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mine: Vec<(&String, &Registration)> = self.names.iter()
+            .filter(|&(_, r)| r.node == self.me)
+            .collect();
+        let mut out = Vec::new();
+        out.extend_from_slice(&(mine.len() as u32).to_be_bytes());
+        for (name, r) in mine {
+            out.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&r.actor.id().creation().to_be_bytes());
+            out.extend_from_slice(&r.actor.id().number().to_be_bytes());
+            out.extend_from_slice(&r.stamp.to_be_bytes());
+        }
+        out
+    }
+}
+
+/// Decodes the payload of a Global frame into names, the creation and number
+/// of their actors, and their stamps, returning None if it is malformed.
+pub(crate) fn decode(mut bytes: &[u8]) -> Option<Vec<(String, u32, u64, u64)>> {
+    let count = u32::from_be_bytes(take(&mut bytes, 4)?.try_into().ok()?);
+    let mut names = Vec::new();
+    for _ in 0..count {
+        let name = take_string(&mut bytes)?;
+        let creation = u32::from_be_bytes(take(&mut bytes, 4)?.try_into().ok()?);
+        let number = u64::from_be_bytes(take(&mut bytes, 8)?.try_into().ok()?);
+        let stamp = u64::from_be_bytes(take(&mut bytes, 8)?.try_into().ok()?);
+        names.push((name, creation, number, stamp));
+    }
+    Some(names)
+}
@@ -0,0 +1,236 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Gossip lets nodes discover each other: a node only needs to connect to one
+//! member of a cluster to learn about, and connect to, all the others.
+//!
+//! Every node keeps a table of the members it knows about, with their address,
+//! status and incarnation number. Nodes send their table to a random peer at
+//! every heartbeat, and to every new peer, and merge the tables they receive:
+//! for each member, the entry with the higher incarnation wins, and for the
+//! same incarnation the more severe status wins (`Up`, then `Suspect`, then
+//! `Down`, then `Left`). Only a node itself bumps its incarnation, which it
+//! does to refute the rumour of its death when it hears that it is suspected
+//! or down.
+//!
+//! A member becomes `Suspect` when a node loses its connection to it, `Down`
+//! when it has been suspected for longer than the suspect timeout, and `Left`
+//! when it leaves the cluster with `Node::leave`. Nodes keep trying to connect
+//! to the members which have not left and they are not connected to, so that
+//! members which were wrongly suspected come back once they can be reached; of
+//! each pair of nodes, the one with the smaller name does that, so that they do
+//! not connect to each other at the same time.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::time::{Duration, Instant};
+
+/// The status of a member of a cluster.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum MemberStatus {
+    /// The member is alive, as far as the node knows.
+    Up,
+    /// A node has lost its connection to the member.
+    Suspect,
+    /// The member has been suspected for too long.
+    Down,
+    /// The member has left the cluster.
+    Left,
+}
+
+/// A member of a cluster, as seen by a node.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Member {
+    name: String,
+    address: String,
+    status: MemberStatus,
+    incarnation: u64,
+}
+
+impl Member {
+    /// Gets the name of the node.
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Gets the address the node listens on.
+    pub fn address(&self) -> &str { &self.address }
+
+    /// Gets the status of the node.
+    pub fn status(&self) -> MemberStatus { self.status }
+
+    /// Gets the incarnation number of the node, which the node increases
+    /// whenever it refutes its suspicion or death.
+    pub fn incarnation(&self) -> u64 { self.incarnation }
+
+    fn newer_than(&self, other: &Member) -> bool {
+        (self.incarnation, self.status) > (other.incarnation, other.status)
+    }
+}
+
+/// The table of the members known to a node, including itself.
+pub(crate) struct Members {
+    me: String,
+    members: HashMap<String, Member>,
+    suspected: HashMap<String, Instant>,
+}
+
+impl Members {
+    pub(crate) fn new(me: &str, address: &str) -> Members {
+        let mut members = HashMap::new();
+        members.insert(me.to_string(), Member {
+            name: me.to_string(),
+            address: address.to_string(),
+            status: MemberStatus::Up,
+            incarnation: 0,
+        });
+        Members { me: me.to_string(), members, suspected: HashMap::new() }
+    }
+
+    /// Gets all the members, sorted by name.
+    pub(crate) fn list(&self) -> Vec<Member> {
+        let mut list: Vec<Member> = self.members.values().cloned().collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
+    /// Gets the members this node should connect to: the ones which have not
+    /// left, and whose name is greater than the name of this node.
+    pub(crate) fn to_connect(&self) -> Vec<(String, String)> {
+        self.members.values()
+            .filter(|m| m.status != MemberStatus::Left && m.name > self.me)
+            .map(|m| (m.name.clone(), m.address.clone()))
+            .collect()
+    }
+
+    /// Merges the table received from a peer, returning whether anything
+    /// changed.
+    pub(crate) fn merge(&mut self, received: Vec<Member>, now: Instant) -> bool {
+        let mut changed = false;
+        for m in received {
+            if m.name == self.me {
+                changed |= self.refute(&m);
+                continue;
+            }
+            let newer = match self.members.get(&m.name) {
+                Some(known) => m.newer_than(known),
+                None => true
+            };
+            if newer {
+                self.set(m, now);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Marks a member which was up as suspect, when the connection to it has
+    /// been lost.
+    pub(crate) fn suspect(&mut self, name: &str, now: Instant) {
+        let suspect = match self.members.get(name) {
+            Some(m) if m.status == MemberStatus::Up && name != self.me => {
+                Member { status: MemberStatus::Suspect, ..m.clone() }
+            },
+            _ => { return; }
+        };
+        self.set(suspect, now);
+    }
+
+    /// Marks as down the members which have been suspected for longer than
+    /// the timeout.
+    pub(crate) fn expire(&mut self, timeout: Duration, now: Instant) {
+        let expired: Vec<String> = self.suspected.iter()
+            .filter(|&(_, since)| now.duration_since(*since) >= timeout)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in expired {
+            let down = Member { status: MemberStatus::Down, ..self.members[&name].clone() };
+            self.set(down, now);
+        }
+    }
+
+    /// Marks this node as having left the cluster.
+    pub(crate) fn leave(&mut self) {
+        let me = self.members.get_mut(&self.me).unwrap();
+        me.incarnation += 1;
+        me.status = MemberStatus::Left;
+    }
+
+    /// Refutes a rumour about this node, by bumping its incarnation number
+    /// over the one of the rumour.
+    fn refute(&mut self, rumour: &Member) -> bool {
+        let me = self.members.get_mut(&self.me).unwrap();
+        if me.status == MemberStatus::Left || rumour.status == MemberStatus::Up
+            || rumour.incarnation < me.incarnation {
+            return false;
+        }
+        me.incarnation = rumour.incarnation + 1;
+        true
+    }
+
+    fn set(&mut self, m: Member, now: Instant) {
+        if m.status == MemberStatus::Suspect {
+            self.suspected.entry(m.name.clone()).or_insert(now);
+        } else {
+            self.suspected.remove(&m.name);
+        }
+        self.members.insert(m.name.clone(), m);
+    }
+
+    /// Encodes the table as the payload of a Gossip frame: a 4-byte count,
+    /// then for each member its name and address (each a 2-byte length and
+    /// the UTF-8 bytes), its 8-byte incarnation number and its status byte.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&(self.members.len() as u32).to_be_bytes());
+        for m in self.members.values() {
+            for s in [&m.name, &m.address] {
+                out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+                out.extend_from_slice(s.as_bytes());
+            }
+            out.extend_from_slice(&m.incarnation.to_be_bytes());
+            out.push(m.status as u8);
+        }
+        out
+    }
+}
+
+/// Takes the first `n` bytes of a frame payload, returning None if it is too
+/// short.
+pub(crate) fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if bytes.len() < n {
+        return None;
+    }
+    let (head, tail) = bytes.split_at(n);
+    *bytes = tail;
+    Some(head)
+}
+
+/// Takes a string prefixed with its length, as a u16, from a frame payload.
+pub(crate) fn take_string(bytes: &mut &[u8]) -> Option<String> {
+    let len = u16::from_be_bytes(take(bytes, 2)?.try_into().ok()?) as usize;
+    String::from_utf8(take(bytes, len)?.to_vec()).ok()
+}
+
+/// Decodes the payload of a Gossip frame, returning None if it is malformed.
+pub(crate) fn decode(mut bytes: &[u8]) -> Option<Vec<Member>> {
+    let count = u32::from_be_bytes(take(&mut bytes, 4)?.try_into().ok()?);
+    let mut members = Vec::new();
+    for _ in 0..count {
+        let name = take_string(&mut bytes)?;
+        let address = take_string(&mut bytes)?;
+        let incarnation = u64::from_be_bytes(take(&mut bytes, 8)?.try_into().ok()?);
+        let status = match take(&mut bytes, 1)?[0] {
+            0 => MemberStatus::Up,
+            1 => MemberStatus::Suspect,
+            2 => MemberStatus::Down,
+            3 => MemberStatus::Left,
+            _ => { return None; }
+        };
+        members.push(Member { name, address, status, incarnation });
+    }
+    Some(members)
+}
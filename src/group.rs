@@ -0,0 +1,109 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Process groups are named sets of actors which can be sent a message all at
+//! once, in the spirit of Erlang's pg.
+//!
+//! The membership table is shared by all the clones of a ProcessGroups handle,
+//! so that broadcasting does not need to go through an actor process. A
+//! monitor actor process is linked to every member, and removes it from all
+//! the groups when it exits.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use Actor;
+use ActorAddress;
+use Message;
+use MessageBuilder;
+use MessageType;
+
+type GroupTable = Arc<Mutex<HashMap<String, Vec<ActorAddress>>>>;
+
+/// ProcessGroups is the handle to a set of named process groups. It can be
+/// cheaply cloned, and all clones share the same groups.
+#[derive(Clone)]
+pub struct ProcessGroups {
+    groups: GroupTable,
+    monitor: ActorAddress,
+}
+
+impl ProcessGroups {
+    /// Creates a new, empty, set of process groups, spawning the actor process
+    /// which monitors the members.
+    pub fn spawn() -> ProcessGroups {
+        let groups = GroupTable::default();
+        let monitor = Actor::new()
+            .with_state(groups.clone())
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Exited))
+            .with_action(|msg, groups, _| {
+                let mut groups = groups.lock().unwrap();
+                for members in groups.values_mut() {
+                    members.retain(|m| m != msg.get_sender());
+                }
+                groups.retain(|_, members| !members.is_empty());
+                Ok(())
+            })
+            .spawn();
+        ProcessGroups { groups, monitor }
+    }
+
+    /// Adds an actor to the named group, creating the group if needed. An actor
+    /// can be in many groups at once, but only once in each group.
+    pub fn join(&self, group: &str, member: &ActorAddress) {
+        let mut groups = self.groups.lock().unwrap();
+        let already_linked = groups.values().any(|members| members.contains(member));
+        let members = groups.entry(group.to_string()).or_default();
+        if !members.contains(member) {
+            members.push(member.clone());
+        }
+        if !already_linked {
+            Message::link().with_sender(&self.monitor).send_to(member);
+        }
+    }
+
+    /// Removes an actor from the named group. Groups with no members left are
+    /// forgotten.
+    pub fn leave(&self, group: &str, member: &ActorAddress) {
+        let mut groups = self.groups.lock().unwrap();
+        if let Some(members) = groups.get_mut(group) {
+            members.retain(|m| m != member);
+        }
+        groups.retain(|_, members| !members.is_empty());
+        if !groups.values().any(|members| members.contains(member)) {
+            Message::unlink().with_sender(&self.monitor).send_to(member);
+        }
+    }
+
+    /// Gets the current members of the named group.
+    pub fn members(&self, group: &str) -> Vec<ActorAddress> {
+        let groups = self.groups.lock().unwrap();
+        groups.get(group).cloned().unwrap_or_default()
+    }
+
+    /// Gets the names of all the groups which have at least one member.
+    pub fn which_groups(&self) -> Vec<String> {
+        let groups = self.groups.lock().unwrap();
+        let mut names: Vec<String> = groups.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Builds the message and sends it to every member of the named group.
+    pub fn broadcast(&self, group: &str, msg: &MessageBuilder) {
+        for m in self.members(group) {
+            msg.send_to(&m);
+        }
+    }
+
+    /// Shuts down the actor process which monitors the members. Members are
+    /// not affected, but will not be removed from the groups upon exit anymore.
+    pub fn shutdown(&self) {
+        Message::shutdown().send_to(&self.monitor);
+    }
+}
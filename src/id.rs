@@ -0,0 +1,135 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Actor ids identify actor processes across all the connected mecha
+//! processes, in the spirit of Erlang's pids.
+//!
+//! An ActorId is made of the name of the node the actor runs on, the creation
+//! number of the process it runs in, and a number which is unique within that
+//! process. The creation number is chosen randomly when the process starts, so
+//! that the ids of a node which has been restarted do not get confused with
+//! the ids it had before.
+//!
+//! The ids of actors running in this process have no node name, as a process
+//! can run many nodes (or none): they get the name of a node when they are
+//! sent to another node. Ids are written as `<node>#<creation>.<number>`, or
+//! `#<creation>.<number>` for actors of this process, and can be parsed back
+//! from that form.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use rand;
+
+/// Gets the creation number of this process.
+fn local_creation() -> u32 {
+    static CREATION: OnceLock<u32> = OnceLock::new();
+    *CREATION.get_or_init(rand::random::<u32>)
+}
+
+/// The id of an actor process. ActorIds can be cheaply cloned, compared,
+/// hashed and ordered.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ActorId {
+    node: Option<Arc<str>>,
+    creation: u32,
+    number: u64,
+}
+
+impl ActorId {
+    /// Creates a new id for an actor of this process.
+    pub(crate) fn new_local() -> ActorId {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        ActorId { node: None, creation: local_creation(), number: NEXT.fetch_add(1, Ordering::Relaxed) }
+    }
+
+    /// Creates an id from its parts. An id whose node is the given local node,
+    /// and whose creation number is the one of this process, refers to an
+    /// actor of this process, so its node is dropped.
+    pub(crate) fn from_parts(node: Option<&str>, local_node: Option<&str>, creation: u32,
+                             number: u64) -> ActorId {
+        let node = match node {
+            Some(n) if Some(n) == local_node && creation == local_creation() => None,
+            n => n.map(|n| n.into())
+        };
+        ActorId { node, creation, number }
+    }
+
+    /// Gets the name of the node the actor runs on, or None if it runs in this
+    /// process.
+    pub fn node(&self) -> Option<&str> { self.node.as_ref().map(|n| &n[..]) }
+
+    /// Gets the creation number of the process the actor runs in.
+    pub fn creation(&self) -> u32 { self.creation }
+
+    /// Gets the number of the actor within its process.
+    pub fn number(&self) -> u64 { self.number }
+
+    /// Checks whether the actor runs in this process.
+    pub fn is_local(&self) -> bool { self.node.is_none() }
+
+    /// Gets the id qualified with the name of a node, if it does not have one
+    /// already.
+    pub fn qualified(&self, node: &str) -> ActorId {
+        ActorId {
+            node: Some(self.node.clone().unwrap_or_else(|| node.into())),
+            creation: self.creation,
+            number: self.number
+        }
+    }
+}
+
+impl fmt::Display for ActorId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}#{}.{}", self.node().unwrap_or(""), self.creation, self.number)
+    }
+}
+
+impl fmt::Debug for ActorId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ActorId({})", self)
+    }
+}
+
+/// The error returned when parsing an ActorId fails.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseActorIdError {
+    input: String,
+}
+
+impl fmt::Display for ParseActorIdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid actor id {:?}", self.input)
+    }
+}
+
+impl Error for ParseActorIdError {}
+
+/// Parses the form written by Display. The node name is everything before the
+/// last '#', so it can contain any character.
+impl FromStr for ActorId {
+    type Err = ParseActorIdError;
+
+    fn from_str(s: &str) -> Result<ActorId, ParseActorIdError> {
+        let error = || ParseActorIdError { input: s.to_string() };
+        let (node, rest) = s.rsplit_once('#').ok_or_else(error)?;
+        let (creation, number) = rest.split_once('.').ok_or_else(error)?;
+        let digits = |x: &str| !x.is_empty() && x.bytes().all(|b| b.is_ascii_digit());
+        if !digits(creation) || !digits(number) {
+            return Err(error());
+        }
+        Ok(ActorId {
+            node: if node.is_empty() { None } else { Some(node.into()) },
+            creation: creation.parse().map_err(|_| error())?,
+            number: number.parse().map_err(|_| error())?
+        })
+    }
+}
@@ -0,0 +1,564 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A lossless JSON mapping for MessageDatum.
+//!
+//! - Void is `null`, a Bool is `true` or `false`, a Str is a JSON string, a
+//!   List is a JSON array and a Map is a JSON object.
+//! - An I64 is an integer, such as `-3`.
+//! - An F64 is a number with a fraction or an exponent, such as `3.0` or
+//!   `1e300`. Infinities and NaN are written as `{"$f64": "inf"}`,
+//!   `{"$f64": "-inf"}` and `{"$f64": "NaN"}`.
+//! - An U64 is written as `{"$u64": 3}`.
+//! - An Act is written as `{"$act": "<id>"}`, with the id of the actor as
+//!   written by `ActorId`'s Display.
+//! - Bytes are written as `{"$bytes": "<base64>"}`.
+//! - A Tuple is written as `{"$tuple": [...]}`.
+//! - Nil is written as `{"$nil": null}`.
+//! - A Map with a single key starting with `$` is wrapped as
+//!   `{"$map": {...}}`, so that it is not mistaken for one of the above.
+//!
+//! When parsing, an integer which is too large for an I64 but fits in an U64
+//! is read as an U64, so that hand-written JSON does not need the tag.
+//!
+//! As with the binary Codec, parsing cannot recreate the channel behind an
+//! ActorAddress, so addresses are parsed as detached addresses which compare
+//! equal to the original but drop every message sent to them.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write;
+use std::sync::Arc;
+
+
+use ActorAddress;
+use ActorId;
+use MessageDatum;
+use datum_path;
+
+/// How deeply nested objects and arrays can be before parsing gives up.
+const MAX_DEPTH: usize = 64;
+
+const TAG_U64: &str = "$u64";
+const TAG_F64: &str = "$f64";
+const TAG_ACT: &str = "$act";
+const TAG_MAP: &str = "$map";
+const TAG_BYTES: &str = "$bytes";
+const TAG_TUPLE: &str = "$tuple";
+const TAG_NIL: &str = "$nil";
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The reasons why parsing JSON into a MessageDatum can fail.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum JsonErrorKind {
+    /// The input ended in the middle of a value.
+    UnexpectedEnd,
+    /// A character which cannot appear at this point of a JSON document.
+    UnexpectedChar(char),
+    /// The input continues after the end of the value.
+    TrailingCharacters,
+    /// A number is malformed or out of range.
+    InvalidNumber,
+    /// A string contains an invalid escape sequence.
+    InvalidEscape,
+    /// A tagged object (such as `{"$u64": ...}`) with an unknown tag or an
+    /// invalid payload.
+    InvalidTag(String),
+    /// Objects or arrays are nested too deeply.
+    TooDeep,
+}
+
+impl fmt::Display for JsonErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JsonErrorKind::UnexpectedEnd => write!(f, "unexpected end of input"),
+            JsonErrorKind::UnexpectedChar(c) => write!(f, "unexpected character {:?}", c),
+            JsonErrorKind::TrailingCharacters => write!(f, "trailing characters after the value"),
+            JsonErrorKind::InvalidNumber => write!(f, "invalid number"),
+            JsonErrorKind::InvalidEscape => write!(f, "invalid escape sequence"),
+            JsonErrorKind::InvalidTag(ref t) => write!(f, "invalid tagged value {}", t),
+            JsonErrorKind::TooDeep => write!(f, "values are nested too deeply"),
+        }
+    }
+}
+
+/// An error found while parsing JSON into a MessageDatum. It reports the path
+/// of the field where the error was found (such as `$.server.port`) and the
+/// byte offset in the input.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct JsonError {
+    kind: JsonErrorKind,
+    path: String,
+    offset: usize,
+}
+
+impl JsonError {
+    /// Gets the reason of the error.
+    pub fn kind(&self) -> &JsonErrorKind { &self.kind }
+
+    /// Gets the path of the field where the error was found. The root is `$`,
+    /// and each key is appended as `.key`, or `["key"]` if it is not a plain
+    /// identifier.
+    pub fn path(&self) -> &str { &self.path }
+
+    /// Gets the byte offset in the input where the error was found.
+    pub fn offset(&self) -> usize { self.offset }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {} (byte {})", self.kind, self.path, self.offset)
+    }
+}
+
+impl Error for JsonError {}
+
+impl MessageDatum {
+    /// Writes the MessageDatum as JSON. Map keys are sorted, so that equal
+    /// datums are written identically.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        write_datum(&mut out, self);
+        out
+    }
+
+    /// Parses a MessageDatum from JSON.
+    pub fn from_json(json: &str) -> Result<MessageDatum, JsonError> {
+        let mut p = Parser { bytes: json.as_bytes(), pos: 0, path: vec![] };
+        let datum = p.datum(0)?;
+        p.skip_whitespace();
+        if p.pos < p.bytes.len() {
+            return Err(p.error(JsonErrorKind::TrailingCharacters));
+        }
+        Ok(datum)
+    }
+}
+
+fn write_datum(out: &mut String, datum: &MessageDatum) {
+    match *datum {
+        MessageDatum::Void => out.push_str("null"),
+        MessageDatum::I64(x) => { let _ = write!(out, "{}", x); },
+        MessageDatum::U64(x) => { let _ = write!(out, "{{\"{}\":{}}}", TAG_U64, x); },
+        MessageDatum::F64(x) => {
+            if x.is_nan() {
+                let _ = write!(out, "{{\"{}\":\"NaN\"}}", TAG_F64);
+            } else if x.is_infinite() {
+                let sign = if x < 0.0 { "-" } else { "" };
+                let _ = write!(out, "{{\"{}\":\"{}inf\"}}", TAG_F64, sign);
+            } else {
+                // Debug always has a fraction or an exponent, and round-trips.
+                let _ = write!(out, "{:?}", x);
+            }
+        },
+        MessageDatum::Str(ref s) => write_str(out, s),
+        MessageDatum::Map(ref m) => {
+            let escape = m.len() == 1 && m.keys().all(|k| k.starts_with('$'));
+            if escape {
+                let _ = write!(out, "{{\"{}\":", TAG_MAP);
+            }
+            out.push('{');
+            let mut keys: Vec<&String> = m.keys().collect();
+            keys.sort();
+            for (i, k) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_str(out, k);
+                out.push(':');
+                write_datum(out, &m[k]);
+            }
+            out.push('}');
+            if escape {
+                out.push('}');
+            }
+        },
+        MessageDatum::Act(ref a) => {
+            let _ = write!(out, "{{\"{}\":", TAG_ACT);
+            write_str(out, &a.id.to_string());
+            out.push('}');
+        },
+        MessageDatum::Bool(x) => out.push_str(if x { "true" } else { "false" }),
+        MessageDatum::Bytes(ref x) => {
+            let _ = write!(out, "{{\"{}\":\"{}\"}}", TAG_BYTES, to_base64(x));
+        },
+        MessageDatum::List(ref l) => write_items(out, l),
+        MessageDatum::Tuple(ref t) => {
+            let _ = write!(out, "{{\"{}\":", TAG_TUPLE);
+            write_items(out, t);
+            out.push('}');
+        },
+        MessageDatum::Nil => { let _ = write!(out, "{{\"{}\":null}}", TAG_NIL); },
+    }
+}
+
+fn write_items(out: &mut String, items: &[MessageDatum]) {
+    out.push('[');
+    for (i, d) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_datum(out, d);
+    }
+    out.push(']');
+}
+
+fn to_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn from_base64(s: &str) -> Option<Vec<u8>> {
+    let s = s.as_bytes();
+    if !s.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(s.len() / 4 * 3);
+    for (i, chunk) in s.chunks(4).enumerate() {
+        let last = i == s.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for &c in &chunk[..4 - padding] {
+            n = (n << 6) | BASE64.iter().position(|&b| b == c)? as u32;
+        }
+        n <<= 6 * padding as u32;
+        out.extend_from_slice(&[(n >> 16) as u8, (n >> 8) as u8, n as u8][..3 - padding]);
+    }
+    Some(out)
+}
+
+fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); },
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    path: Vec<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, kind: JsonErrorKind) -> JsonError {
+        JsonError { kind, path: datum_path(&self.path), offset: self.pos }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.bytes.len() &&
+              (self.bytes[self.pos] as char).is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Result<u8, JsonError> {
+        self.skip_whitespace();
+        match self.bytes.get(self.pos) {
+            Some(b) => Ok(*b),
+            None => Err(self.error(JsonErrorKind::UnexpectedEnd))
+        }
+    }
+
+    fn unexpected(&self) -> JsonError {
+        let rest = String::from_utf8_lossy(&self.bytes[self.pos..]).into_owned();
+        match rest.chars().next() {
+            Some(c) => self.error(JsonErrorKind::UnexpectedChar(c)),
+            None => self.error(JsonErrorKind::UnexpectedEnd)
+        }
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), JsonError> {
+        if self.peek()? == b {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn datum(&mut self, depth: usize) -> Result<MessageDatum, JsonError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error(JsonErrorKind::TooDeep));
+        }
+        match self.peek()? {
+            b'n' => self.literal("null", MessageDatum::Void),
+            b't' => self.literal("true", MessageDatum::Bool(true)),
+            b'f' => self.literal("false", MessageDatum::Bool(false)),
+            b'[' => Ok(MessageDatum::List(self.array(depth)?.into())),
+            b'"' => Ok(MessageDatum::Str(self.string()?.into())),
+            b'{' => {
+                let m = self.object(depth)?;
+                self.interpret(m)
+            },
+            b'-' | b'0'..=b'9' => self.number(),
+            _ => Err(self.unexpected())
+        }
+    }
+
+    fn literal(&mut self, word: &str, datum: MessageDatum) -> Result<MessageDatum, JsonError> {
+        if self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(datum)
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn number(&mut self) -> Result<MessageDatum, JsonError> {
+        let start = self.pos;
+        let mut float = false;
+        while let Some(&b) = self.bytes.get(self.pos) {
+            match b {
+                b'0'..=b'9' | b'-' | b'+' => {},
+                b'.' | b'e' | b'E' => { float = true; },
+                _ => { break; }
+            }
+            self.pos += 1;
+        }
+        let text = ::std::str::from_utf8(&self.bytes[start..self.pos]).unwrap();
+        let valid = if float {
+            text.parse::<f64>().ok().map(MessageDatum::F64)
+        } else {
+            text.parse::<i64>().ok().map(MessageDatum::I64)
+                .or_else(|| text.parse::<u64>().ok().map(MessageDatum::U64))
+        };
+        // Rust is more lenient than JSON about leading zeros and signs.
+        let digits = text.strip_prefix('-').unwrap_or(text);
+        let leading = digits.as_bytes();
+        let strict = leading.first().is_some_and(|b| b.is_ascii_digit()) &&
+                     !(leading[0] == b'0' && leading.get(1).is_some_and(|b| b.is_ascii_digit()));
+        match valid {
+            Some(datum) if strict => Ok(datum),
+            _ => {
+                self.pos = start;
+                Err(self.error(JsonErrorKind::InvalidNumber))
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect(b'"')?;
+        let mut s = String::new();
+        loop {
+            let start = self.pos;
+            while let Some(&b) = self.bytes.get(self.pos) {
+                if b == b'"' || b == b'\\' || b < 0x20 { break; }
+                self.pos += 1;
+            }
+            // The input is a &str, and we only stop at ASCII characters.
+            s.push_str(::std::str::from_utf8(&self.bytes[start..self.pos]).unwrap());
+            match self.bytes.get(self.pos) {
+                Some(&b'"') => { self.pos += 1; return Ok(s); },
+                Some(&b'\\') => {
+                    self.pos += 1;
+                    s.push(self.escape()?);
+                },
+                Some(_) => { return Err(self.unexpected()); },
+                None => { return Err(self.error(JsonErrorKind::UnexpectedEnd)); }
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, JsonError> {
+        let b = match self.bytes.get(self.pos) {
+            Some(&b) => b,
+            None => { return Err(self.error(JsonErrorKind::UnexpectedEnd)); }
+        };
+        self.pos += 1;
+        match b {
+            b'"' => Ok('"'),
+            b'\\' => Ok('\\'),
+            b'/' => Ok('/'),
+            b'b' => Ok('\u{8}'),
+            b'f' => Ok('\u{c}'),
+            b'n' => Ok('\n'),
+            b'r' => Ok('\r'),
+            b't' => Ok('\t'),
+            b'u' => {
+                let high = self.hex4()?;
+                let code = if (0xd800..0xdc00).contains(&high) {
+                    if !self.bytes[self.pos..].starts_with(b"\\u") {
+                        return Err(self.error(JsonErrorKind::InvalidEscape));
+                    }
+                    self.pos += 2;
+                    let low = self.hex4()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.error(JsonErrorKind::InvalidEscape));
+                    }
+                    0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                } else {
+                    high
+                };
+                ::std::char::from_u32(code).ok_or_else(|| self.error(JsonErrorKind::InvalidEscape))
+            },
+            _ => Err(self.error(JsonErrorKind::InvalidEscape))
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self.bytes.get(self.pos..self.pos + 4)
+            .and_then(|d| ::std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok());
+        match digits {
+            Some(x) => { self.pos += 4; Ok(x) },
+            None => Err(self.error(JsonErrorKind::InvalidEscape))
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Vec<MessageDatum>, JsonError> {
+        self.expect(b'[')?;
+        let mut items = vec![];
+        if self.peek()? == b']' {
+            self.pos += 1;
+            return Ok(items);
+        }
+        loop {
+            self.path.push(items.len().to_string());
+            items.push(self.datum(depth + 1)?);
+            self.path.pop();
+            match self.peek()? {
+                b',' => { self.pos += 1; },
+                b']' => { self.pos += 1; return Ok(items); },
+                _ => { return Err(self.unexpected()); }
+            }
+        }
+    }
+
+    // Parses an object without interpreting it as a tagged value. The value
+    // of a "$map" key is not interpreted either, since it is only known to be
+    // an escaped map once the whole object has been read.
+    fn object(&mut self, depth: usize) -> Result<HashMap<String, MessageDatum>, JsonError> {
+        self.expect(b'{')?;
+        let mut m = HashMap::new();
+        if self.peek()? == b'}' {
+            self.pos += 1;
+            return Ok(m);
+        }
+        loop {
+            let key = self.string()?;
+            self.expect(b':')?;
+            self.path.push(key);
+            let value = if self.path.last().unwrap() == TAG_MAP && self.peek()? == b'{' {
+                if depth >= MAX_DEPTH {
+                    return Err(self.error(JsonErrorKind::TooDeep));
+                }
+                MessageDatum::from(self.object(depth + 1)?)
+            } else {
+                self.datum(depth + 1)?
+            };
+            m.insert(self.path.pop().unwrap(), value);
+            match self.peek()? {
+                b',' => { self.pos += 1; },
+                b'}' => { self.pos += 1; return Ok(m); },
+                _ => { return Err(self.unexpected()); }
+            }
+        }
+    }
+
+    fn interpret(&mut self, mut m: HashMap<String, MessageDatum>)
+        -> Result<MessageDatum, JsonError> {
+        if m.len() != 1 || !m.keys().all(|k| k.starts_with('$')) {
+            self.interpret_escaped_key(&mut m)?;
+            return Ok(MessageDatum::from(m));
+        }
+        let (key, value) = m.into_iter().next().unwrap();
+        let datum = match key.as_str() {
+            TAG_U64 => match value {
+                MessageDatum::I64(x) if x >= 0 => Some(MessageDatum::U64(x as u64)),
+                MessageDatum::U64(x) => Some(MessageDatum::U64(x)),
+                _ => None
+            },
+            TAG_F64 => match value.as_str_ref() {
+                Some("NaN") => Some(MessageDatum::F64(f64::NAN)),
+                Some("inf") => Some(MessageDatum::F64(f64::INFINITY)),
+                Some("-inf") => Some(MessageDatum::F64(f64::NEG_INFINITY)),
+                _ => None
+            },
+            TAG_ACT => match value {
+                MessageDatum::Str(ref s) => {
+                    s.parse::<ActorId>().ok().map(|id| MessageDatum::Act(ActorAddress::detached(id)))
+                },
+                _ => None
+            },
+            TAG_BYTES => match value {
+                MessageDatum::Str(ref s) => from_base64(s).map(MessageDatum::from),
+                _ => None
+            },
+            TAG_TUPLE => match value {
+                MessageDatum::List(items) => Some(MessageDatum::Tuple(items)),
+                _ => None
+            },
+            TAG_NIL => match value {
+                MessageDatum::Void => Some(MessageDatum::Nil),
+                _ => None
+            },
+            TAG_MAP => match value {
+                MessageDatum::Map(m) => {
+                    let mut m = unshare(m);
+                    self.path.push(key.clone());
+                    self.interpret_escaped_key(&mut m)?;
+                    self.path.pop();
+                    Some(MessageDatum::from(m))
+                },
+                _ => None
+            },
+            _ => None
+        };
+        match datum {
+            Some(datum) => Ok(datum),
+            None => {
+                self.path.push(key.clone());
+                let e = self.error(JsonErrorKind::InvalidTag(key));
+                self.path.pop();
+                Err(e)
+            }
+        }
+    }
+
+    // Interprets the value of a "$map" key in an object which turned out not
+    // to be an escaped map.
+    fn interpret_escaped_key(&mut self, m: &mut HashMap<String, MessageDatum>)
+        -> Result<(), JsonError> {
+        if let Some(MessageDatum::Map(inner)) = m.remove(TAG_MAP) {
+            self.path.push(TAG_MAP.to_string());
+            let inner = self.interpret(unshare(inner))?;
+            m.insert(self.path.pop().unwrap(), inner);
+        }
+        Ok(())
+    }
+}
+
+// Takes back a map parsed from an object, which is not shared yet.
+fn unshare(m: Arc<HashMap<String, MessageDatum>>) -> HashMap<String, MessageDatum> {
+    Arc::try_unwrap(m).unwrap_or_else(|m| (*m).clone())
+}
@@ -175,6 +175,8 @@
 //! ```
 //! extern crate mecha;
 //! use std::sync::mpsc;
+//! # use std::thread;
+//! # use std::time::Duration;
 //!
 //! #[derive(Default)]
 //! struct CounterState { active: bool, count: i32 }
@@ -182,7 +184,6 @@
 //! const INC : &'static str = ":inc";
 //! const ACTIVATE : &'static str = ":activate";
 //!
-//! #[test]
 //! fn test_stateful() {
 //!     let (tx, rx) = mpsc::channel();
 //!     let initiator = mecha::ActorAddress::new(tx);
@@ -240,6 +241,7 @@
 //!         _ => { println!("Actor must have exited with an error."); }
 //!     }
 //! }
+//! # test_stateful();
 //! ```
 //!
 //!
@@ -264,33 +266,214 @@
 
 use std::thread;
 use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, AtomicU64, Ordering};
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+use std::fmt;
 
-extern crate uuid;
+extern crate rand;
+extern crate sha2;
+#[macro_use]
+extern crate log;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "derive")]
+extern crate mecha_derive;
+// The derived code refers to the crate as ::mecha, which has to resolve in
+// the tests of the crate itself too.
+#[cfg(all(test, feature = "derive"))]
+extern crate self as mecha;
+#[cfg(test)]
+extern crate quickcheck;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
+
+mod server;
+mod fsm;
+mod timer;
+mod event;
+mod group;
+mod pubsub;
+mod router;
+mod autoscale;
+mod codec;
+mod json;
+mod atom;
+mod id;
+mod typed;
+#[macro_use]
+mod pattern;
+mod transport;
+mod auth;
+mod membership;
+mod gossip;
+mod global;
+mod node;
+mod singleton;
+mod sharding;
+mod reliable;
+#[cfg(feature = "serde")]
+mod value;
+
+pub use server::{Server, ServerState};
+pub use fsm::{Fsm, FsmState, Transition};
+pub use event::{EventHandler, EventManager, EVENT_HANDLER_EXITED};
+pub use group::ProcessGroups;
+pub use pubsub::{PubSub, topic_matches};
+pub use router::{Router, RoutingStrategy, ROUTER_RESIZE};
+pub use autoscale::Autoscaling;
+pub use codec::{Codec, CodecError, CODEC_VERSION};
+pub use json::{JsonError, JsonErrorKind};
+pub use atom::{Atom, MAX_ATOMS};
+pub use id::{ActorId, ParseActorIdError};
+pub use typed::{FromDatum, TypedAddress, TypedMessage};
+pub use pattern::{Bindings, DatumKind, DatumPattern, Matcher, Pattern};
+pub use membership::{Membership, NODE_DOWN, NODE_LOST, NODE_UP};
+pub use gossip::{Member, MemberStatus};
+pub use global::GLOBAL_NAME_CONFLICT;
+pub use node::Node;
+pub use singleton::Singleton;
+pub use sharding::Sharding;
+pub use reliable::{ReliableChannel, ReliableSender};
+#[cfg(feature = "serde")]
+pub use value::{DatumSerializer, ValueError, from_datum, to_datum};
+#[cfg(feature = "derive")]
+pub use mecha_derive::MechaMessage;
 
 /// An ActorAddress structure is used, essentially, just as the identifier of an
 /// actor for sending messages to it. ActorAddresses can be cheaply cloned and
-/// passed around.
+/// passed around. The identity of the actor is its ActorId.
 #[derive(Clone, Debug)]
 pub struct ActorAddress {
-    id: uuid::Uuid,
-    endpoint: mpsc::Sender<Message>
+    id: ActorId,
+    endpoint: Endpoint,
+    stats: Arc<MailboxStats>
+}
+
+/// Where the messages sent to an ActorAddress go: the mailbox of the actor, or
+/// the channel which forwards them to all the actors of another node.
+#[derive(Clone, Debug)]
+enum Endpoint {
+    Local(mpsc::Sender<Message>),
+    Remote(mpsc::Sender<(ActorId, Message)>)
+}
+
+/// The statistics of the mailbox of an actor, shared by all the clones of its
+/// ActorAddress.
+#[derive(Debug, Default)]
+struct MailboxStats {
+    depth: AtomicUsize,
+    latency_us: AtomicU64
+}
+
+impl MailboxStats {
+    /// Records that a message has been taken out of the mailbox and processed.
+    fn processed(&self, msg: &Message) {
+        self.depth.fetch_sub(1, Ordering::Relaxed);
+        let waited = msg.sent_at.elapsed();
+        let sample = waited.as_secs() * 1_000_000 + u64::from(waited.subsec_micros());
+        // Keep a moving average, so that a single message does not make the
+        // latency jump around.
+        let old = self.latency_us.load(Ordering::Relaxed);
+        self.latency_us.store((old * 3 + sample) / 4, Ordering::Relaxed);
+    }
 }
 
 impl ActorAddress {
     /// Creates a new ActorAddress with a provided sender half of a channel.
     pub fn new(endpoint: mpsc::Sender<Message>) -> ActorAddress {
-        ActorAddress { id: uuid::Uuid::new_v4(), endpoint: endpoint }
+        ActorAddress {
+            id: ActorId::new_local(),
+            endpoint: Endpoint::Local(endpoint),
+            stats: Arc::new(MailboxStats::default())
+        }
+    }
+
+    /// Creates an ActorAddress with the given id which is not connected to any
+    /// actor: messages sent to it are dropped.
+    fn detached(id: ActorId) -> ActorAddress {
+        let (endpoint, _) = mpsc::channel();
+        ActorAddress { id, endpoint: Endpoint::Local(endpoint), stats: Arc::new(MailboxStats::default()) }
+    }
+
+    /// Creates an ActorAddress for the actor with the given id on another
+    /// node, whose messages are sent, along with the id, to the provided
+    /// channel to be forwarded.
+    fn remote(id: ActorId, endpoint: mpsc::Sender<(ActorId, Message)>) -> ActorAddress {
+        ActorAddress { id, endpoint: Endpoint::Remote(endpoint), stats: Arc::new(MailboxStats::default()) }
+    }
+
+    /// Gets the id of the actor.
+    pub fn id(&self) -> &ActorId { &self.id }
+
+    /// Gets the name of the node the actor runs on, or None if it runs in this
+    /// process.
+    pub fn node(&self) -> Option<&str> { self.id.node() }
+
+    /// Gets the number of messages that have been sent to the actor and not
+    /// processed yet, including the ones it is not able to match. For "fake"
+    /// actor addresses wrapping a channel, this is the number of messages ever
+    /// sent to the channel.
+    pub fn mailbox_len(&self) -> usize {
+        self.stats.depth.load(Ordering::Relaxed)
+    }
+
+    /// Gets how long messages have recently been waiting in the mailbox of the
+    /// actor before being processed, as a moving average.
+    pub fn mailbox_latency(&self) -> Duration {
+        Duration::from_micros(self.stats.latency_us.load(Ordering::Relaxed))
+    }
+
+    /// Sends a message to the actor, returning false if the actor is not
+    /// running anymore.
+    fn deliver(&self, mut msg: Message) -> bool {
+        msg.sent_at = Instant::now();
+        self.stats.depth.fetch_add(1, Ordering::Relaxed);
+        let sent = match self.endpoint {
+            Endpoint::Local(ref endpoint) => endpoint.send(msg).is_ok(),
+            Endpoint::Remote(ref endpoint) => endpoint.send((self.id.clone(), msg)).is_ok()
+        };
+        if !sent {
+            self.stats.depth.fetch_sub(1, Ordering::Relaxed);
+            return false;
+        }
+        true
     }
 }
 
+/// Two ActorAddresses are equal if they identify the same actor process,
+/// regardless of which clone of the address is being compared.
+impl PartialEq for ActorAddress {
+    fn eq(&self, other: &ActorAddress) -> bool { self.id == other.id }
+}
+impl Eq for ActorAddress {}
+impl Hash for ActorAddress {
+    fn hash<H: Hasher>(&self, state: &mut H) { self.id.hash(state); }
+}
+/// ActorAddresses are ordered by their ActorIds.
+impl PartialOrd for ActorAddress {
+    fn partial_cmp(&self, other: &ActorAddress) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for ActorAddress {
+    fn cmp(&self, other: &ActorAddress) -> std::cmp::Ordering { self.id.cmp(&other.id) }
+}
+impl fmt::Display for ActorAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.id.fmt(f) }
+}
+
 /// A MessageType defines a number of standard messages (such as the one to
 /// stop an actor) and a Custom type which can be used to send user-defined
 /// messages between actors.
 ///
 /// Custom types contain a static str (so basically they're created from a
-/// string literal) which can be matched on.
-#[derive(Clone, PartialOrd, PartialEq, Ord, Eq, Debug)]
+/// string literal, or from an Atom for names only known at runtime) which can
+/// be matched on.
+#[derive(Clone, PartialOrd, Ord, Debug)]
 pub enum MessageType {
     /// A message of this type notifies linked actors that the sender has
     /// exited. This message cannot be manually sent (and the builder pattern
@@ -302,25 +485,77 @@ pub enum MessageType {
     /// A message of this type will tell the actor to be linked to the sender,
     /// i.e. the sender will be notified when the receiver exits.
     Link,
+    /// A message of this type will tell the actor to remove the sender from
+    /// its links, i.e. the sender will no longer be notified when the
+    /// receiver exits.
+    Unlink,
     /// A message of this type will stop and kill the actor receiving it.
     Shutdown,
+    /// A message of this type is a synchronous request: the receiver is
+    /// expected to answer the sender with a Reply message. Like Custom, it
+    /// contains a static str which can be matched on.
+    Call(&'static str),
+    /// A message of this type carries the answer to a Call. This message
+    /// cannot be manually sent; it is sent by server actors (see `Server`).
+    Reply,
     /// This is a custom message type to use for user-defined messages.
     Custom(&'static str),
 }
 
+impl PartialEq for MessageType {
+    fn eq(&self, other: &MessageType) -> bool {
+        match (self, other) {
+            (&MessageType::Call(a), &MessageType::Call(b)) |
+            (&MessageType::Custom(a), &MessageType::Custom(b)) => {
+                // Names of atoms (and often string literals) are the same
+                // string, so compare the pointers first.
+                std::ptr::eq(a, b) || a == b
+            },
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other)
+        }
+    }
+}
+
+impl Eq for MessageType {}
+
+impl Hash for MessageType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match *self {
+            MessageType::Call(name) | MessageType::Custom(name) => name.hash(state),
+            _ => {}
+        }
+    }
+}
+
 /// This variant type specifies what kind of data can be passed around in
 /// messages; we believe it is better to have a well defined variant type rather
 /// than something like an Any. This makes serialization well defined, and
-/// the Map variant can serialize complex data structures anyway.
-#[derive(Clone, Debug)]
+/// the Map, List and Tuple variants can serialize complex data structures
+/// anyway.
+///
+/// A List is a sequence of any length, while a Tuple is a group of a fixed
+/// number of values, like a Rust tuple. Void means that there is no datum at
+/// all, while Nil is an explicitly missing value, like a `None`.
+///
+/// Strings, byte buffers, Maps, Lists and Tuples are immutable and
+/// reference-counted, so cloning a MessageDatum (for example, to send the same
+/// message to many actors) never copies its contents. The `as_*_ref` accessors
+/// and `get_path` borrow from the MessageDatum without allocating.
+#[derive(Clone, PartialEq, Debug)]
 pub enum MessageDatum {
     Void,
     I64(i64),
     U64(u64),
     F64(f64),
-    Str(String),
-    Map(HashMap<String, MessageDatum>),
-    Act(ActorAddress)
+    Str(Arc<str>),
+    Map(Arc<HashMap<String, MessageDatum>>),
+    Act(ActorAddress),
+    Bool(bool),
+    Bytes(Arc<[u8]>),
+    List(Arc<[MessageDatum]>),
+    Tuple(Arc<[MessageDatum]>),
+    Nil
 }
 impl MessageDatum {
     /// Extracts (clones) an i64 from the MessageDatum if possible.
@@ -346,15 +581,23 @@ impl MessageDatum {
     }
     /// Extracts (clones) a String from the MessageDatum if possible.
     pub fn as_str(&self) -> Option<String> {
+        self.as_str_ref().map(|x| x.to_string())
+    }
+    /// Borrows a string from the MessageDatum if possible.
+    pub fn as_str_ref(&self) -> Option<&str> {
         match *self {
-            MessageDatum::Str(ref x) => Some(x.clone()),
+            MessageDatum::Str(ref x) => Some(x),
             _ => None
         }
     }
     /// Extracts (clones) a Map from the MessageDatum if possible.
     pub fn as_map(&self) -> Option<HashMap<String, MessageDatum>> {
+        self.as_map_ref().cloned()
+    }
+    /// Borrows a Map from the MessageDatum if possible.
+    pub fn as_map_ref(&self) -> Option<&HashMap<String, MessageDatum>> {
         match *self {
-            MessageDatum::Map(ref m) => Some(m.clone()),
+            MessageDatum::Map(ref m) => Some(m),
             _ => None
         }
     }
@@ -365,6 +608,83 @@ impl MessageDatum {
             _ => None
         }
     }
+    /// Extracts (clones) a bool from the MessageDatum if possible.
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            MessageDatum::Bool(x) => Some(x),
+            _ => None
+        }
+    }
+    /// Extracts (clones) a byte buffer from the MessageDatum if possible.
+    pub fn as_bytes(&self) -> Option<Vec<u8>> {
+        self.as_bytes_ref().map(|x| x.to_vec())
+    }
+    /// Borrows a byte buffer from the MessageDatum if possible.
+    pub fn as_bytes_ref(&self) -> Option<&[u8]> {
+        match *self {
+            MessageDatum::Bytes(ref x) => Some(x),
+            _ => None
+        }
+    }
+    /// Extracts (clones) a List from the MessageDatum if possible.
+    pub fn as_list(&self) -> Option<Vec<MessageDatum>> {
+        self.as_list_ref().map(|x| x.to_vec())
+    }
+    /// Borrows the items of a List from the MessageDatum if possible.
+    pub fn as_list_ref(&self) -> Option<&[MessageDatum]> {
+        match *self {
+            MessageDatum::List(ref x) => Some(x),
+            _ => None
+        }
+    }
+    /// Extracts (clones) a Tuple from the MessageDatum if possible.
+    pub fn as_tuple(&self) -> Option<Vec<MessageDatum>> {
+        self.as_tuple_ref().map(|x| x.to_vec())
+    }
+    /// Borrows the items of a Tuple from the MessageDatum if possible.
+    pub fn as_tuple_ref(&self) -> Option<&[MessageDatum]> {
+        match *self {
+            MessageDatum::Tuple(ref x) => Some(x),
+            _ => None
+        }
+    }
+    /// Borrows the value with the given key from a Map, or the item with the
+    /// given index from a List or a Tuple.
+    pub fn get(&self, key: &str) -> Option<&MessageDatum> {
+        match *self {
+            MessageDatum::Map(ref m) => m.get(key),
+            MessageDatum::List(ref x) | MessageDatum::Tuple(ref x) => {
+                key.parse::<usize>().ok().and_then(|i| x.get(i))
+            },
+            _ => None
+        }
+    }
+    /// Borrows a value nested in Maps, Lists and Tuples, following the given
+    /// keys (or indices) from the outermost. For example,
+    /// `datum.get_path(&["users", "0", "name"])`.
+    pub fn get_path(&self, path: &[&str]) -> Option<&MessageDatum> {
+        path.iter().try_fold(self, |d, key| d.get(key))
+    }
+    /// Checks whether the MessageDatum is Nil.
+    pub fn is_nil(&self) -> bool {
+        matches!(*self, MessageDatum::Nil)
+    }
+}
+/// Formats the path of a value nested in Maps with the given keys, from the
+/// outermost. The root is `$`, and each key is appended as `.key`, or
+/// `["key"]` if it is not a plain identifier.
+fn datum_path(keys: &[String]) -> String {
+    let mut path = "$".to_string();
+    for k in keys {
+        let plain = !k.is_empty() && k.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if plain {
+            path.push('.');
+            path.push_str(k);
+        } else {
+            path.push_str(&format!("[{:?}]", k));
+        }
+    }
+    path
 }
 impl From<i64> for MessageDatum {
     fn from(x: i64) -> MessageDatum { MessageDatum::I64(x) }
@@ -376,13 +696,13 @@ impl From<f64> for MessageDatum {
     fn from(x: f64) -> MessageDatum { MessageDatum::F64(x) }
 }
 impl From<String> for MessageDatum {
-    fn from(x: String) -> MessageDatum { MessageDatum::Str(x) }
+    fn from(x: String) -> MessageDatum { MessageDatum::Str(x.into()) }
 }
 impl<'a> From<&'a str> for MessageDatum {
-    fn from(x: &'a str) -> MessageDatum { MessageDatum::Str(x.to_string()) }
+    fn from(x: &'a str) -> MessageDatum { MessageDatum::Str(x.into()) }
 }
 impl From<HashMap<String, MessageDatum>> for MessageDatum {
-    fn from(x: HashMap<String, MessageDatum>) -> MessageDatum { MessageDatum::Map(x) }
+    fn from(x: HashMap<String, MessageDatum>) -> MessageDatum { MessageDatum::Map(Arc::new(x)) }
 }
 impl From<ActorAddress> for MessageDatum {
     fn from(x: ActorAddress) -> MessageDatum { MessageDatum::Act(x) }
@@ -390,6 +710,38 @@ impl From<ActorAddress> for MessageDatum {
 impl<'a> From<&'a ActorAddress> for MessageDatum {
     fn from(x: &'a ActorAddress) -> MessageDatum { MessageDatum::Act(x.clone()) }
 }
+impl From<bool> for MessageDatum {
+    fn from(x: bool) -> MessageDatum { MessageDatum::Bool(x) }
+}
+impl From<Vec<u8>> for MessageDatum {
+    fn from(x: Vec<u8>) -> MessageDatum { MessageDatum::Bytes(x.into()) }
+}
+impl<'a> From<&'a [u8]> for MessageDatum {
+    fn from(x: &'a [u8]) -> MessageDatum { MessageDatum::Bytes(x.into()) }
+}
+impl From<Vec<MessageDatum>> for MessageDatum {
+    fn from(x: Vec<MessageDatum>) -> MessageDatum { MessageDatum::List(x.into()) }
+}
+impl<A, B> From<(A, B)> for MessageDatum
+    where A: Into<MessageDatum>, B: Into<MessageDatum> {
+    fn from(x: (A, B)) -> MessageDatum {
+        MessageDatum::Tuple(Arc::new([x.0.into(), x.1.into()]))
+    }
+}
+impl<A, B, C> From<(A, B, C)> for MessageDatum
+    where A: Into<MessageDatum>, B: Into<MessageDatum>, C: Into<MessageDatum> {
+    fn from(x: (A, B, C)) -> MessageDatum {
+        MessageDatum::Tuple(Arc::new([x.0.into(), x.1.into(), x.2.into()]))
+    }
+}
+impl<T: Into<MessageDatum>> From<Option<T>> for MessageDatum {
+    fn from(x: Option<T>) -> MessageDatum {
+        match x {
+            Some(x) => x.into(),
+            None => MessageDatum::Nil
+        }
+    }
+}
 
 /// A Message contains a type, the actor from whom the message comes, and a
 /// datum. A Message can be created and sent using a builder pattern.
@@ -397,7 +749,8 @@ impl<'a> From<&'a ActorAddress> for MessageDatum {
 pub struct Message {
     mt: MessageType,
     sender: ActorAddress,
-    datum: MessageDatum
+    datum: MessageDatum,
+    sent_at: Instant
 }
 
 /// The builder struct for a Message.
@@ -433,6 +786,15 @@ impl Message {
         }
     }
 
+    /// Initializes a message builder for an Unlink typed message.
+    pub fn unlink() -> MessageBuilder {
+        MessageBuilder {
+            mt: MessageType::Unlink,
+            sender: None,
+            datum: None,
+        }
+    }
+
     /// Initializes a message builder for a Shutdown typed message.
     pub fn shutdown() -> MessageBuilder {
         MessageBuilder {
@@ -442,8 +804,28 @@ impl Message {
         }
     }
 
+    /// Initializes a message builder for a Call typed message.
+    pub fn call(mt: &'static str) -> MessageBuilder {
+        Atom::from_static(mt);
+        MessageBuilder {
+            mt: MessageType::Call(mt),
+            sender: None,
+            datum: None,
+        }
+    }
+
+    /// Initializes a message builder for a Reply typed message.
+    fn reply() -> MessageBuilder {
+        MessageBuilder {
+            mt: MessageType::Reply,
+            sender: None,
+            datum: None,
+        }
+    }
+
     /// Initializes a message builder for a Custom typed message.
     pub fn custom(mt: &'static str) -> MessageBuilder {
+        Atom::from_static(mt);
         MessageBuilder {
             mt: MessageType::Custom(mt),
             sender: None,
@@ -452,6 +834,9 @@ impl Message {
     }
 }
 
+// The builder methods spell out the lifetime of the returned reference, as
+// they always have.
+#[allow(clippy::needless_lifetimes)]
 impl MessageBuilder {
     /// Specifies the sender of the message.
     pub fn with_sender<'a>(&'a mut self, f: &ActorAddress) -> &'a mut MessageBuilder {
@@ -482,7 +867,7 @@ impl MessageBuilder {
 
     /// Specifies a string as the datum of the message.
     pub fn with_str<'a>(&'a mut self, s: &str) -> &'a mut MessageBuilder {
-        self.with_datum(MessageDatum::from(s.to_string()))
+        self.with_datum(MessageDatum::from(s))
     }
 
     /// Specifies a map as the datum of the message.
@@ -495,6 +880,31 @@ impl MessageBuilder {
         self.with_datum(MessageDatum::from(a))
     }
 
+    /// Specifies a bool as the datum of the message.
+    pub fn with_bool<'a>(&'a mut self, b: bool) -> &'a mut MessageBuilder {
+        self.with_datum(MessageDatum::from(b))
+    }
+
+    /// Specifies a byte buffer as the datum of the message.
+    pub fn with_bytes<'a>(&'a mut self, b: &[u8]) -> &'a mut MessageBuilder {
+        self.with_datum(MessageDatum::from(b))
+    }
+
+    /// Specifies a list as the datum of the message.
+    pub fn with_list<'a>(&'a mut self, l: Vec<MessageDatum>) -> &'a mut MessageBuilder {
+        self.with_datum(MessageDatum::List(l.into()))
+    }
+
+    /// Specifies a tuple as the datum of the message.
+    pub fn with_tuple<'a>(&'a mut self, t: Vec<MessageDatum>) -> &'a mut MessageBuilder {
+        self.with_datum(MessageDatum::Tuple(t.into()))
+    }
+
+    /// Specifies Nil as the datum of the message.
+    pub fn with_nil<'a>(&'a mut self) -> &'a mut MessageBuilder {
+        self.with_datum(MessageDatum::Nil)
+    }
+
     /// Builds the Message, should a user want to store it. Generally this is
     /// not necessary, just use `send_to()` to send it directly.
     pub fn build(&self) -> Message {
@@ -506,7 +916,8 @@ impl MessageBuilder {
             datum: match self.datum {
                 None => MessageDatum::Void,
                 Some(ref x) => x.clone(),
-            }
+            },
+            sent_at: Instant::now()
         }
     }
 
@@ -518,7 +929,13 @@ impl MessageBuilder {
     /// `mecha::Message::custom("blah").with_sender(&some_actor).with_i64(123).send_to(&other_actor);`
     ///
     pub fn send_to(&self, to: &ActorAddress) {
-        to.endpoint.send(self.build()).unwrap_or(()); // TODO: Error handling.
+        to.deliver(self.build()); // TODO: Error handling.
+    }
+
+    /// Builds the Message and sends it to the specified actor once the
+    /// provided delay has elapsed. This does not block the caller.
+    pub fn send_after(&self, to: &ActorAddress, delay: Duration) {
+        timer::schedule(Instant::now() + delay, to, self.build());
     }
 }
 
@@ -529,6 +946,12 @@ pub struct Stateless;
 type MatchResult = bool;
 type ActionResult = Result<(), String>;
 
+type MatchFn<ActorState> = Box<dyn Matcher<ActorState>>;
+type ActionFn<ActorState> =
+    Box<dyn Fn(&Bindings, &Message, &mut ActorState, &ActorAddress) -> ActionResult + Send>;
+type InitFn<ActorState> = Box<dyn Fn(&mut ActorState, &ActorAddress) -> ActionResult + Send>;
+type TerminateFn<ActorState> = Box<dyn Fn(&mut ActorState, &MessageDatum) + Send>;
+
 /// Actor provides an API for creating actor processes based on a definition of
 /// state and a list of "match" clauses each with its own list of actions to
 /// perform upon a match.
@@ -557,14 +980,25 @@ type ActionResult = Result<(), String>;
 /// potentially modified). They also take a reference to the address of the
 /// actor process itself, so that it can be used as the sender of messages to
 /// other actor processes.
+///
+/// Optionally, init and terminate clauses can be provided. Init clauses run in
+/// the actor process before any message is processed; terminate clauses run
+/// when the actor process exits, and receive the exit reason (the same datum
+/// that is sent to linked actors in the Exited message).
 pub struct Actor<ActorState: 'static + Sized + Default + Send> {
     state: ActorState,
-    matches: Vec<Box<Fn(&Message, &ActorState) -> MatchResult + Send>>,
-    actions: Vec<Vec<Box<Fn(&Message, &mut ActorState, &ActorAddress) -> ActionResult + Send>>>,
+    matches: Vec<MatchFn<ActorState>>,
+    actions: Vec<Vec<ActionFn<ActorState>>>,
+    inits: Vec<InitFn<ActorState>>,
+    terminates: Vec<TerminateFn<ActorState>>,
     mailbox: Vec<Message>,
     uplinks: Vec<ActorAddress>
 }
 
+impl<ActorState: 'static + Sized + Default + Send> Default for Actor<ActorState> {
+    fn default() -> Self { Actor::new() }
+}
+
 impl<ActorState: 'static + Sized + Default + Send> Actor<ActorState> {
 
     /// Initializes the Actor building process.
@@ -573,6 +1007,8 @@ impl<ActorState: 'static + Sized + Default + Send> Actor<ActorState> {
             state: ActorState::default(),
             matches: Vec::new(),
             actions: Vec::new(),
+            inits: Vec::new(),
+            terminates: Vec::new(),
             mailbox: Vec::new(),
             uplinks: Vec::new(),
         }
@@ -588,11 +1024,11 @@ impl<ActorState: 'static + Sized + Default + Send> Actor<ActorState> {
     /// Adds a match clause to the Actor. Match clauses are functions or
     /// closures that take a reference to a Message and a reference to the
     /// current actor state. They must return true only on a successful match,
-    /// and have no side effects.
-    pub fn with_match<T>(mut self, mc: T) -> Self
+    /// and have no side effects. A Pattern is added with `with_matcher`
+    /// instead, so that the actions get its bindings.
+    pub fn with_match<T>(self, mc: T) -> Self
         where T: 'static + Fn(&Message, &ActorState) -> MatchResult + Send {
-        self.matches.push(Box::new(mc));
-        self
+        self.with_matcher(mc)
     }
 
     /// Adds an action clause to the current match clause of the Actor. Action
@@ -601,12 +1037,30 @@ impl<ActorState: 'static + Sized + Default + Send> Actor<ActorState> {
     /// potentially modified). They also take a reference to the address of the
     /// actor process itself, so that it can be used as the sender of messages
     /// to other actor processes.
-    pub fn with_action<T>(mut self, ac: T) -> Self
+    pub fn with_action<T>(self, ac: T) -> Self
         where T: 'static + Fn(&Message, &mut ActorState, &ActorAddress) -> ActionResult + Send {
-        while self.actions.len() < self.matches.len() {
-            self.actions.push(Vec::new());
-        }
-        self.actions[self.matches.len() -1].push(Box::new(ac));
+        self.with_bound_action(move |_, msg, state, myself| ac(msg, state, myself))
+    }
+
+    /// Adds an init clause to the Actor. Init clauses are functions or closures
+    /// that take a mutable reference to the actor state and a reference to the
+    /// address of the actor process itself. They run in the actor process
+    /// before any message is processed; if one fails, the actor process exits
+    /// with the returned error.
+    pub fn with_init<T>(mut self, ic: T) -> Self
+        where T: 'static + Fn(&mut ActorState, &ActorAddress) -> ActionResult + Send {
+        self.inits.push(Box::new(ic));
+        self
+    }
+
+    /// Adds a terminate clause to the Actor. Terminate clauses are functions or
+    /// closures that take a mutable reference to the actor state and a
+    /// reference to the exit reason, which is Void on a clean shutdown and a
+    /// String on errors. They run when the actor process exits, before the
+    /// linked actors are notified.
+    pub fn with_terminate<T>(mut self, tc: T) -> Self
+        where T: 'static + Fn(&mut ActorState, &MessageDatum) + Send {
+        self.terminates.push(Box::new(tc));
         self
     }
 
@@ -647,6 +1101,12 @@ fn main_actor_loop<ActorState>(
     own_address: &ActorAddress,
     receiver: mpsc::Receiver<Message>)
     where ActorState: 'static + Sized + Default + Send {
+    for init in actor.inits.iter() {
+        if let Err(e) = init(&mut actor.state, own_address) {
+            exit_actor(actor, own_address, MessageDatum::from(e));
+            return;
+        }
+    }
     'main: loop {
         // Match zero or one message and perform the associated action.
         let mut matched = false;
@@ -654,10 +1114,11 @@ fn main_actor_loop<ActorState>(
         let mut matched_message_idx: usize = 0;
         'outer: for msg in actor.mailbox.iter() {
             for (matcher, actions) in actor.matches.iter().zip(actor.actions.iter()) {
-                matched = matcher(msg, &actor.state);
-                if matched {
+                let bindings = matcher.bind(msg, &actor.state);
+                matched = bindings.is_some();
+                if let Some(bindings) = bindings {
                     for a in actions.iter() {
-                        result = a(msg, &mut actor.state, &own_address);
+                        result = a(&bindings, msg, &mut actor.state, own_address);
                         match result {
                             Ok(()) => (),
                             Err(_) => { break 'outer; }
@@ -673,12 +1134,8 @@ fn main_actor_loop<ActorState>(
             // and notify the uplinks (the "let it crash" pattern).
             match result {
                 Ok(()) => (),
-                Err(ref e) => {
-                    for u in actor.uplinks.iter() {
-                        Message::exited().with_sender(&own_address)
-                                         .with_str(e)
-                                         .send_to(u);
-                    }
+                Err(e) => {
+                    exit_actor(actor, own_address, MessageDatum::from(e));
                     break 'main;
                 }
             }
@@ -690,12 +1147,11 @@ fn main_actor_loop<ActorState>(
                     actor.uplinks.push(
                         msg.get_sender().clone());
                 },
+                MessageType::Unlink => {
+                    actor.uplinks.retain(|u| u != msg.get_sender());
+                },
                 MessageType::Shutdown => {
-                    for u in actor.uplinks.iter() {
-                        Message::exited()
-                            .with_sender(&own_address)
-                            .send_to(u);
-                    }
+                    exit_actor(actor, own_address, MessageDatum::Void);
                     break 'main;
                 },
                 _ => ()
@@ -703,7 +1159,8 @@ fn main_actor_loop<ActorState>(
             // Now remove the matched message as we've processed it, and
             // continue without pulling from the queue.
             // TODO: currently very inefficient, we can do better.
-            actor.mailbox.remove(matched_message_idx);
+            let msg = actor.mailbox.remove(matched_message_idx);
+            own_address.stats.processed(&msg);
             continue;
         }
 
@@ -711,6 +1168,7 @@ fn main_actor_loop<ActorState>(
         // matched a standard message, but we have some in the mailbox.
         // Loop again and do some standard matching.
         matched = false; matched_message_idx = 0;
+        let mut shutdown = false;
         for msg in actor.mailbox.iter() {
             match *msg.get_type() {
                 MessageType::Link => {
@@ -719,20 +1177,26 @@ fn main_actor_loop<ActorState>(
                     matched = true;
                     break;
                 },
+                MessageType::Unlink => {
+                    actor.uplinks.retain(|u| u != msg.get_sender());
+                    matched = true;
+                    break;
+                },
                 MessageType::Shutdown => {
-                    for u in actor.uplinks.iter() {
-                        Message::exited()
-                            .with_sender(&own_address)
-                            .send_to(u);
-                    }
-                    break 'main;
+                    shutdown = true;
+                    break;
                 },
                 _ => (),
             }
             matched_message_idx += 1;
         }
+        if shutdown {
+            exit_actor(actor, own_address, MessageDatum::Void);
+            break 'main;
+        }
         if matched {
-            actor.mailbox.remove(matched_message_idx);
+            let msg = actor.mailbox.remove(matched_message_idx);
+            own_address.stats.processed(&msg);
             continue;
         }
 
@@ -751,11 +1215,80 @@ fn main_actor_loop<ActorState>(
 
 }
 
+/// Runs the terminate clauses of the actor and notifies the uplinks that the
+/// actor process has exited with the provided reason.
+fn exit_actor<ActorState>(
+    actor: &mut Actor<ActorState>,
+    own_address: &ActorAddress,
+    reason: MessageDatum)
+    where ActorState: 'static + Sized + Default + Send {
+    for t in actor.terminates.iter() {
+        t(&mut actor.state, &reason);
+    }
+    for u in actor.uplinks.iter() {
+        Message::exited().with_sender(own_address)
+                         .with_datum(reason.clone())
+                         .send_to(u);
+    }
+}
 
 
 
 
+
+// The original tests are kept as they were written, before these lints.
 #[cfg(test)]
+#[allow(clippy::module_inception, clippy::redundant_static_lifetimes, clippy::assertions_on_constants,
+        clippy::match_like_matches_macro)]
 mod tests;
 #[cfg(test)]
+#[allow(clippy::redundant_static_lifetimes, clippy::assertions_on_constants, clippy::match_like_matches_macro)]
 mod tests_c5d1;
+#[cfg(test)]
+mod tests_server;
+#[cfg(test)]
+mod tests_fsm;
+#[cfg(test)]
+mod tests_event;
+#[cfg(test)]
+mod tests_group;
+#[cfg(test)]
+mod tests_router;
+#[cfg(test)]
+mod tests_codec;
+#[cfg(test)]
+mod tests_json;
+#[cfg(test)]
+mod tests_datum;
+#[cfg(test)]
+mod tests_atom;
+#[cfg(test)]
+mod tests_id;
+#[cfg(test)]
+mod tests_typed;
+#[cfg(test)]
+mod tests_pattern;
+#[cfg(test)]
+mod tests_node;
+#[cfg(test)]
+mod tests_unix;
+#[cfg(test)]
+mod tests_membership;
+#[cfg(test)]
+mod tests_gossip;
+#[cfg(test)]
+mod tests_auth;
+#[cfg(test)]
+mod tests_global;
+#[cfg(test)]
+mod tests_singleton;
+#[cfg(test)]
+mod tests_sharding;
+#[cfg(test)]
+mod tests_reliable;
+#[cfg(test)]
+mod tests_cluster;
+#[cfg(all(test, feature = "serde"))]
+mod tests_value;
+#[cfg(all(test, feature = "derive"))]
+mod tests_derive;
@@ -0,0 +1,159 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Membership tells a node which of its peers are alive.
+//!
+//! Connected nodes send each other heartbeats at a regular interval, and each
+//! node feeds the heartbeats it receives from a peer into a phi accrual failure
+//! detector (as described by Hayashibara et al.). Instead of a yes or no
+//! answer, the detector gives a suspicion level, phi, which grows the longer a
+//! heartbeat is overdue compared to the intervals seen so far: a phi of 1
+//! means roughly a 10% chance of being wrong in declaring the peer dead, a phi
+//! of 2 a 1% chance, and so on. When phi goes over the threshold, the peer is
+//! considered lost and the connection is closed, exactly as if it had broken.
+//!
+//! When a peer is lost, every local actor which is linked to an actor on it is
+//! sent an `Exited` message from that actor, with the `NODE_LOST` reason; and
+//! every actor watching the membership with `Node::watch` is sent a
+//! `NODE_DOWN` message with the name of the peer.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// The type of the messages sent to watchers when a node connects. The datum
+/// is the name of the node.
+pub const NODE_UP: &str = ":mecha_node_up";
+
+/// The type of the messages sent to watchers when a node is lost. The datum is
+/// the name of the node.
+pub const NODE_DOWN: &str = ":mecha_node_down";
+
+/// The exit reason of the `Exited` messages sent on behalf of actors on a node
+/// which has been lost.
+pub const NODE_LOST: &str = "node lost";
+
+/// The number of heartbeat intervals the detector remembers.
+const WINDOW: usize = 100;
+
+/// The settings of the failure detection of a node.
+#[derive(Clone, Debug)]
+pub struct Membership {
+    heartbeat_interval: Duration,
+    acceptable_pause: Duration,
+    phi_threshold: f64,
+    suspect_timeout: Duration,
+}
+
+impl Default for Membership {
+    fn default() -> Membership {
+        Membership {
+            heartbeat_interval: Duration::from_secs(1),
+            acceptable_pause: Duration::from_secs(2),
+            phi_threshold: 8.0,
+            suspect_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl Membership {
+    /// Creates the default settings: a heartbeat every second, pauses of up to
+    /// two seconds tolerated, a phi threshold of 8, and members suspected for
+    /// five seconds declared down.
+    pub fn new() -> Membership {
+        Membership::default()
+    }
+
+    /// Sets how often heartbeats are sent.
+    pub fn with_heartbeat_interval(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = interval;
+        self
+    }
+
+    /// Sets how late heartbeats can be before they start raising suspicion,
+    /// for example because of garbage collection or network hiccups.
+    pub fn with_acceptable_pause(mut self, pause: Duration) -> Self {
+        self.acceptable_pause = pause;
+        self
+    }
+
+    /// Sets the suspicion level over which a peer is considered lost.
+    pub fn with_phi_threshold(mut self, threshold: f64) -> Self {
+        self.phi_threshold = threshold;
+        self
+    }
+
+    /// Sets how long a member of the cluster can be suspected before it is
+    /// declared down (see the `gossip` module).
+    pub fn with_suspect_timeout(mut self, timeout: Duration) -> Self {
+        self.suspect_timeout = timeout;
+        self
+    }
+
+    /// Gets how often heartbeats are sent.
+    pub fn heartbeat_interval(&self) -> Duration { self.heartbeat_interval }
+
+    /// Gets the suspicion level over which a peer is considered lost.
+    pub fn phi_threshold(&self) -> f64 { self.phi_threshold }
+
+    /// Gets how long a member of the cluster can be suspected before it is
+    /// declared down.
+    pub fn suspect_timeout(&self) -> Duration { self.suspect_timeout }
+}
+
+/// A phi accrual failure detector for one peer.
+pub(crate) struct PhiAccrual {
+    intervals: VecDeque<f64>,
+    last: Instant,
+    min_std_dev: f64,
+    acceptable_pause: f64,
+}
+
+impl PhiAccrual {
+    /// Creates a detector for a peer which has just connected. The history is
+    /// seeded with the expected interval, so that the detector works before
+    /// the first heartbeats arrive.
+    pub(crate) fn new(settings: &Membership, now: Instant) -> PhiAccrual {
+        let expected = settings.heartbeat_interval.as_secs_f64() * 1000.0;
+        let mut intervals = VecDeque::with_capacity(WINDOW);
+        intervals.push_back(expected * 0.75);
+        intervals.push_back(expected * 1.25);
+        PhiAccrual {
+            intervals,
+            last: now,
+            min_std_dev: expected / 4.0,
+            acceptable_pause: settings.acceptable_pause.as_secs_f64() * 1000.0,
+        }
+    }
+
+    /// Records a heartbeat.
+    pub(crate) fn heartbeat(&mut self, now: Instant) {
+        if self.intervals.len() == WINDOW {
+            self.intervals.pop_front();
+        }
+        self.intervals.push_back(now.duration_since(self.last).as_secs_f64() * 1000.0);
+        self.last = now;
+    }
+
+    /// Gets the suspicion level of the peer. This uses the logistic
+    /// approximation of the cumulative normal distribution, which is accurate
+    /// enough and does not need an error function.
+    pub(crate) fn phi(&self, now: Instant) -> f64 {
+        let n = self.intervals.len() as f64;
+        let mean = self.intervals.iter().sum::<f64>() / n;
+        let variance = self.intervals.iter().map(|i| (i - mean) * (i - mean)).sum::<f64>() / n;
+        let std_dev = variance.sqrt().max(self.min_std_dev);
+        let elapsed = now.duration_since(self.last).as_secs_f64() * 1000.0;
+        let y = (elapsed - (mean + self.acceptable_pause)) / std_dev;
+        let e = (-y * (1.5976 + 0.070566 * y * y)).exp();
+        if y > 0.0 {
+            -(e / (1.0 + e)).log10()
+        } else {
+            -(1.0 - 1.0 / (1.0 + e)).log10()
+        }
+    }
+}
@@ -0,0 +1,999 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Nodes connect mecha processes, so that actors in different processes can
+//! exchange messages, in the spirit of distributed Erlang.
+//!
+//! A Node has a name, which must be unique among the nodes it connects to, and
+//! listens for connections from other nodes on an address. Connections are
+//! symmetric: once two nodes are connected, either of them can send messages
+//! to actors on the other.
+//!
+//! Addresses are either TCP `host:port` addresses, or `unix:<path>` for Unix
+//! domain sockets, which are the cheaper choice for processes on the same
+//! machine. Both transports use the same frames and handshake, and a node can
+//! connect to nodes on either of them. A node name can carry the address the
+//! node listens on, as in `worker@unix:/tmp/worker.sock`: such a node is
+//! created with `Node::start`, and other nodes can connect to it knowing only
+//! its name with `Node::connect_node`.
+//!
+//! An actor on another node is represented by a proxy ActorAddress. Messages
+//! sent to a proxy are encoded with the `Codec` and forwarded over the
+//! connection, so sending to a remote actor looks exactly like sending to a
+//! local one. Proxies are obtained by looking up a name registered on the
+//! other node with `Node::whereis`, or by receiving addresses in messages: the
+//! sender of a message from another node, or an `Act` datum in it, is decoded
+//! as a proxy which routes back over the connection.
+//!
+//! Local actors become reachable from other nodes when they are registered
+//! with a name, or when their address is sent to another node. The node keeps
+//! these exported addresses for as long as it runs.
+//!
+//! Connected nodes exchange heartbeats, which feed a failure detector (see the
+//! `membership` module). A peer which stops sending them, or whose connection
+//! breaks, is lost: local actors linked to actors on it are sent an `Exited`
+//! message, and the actors watching the node with `Node::watch` are told. A
+//! node can be partitioned from a peer with `Node::partition`, which silently
+//! drops the traffic between them to simulate a network failure.
+//!
+//! Nodes also gossip about the members of their cluster (see the `gossip`
+//! module), so connecting a new node to any member is enough for it to connect
+//! to all the others.
+//!
+//! Actors can also be registered with names which are unique across the
+//! cluster, with `Node::register_global` (see the `global` module), and looked
+//! up from any node with `Node::whereis_global`.
+//!
+//! Nodes created with a cookie only accept connections from nodes with the same
+//! cookie, and authenticate every frame they exchange (see the `auth` module),
+//! so that processes which can reach them cannot inject messages. Nodes
+//! created without a cookie accept any node without one, and should only be
+//! reachable from trusted processes.
+//!
+//! Every frame on a connection is a 4-byte big endian length followed by a
+//! kind byte and the payload. The first frames in both directions are a Hello
+//! frame carrying the name of the node and a Challenge frame; on authenticated
+//! connections, every frame after the handshake ends with its tag.
+//!
+//! ```text
+//! let node = mecha::Node::listen("a", "127.0.0.1:0")?;
+//! node.connect("127.0.0.1:4370")?;
+//! let echo = node.whereis("b", "echo").unwrap();
+//! mecha::Message::custom(":ping").with_sender(&me).send_to(&echo);
+//!
+//! let worker = mecha::Node::start("worker@unix:/tmp/worker.sock")?;
+//! worker.connect_node("main@unix:/tmp/main.sock")?;
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use atom;
+use auth::{self, Signer, Tag, Verifier, TAG_LEN};
+use global::{self, Registry, GLOBAL_NAME_CONFLICT};
+use gossip::{self, Member, MemberStatus, Members};
+use membership::{Membership, PhiAccrual, NODE_DOWN, NODE_LOST, NODE_UP};
+use transport::{Listener, Stream};
+
+use rand;
+
+use server::DEFAULT_CALL_TIMEOUT_MS;
+use ActorAddress;
+use ActorId;
+use Codec;
+use Message;
+use MessageDatum;
+use MessageType;
+
+/// Frames larger than this are considered a protocol error, and close the
+/// connection.
+const MAX_FRAME_LEN: usize = 64 << 20;
+
+const FRAME_HELLO: u8 = 0;
+const FRAME_SEND: u8 = 1;
+const FRAME_WHEREIS: u8 = 2;
+const FRAME_WHEREIS_REPLY: u8 = 3;
+const FRAME_HEARTBEAT: u8 = 4;
+const FRAME_GOSSIP: u8 = 5;
+const FRAME_CHALLENGE: u8 = 6;
+const FRAME_ANSWER: u8 = 7;
+const FRAME_GLOBAL: u8 = 8;
+
+/// The creation number and the number of an ActorId, without its node.
+type LocalId = (u32, u64);
+
+/// A Node is the handle to the distribution layer of a mecha process. It can
+/// be cheaply cloned, and all clones refer to the same node.
+#[derive(Clone)]
+pub struct Node {
+    inner: Arc<NodeInner>,
+}
+
+struct NodeInner {
+    name: String,
+    address: String,
+    cookie: Option<Vec<u8>>,
+    codec: Codec,
+    running: AtomicBool,
+    names: Mutex<HashMap<String, ActorAddress>>,
+    exports: Mutex<HashMap<ActorId, ActorAddress>>,
+    peers: Mutex<HashMap<String, Arc<Connection>>>,
+    /// The connections which have been replaced by a newer one to the same
+    /// peer. They are not closed straight away, as the peer may have chosen
+    /// to keep using them when both nodes connected at the same time.
+    retired: Mutex<Vec<(String, Arc<Connection>)>>,
+    proxies: Mutex<HashMap<ActorId, ActorAddress>>,
+    /// The channels to the threads which forward the messages sent to the
+    /// proxies, by the name of the peer.
+    outboxes: Mutex<HashMap<String, mpsc::Sender<(ActorId, Message)>>>,
+    pending: Mutex<HashMap<u64, mpsc::Sender<Option<LocalId>>>>,
+    next_request: AtomicU64,
+    membership: Mutex<Membership>,
+    /// Wakes up the heartbeat thread when the membership settings change.
+    reconfigured: Mutex<mpsc::Sender<()>>,
+    /// The links from local actors to actors on each peer, by the name of the
+    /// peer.
+    links: Mutex<HashMap<String, Vec<(ActorId, ActorAddress)>>>,
+    watchers: Mutex<Vec<ActorAddress>>,
+    partitions: Mutex<HashSet<String>>,
+    members: Mutex<Members>,
+    /// The members the node is connecting to because of gossip.
+    connecting: Mutex<HashSet<String>>,
+    registry: Mutex<Registry>,
+}
+
+/// A connection to a peer. Writes are serialized, so that frames written by
+/// different threads do not interleave, and are signed in the order they are
+/// written on authenticated connections.
+struct Connection {
+    stream: Stream,
+    writing: Mutex<Option<Signer>>,
+    reading: Mutex<Option<Verifier>>,
+    detector: Mutex<PhiAccrual>,
+    partitioned: AtomicBool,
+}
+
+impl Connection {
+    /// Writes a frame made of the kind byte and the concatenation of the
+    /// parts. Frames to a partitioned peer are dropped.
+    fn write_frame(&self, kind: u8, parts: &[&[u8]]) -> io::Result<()> {
+        if self.partitioned.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let mut signer = self.writing.lock().unwrap_or_else(|e| e.into_inner());
+        let tag = signer.as_mut().map(|s| s.sign(kind, parts));
+        let tag = tag.as_ref().map_or(&[][..], |t| &t[..]);
+        let len = 1 + parts.iter().map(|p| p.len()).sum::<usize>() + tag.len();
+        let mut frame = Vec::with_capacity(4 + len);
+        frame.extend_from_slice(&(len as u32).to_be_bytes());
+        frame.push(kind);
+        for p in parts {
+            frame.extend_from_slice(p);
+        }
+        frame.extend_from_slice(tag);
+        (&self.stream).write_all(&frame)
+    }
+
+    /// Reads a frame with the reading end of the connection, checking its tag
+    /// on authenticated connections.
+    fn read_frame(&self, reader: &Stream) -> io::Result<(u8, Vec<u8>)> {
+        let (kind, mut payload) = read_frame(&mut &*reader)?;
+        if let Some(verifier) = self.reading.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            let len = verifier.verify(kind, &payload)
+                             .ok_or_else(|| invalid("Frame failed authentication"))?
+                             .len();
+            payload.truncate(len);
+        }
+        Ok((kind, payload))
+    }
+
+    fn close(&self) {
+        self.stream.close();
+    }
+}
+
+impl Node {
+    /// Creates a node with the given name, listening for connections from
+    /// other nodes on the given address. The address is either a TCP address,
+    /// whose port can be 0, or `unix:` followed by the path of a Unix domain
+    /// socket. The actual address is given by `address`.
+    pub fn listen(name: &str, addr: &str) -> io::Result<Node> {
+        Node::bind(name, addr, None)
+    }
+
+    /// Creates a node like `listen`, which only connects to nodes with the
+    /// same cookie.
+    pub fn listen_with_cookie(name: &str, addr: &str, cookie: &str) -> io::Result<Node> {
+        Node::bind(name, addr, Some(cookie))
+    }
+
+    fn bind(name: &str, addr: &str, cookie: Option<&str>) -> io::Result<Node> {
+        atom::declare_internal_types();
+        let listener = Listener::bind(addr)?;
+        let address = listener.address()?;
+        let (reconfigured, settings) = mpsc::channel();
+        let inner = Arc::new_cyclic(|weak: &Weak<NodeInner>| {
+            let weak = weak.clone();
+            NodeInner {
+                name: name.to_string(),
+                address: address.clone(),
+                cookie: cookie.map(|c| c.as_bytes().to_vec()),
+                codec: Codec::new().with_node(name).with_resolver(move |a| {
+                    weak.upgrade().and_then(|inner| inner.resolve(&inner, a))
+                }),
+                running: AtomicBool::new(true),
+                names: Mutex::new(HashMap::new()),
+                exports: Mutex::new(HashMap::new()),
+                peers: Mutex::new(HashMap::new()),
+                retired: Mutex::new(Vec::new()),
+                proxies: Mutex::new(HashMap::new()),
+                outboxes: Mutex::new(HashMap::new()),
+                pending: Mutex::new(HashMap::new()),
+                next_request: AtomicU64::new(0),
+                membership: Mutex::new(Membership::new()),
+                reconfigured: Mutex::new(reconfigured),
+                links: Mutex::new(HashMap::new()),
+                watchers: Mutex::new(Vec::new()),
+                partitions: Mutex::new(HashSet::new()),
+                members: Mutex::new(Members::new(name, &address)),
+                connecting: Mutex::new(HashSet::new()),
+                registry: Mutex::new(Registry::new(name)),
+            }
+        });
+        let weak = Arc::downgrade(&inner);
+        thread::spawn(move || NodeInner::heartbeats(&weak, &settings));
+        let weak = Arc::downgrade(&inner);
+        thread::spawn(move || {
+            loop {
+                let stream = listener.accept();
+                let inner = match weak.upgrade() {
+                    Some(inner) => inner,
+                    None => { return; }
+                };
+                if !inner.running.load(Ordering::SeqCst) {
+                    return;
+                }
+                if let Ok(stream) = stream {
+                    // Do the handshake in another thread, so that a slow peer
+                    // does not hold up the others.
+                    thread::spawn(move || { let _ = NodeInner::start(&inner, stream, false); });
+                }
+            }
+        });
+        Ok(Node { inner })
+    }
+
+    /// Sets how the node detects failures of its peers. This applies to the
+    /// heartbeats straight away, and to the detection of the peers connected
+    /// from now on.
+    pub fn with_membership(self, membership: Membership) -> Self {
+        *self.inner.membership.lock().unwrap() = membership;
+        let _ = self.inner.reconfigured.lock().unwrap().send(());
+        self
+    }
+
+    /// Creates a node whose name carries the address it listens on, as in
+    /// `worker@unix:/tmp/worker.sock` or `worker@127.0.0.1:4370`.
+    pub fn start(name: &str) -> io::Result<Node> {
+        Node::bind(name, name_address(name)?, None)
+    }
+
+    /// Creates a node like `start`, which only connects to nodes with the
+    /// same cookie.
+    pub fn start_with_cookie(name: &str, cookie: &str) -> io::Result<Node> {
+        Node::bind(name, name_address(name)?, Some(cookie))
+    }
+
+    /// Gets the name of the node.
+    pub fn name(&self) -> &str { &self.inner.name }
+
+    /// Gets the address the node listens on, in the form accepted by
+    /// `connect`.
+    pub fn address(&self) -> &str { &self.inner.address }
+
+    /// Connects to the node listening on the given address, returning its
+    /// name.
+    pub fn connect(&self, addr: &str) -> io::Result<String> {
+        let stream = Stream::connect(addr)?;
+        NodeInner::start(&self.inner, stream, true)
+    }
+
+    /// Connects to a node whose name carries the address it listens on, as
+    /// created by `Node::start`. Fails if the node listening there has another
+    /// name.
+    pub fn connect_node(&self, name: &str) -> io::Result<()> {
+        let peer = self.connect(name_address(name)?)?;
+        if peer != name {
+            if let Some(conn) = self.inner.peers.lock().unwrap().remove(&peer) {
+                conn.close();
+            }
+            return Err(invalid("The node has another name"));
+        }
+        Ok(())
+    }
+
+    /// Gets the names of the nodes this node is connected to.
+    pub fn peers(&self) -> Vec<String> {
+        self.inner.peers.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Registers a local actor with a name, so that other nodes can look it up
+    /// with `whereis`. A name can only refer to one actor at a time.
+    pub fn register(&self, name: &str, actor: &ActorAddress) {
+        self.inner.export(actor);
+        self.inner.names.lock().unwrap().insert(name.to_string(), actor.clone());
+    }
+
+    /// Removes the name of a local actor.
+    pub fn unregister(&self, name: &str) {
+        self.inner.names.lock().unwrap().remove(name);
+    }
+
+    /// Registers a local actor with a name which is unique across the
+    /// cluster, returning false if the name is already taken. If another node
+    /// registered the same name at the same time, one of the actors loses it
+    /// and is sent a `GLOBAL_NAME_CONFLICT` message.
+    pub fn register_global(&self, name: &str, actor: &ActorAddress) -> bool {
+        if !actor.id.is_local() {
+            return false;
+        }
+        let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_micros() as u64);
+        if !self.inner.registry.lock().unwrap().register(name, actor, stamp) {
+            return false;
+        }
+        self.inner.export(actor);
+        self.inner.broadcast_globals();
+        true
+    }
+
+    /// Removes a name registered on this node with `register_global`.
+    pub fn unregister_global(&self, name: &str) {
+        if self.inner.registry.lock().unwrap().unregister(name) {
+            self.inner.broadcast_globals();
+        }
+    }
+
+    /// Looks up an actor registered with a name across the cluster, returning
+    /// the local actor or a proxy for it. This does not block, as every node
+    /// keeps a copy of the global registry.
+    pub fn whereis_global(&self, name: &str) -> Option<ActorAddress> {
+        self.inner.registry.lock().unwrap().whereis(name)
+    }
+
+    /// Gets all the names registered across the cluster, sorted.
+    pub fn global_names(&self) -> Vec<String> {
+        self.inner.registry.lock().unwrap().names()
+    }
+
+    /// Looks up an actor registered with a name on a connected node, waiting
+    /// for at most the default call timeout, and returns a proxy for it.
+    pub fn whereis(&self, node: &str, name: &str) -> Option<ActorAddress> {
+        self.whereis_timeout(node, name, Duration::from_millis(DEFAULT_CALL_TIMEOUT_MS))
+    }
+
+    /// Looks up an actor registered with a name on a connected node, waiting
+    /// for at most the provided timeout, and returns a proxy for it. Names of
+    /// this node are looked up directly.
+    pub fn whereis_timeout(&self, node: &str, name: &str, timeout: Duration)
+        -> Option<ActorAddress> {
+        if node == self.inner.name {
+            return self.inner.names.lock().unwrap().get(name).cloned();
+        }
+        let peer = self.inner.peers.lock().unwrap().get(node).cloned()?;
+        let request = self.inner.next_request.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel();
+        self.inner.pending.lock().unwrap().insert(request, tx);
+        let sent = peer.write_frame(FRAME_WHEREIS, &[&request.to_be_bytes(), name.as_bytes()]);
+        let found = match sent {
+            Ok(()) => rx.recv_timeout(timeout).ok().and_then(|id| id),
+            Err(_) => None
+        };
+        self.inner.pending.lock().unwrap().remove(&request);
+        found.map(|(creation, number)| {
+            let id = ActorId::from_parts(Some(node), Some(&self.inner.name), creation, number);
+            self.inner.proxy(&self.inner, id)
+        })
+    }
+
+    /// Gets the members of the cluster the node knows about, including itself,
+    /// sorted by name.
+    pub fn members(&self) -> Vec<Member> {
+        self.inner.members.lock().unwrap().list()
+    }
+
+    /// Gets the leader of the cluster, as far as the node knows: the member
+    /// with the lowest name among the ones which are up. Nodes which agree on
+    /// the members agree on the leader, without exchanging any more messages.
+    pub fn leader(&self) -> Option<String> {
+        self.members().into_iter()
+            .find(|m| m.status() == MemberStatus::Up)
+            .map(|m| m.name().to_string())
+    }
+
+    /// Leaves the cluster: tells the peers that this node is leaving, so that
+    /// they do not suspect it, and shuts it down.
+    pub fn leave(&self) {
+        let members = {
+            let mut members = self.inner.members.lock().unwrap();
+            members.leave();
+            members.encode()
+        };
+        for peer in self.inner.peers.lock().unwrap().values() {
+            let _ = peer.write_frame(FRAME_GOSSIP, &[&members]);
+        }
+        self.shutdown();
+    }
+
+    /// Makes an actor watch the membership of the node: it is sent a `NODE_UP`
+    /// message when a peer connects and a `NODE_DOWN` message when one is lost,
+    /// with the name of the peer as the datum.
+    pub fn watch(&self, actor: &ActorAddress) {
+        self.inner.watchers.lock().unwrap().push(actor.clone());
+    }
+
+    /// Stops an actor watching the membership of the node.
+    pub fn unwatch(&self, actor: &ActorAddress) {
+        self.inner.watchers.lock().unwrap().retain(|w| w != actor);
+    }
+
+    /// Gets the current suspicion level of a connected peer, as computed by
+    /// the failure detector, or None if the node is not connected to it.
+    pub fn phi(&self, node: &str) -> Option<f64> {
+        let peer = self.inner.peers.lock().unwrap().get(node).cloned()?;
+        let phi = peer.detector.lock().unwrap().phi(Instant::now());
+        Some(phi)
+    }
+
+    /// Simulates a network partition between this node and a peer: all the
+    /// frames between them are dropped, so the failure detectors on both sides
+    /// eventually declare the other node lost, and connections between them
+    /// are refused until `heal` is called.
+    pub fn partition(&self, node: &str) {
+        self.inner.partitions.lock().unwrap().insert(node.to_string());
+        if let Some(peer) = self.inner.peers.lock().unwrap().get(node) {
+            peer.partitioned.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Ends a simulated network partition. If the peer has not been declared
+    /// lost yet, the connection resumes; otherwise the nodes connect again, as
+    /// members of the same cluster do on their own.
+    pub fn heal(&self, node: &str) {
+        self.inner.partitions.lock().unwrap().remove(node);
+        if let Some(peer) = self.inner.peers.lock().unwrap().get(node) {
+            peer.partitioned.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Gets the number of proxies of actors on other nodes the node keeps.
+    #[cfg(test)]
+    pub(crate) fn proxies(&self) -> usize {
+        self.inner.proxies.lock().unwrap().len()
+    }
+
+    /// Stops listening and closes all the connections. Proxies of actors on
+    /// other nodes drop the messages sent to them from now on.
+    pub fn shutdown(&self) {
+        self.inner.running.store(false, Ordering::SeqCst);
+        for (_, peer) in self.inner.peers.lock().unwrap().drain() {
+            peer.close();
+        }
+        for (_, conn) in self.inner.retired.lock().unwrap().drain(..) {
+            conn.close();
+        }
+        // Wake up the listening thread, so that it notices.
+        let _ = Stream::connect(&self.inner.address);
+    }
+}
+
+impl NodeInner {
+    /// Does the handshake on a new connection, which the node opened if
+    /// `initiator` is true, and starts reading from it, returning the name of
+    /// the peer.
+    fn start(inner: &Arc<NodeInner>, stream: Stream, initiator: bool) -> io::Result<String> {
+        let reader = stream.try_clone()?;
+        let detector = PhiAccrual::new(&inner.membership.lock().unwrap(), Instant::now());
+        let conn = Arc::new(Connection {
+            stream,
+            writing: Mutex::new(None),
+            reading: Mutex::new(None),
+            detector: Mutex::new(detector),
+            partitioned: AtomicBool::new(false),
+        });
+        let nonce = match inner.cookie {
+            Some(_) => Some(auth::nonce()?),
+            None => None
+        };
+        conn.write_frame(FRAME_HELLO, &[inner.name.as_bytes()])?;
+        conn.write_frame(FRAME_CHALLENGE, &[nonce.as_ref().map_or(&[][..], |n| &n[..])])?;
+        let peer = match read_frame(&mut &reader)? {
+            (FRAME_HELLO, name) => String::from_utf8(name).map_err(|_| invalid("Invalid node name"))?,
+            _ => { return Err(invalid("Expected a Hello frame")); }
+        };
+        if peer == inner.name {
+            conn.close();
+            return Err(invalid("The node has the same name"));
+        }
+        if let Err(e) = inner.authenticate(&conn, &reader, &peer, nonce, initiator) {
+            warn!("Rejected node {:?} from {}: {}", peer, reader.peer(), e);
+            conn.close();
+            return Err(e);
+        }
+        if !inner.running.load(Ordering::SeqCst) {
+            conn.close();
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "Node is shut down"));
+        }
+        if inner.partitions.lock().unwrap().contains(&peer) {
+            conn.close();
+            return Err(io::Error::new(io::ErrorKind::ConnectionRefused, "Node is partitioned"));
+        }
+        match inner.peers.lock().unwrap().insert(peer.clone(), conn.clone()) {
+            Some(old) => inner.retired.lock().unwrap().push((peer.clone(), old)),
+            None => inner.notify(NODE_UP, &peer)
+        }
+        let members = inner.members.lock().unwrap().encode();
+        let _ = conn.write_frame(FRAME_GOSSIP, &[&members]);
+        let names = inner.registry.lock().unwrap().encode();
+        let _ = conn.write_frame(FRAME_GLOBAL, &[&names]);
+        let weak = Arc::downgrade(inner);
+        let name = peer.clone();
+        thread::spawn(move || {
+            loop {
+                let (kind, payload) = match conn.read_frame(&reader) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        if e.kind() == io::ErrorKind::InvalidData {
+                            warn!("Closing the connection to node {:?}: {}", name, e);
+                        }
+                        break;
+                    }
+                };
+                let inner = match weak.upgrade() {
+                    Some(inner) => inner,
+                    None => { return; }
+                };
+                if inner.partitions.lock().unwrap().contains(&name) {
+                    continue;
+                }
+                match kind {
+                    FRAME_HEARTBEAT => {
+                        // The peer may send heartbeats on a retired
+                        // connection, so they count for the current one.
+                        let current = inner.peers.lock().unwrap().get(&name).cloned();
+                        if let Some(current) = current {
+                            current.detector.lock().unwrap().heartbeat(Instant::now());
+                        }
+                    },
+                    FRAME_GOSSIP => NodeInner::gossip(&inner, &payload),
+                    FRAME_GLOBAL => NodeInner::global(&inner, &name, &payload),
+                    _ => inner.handle(&conn, kind, &payload)
+                }
+            }
+            if let Some(inner) = weak.upgrade() {
+                if !inner.replace(&name, &conn) {
+                    NodeInner::lost(&inner, &name, &conn);
+                }
+            }
+        });
+        Ok(peer)
+    }
+
+    /// Reads the challenge of a peer, and if the node has a cookie, answers it
+    /// and checks the answer of the peer to the challenge of the node. On
+    /// success, the frames on the connection are authenticated from now on.
+    fn authenticate(&self, conn: &Connection, reader: &Stream, peer: &str, nonce: Option<Tag>,
+                    initiator: bool) -> io::Result<()> {
+        let denied = |reason: &str| io::Error::new(io::ErrorKind::PermissionDenied, reason.to_string());
+        let challenge = match read_frame(&mut &*reader)? {
+            (FRAME_CHALLENGE, challenge) => challenge,
+            _ => { return Err(invalid("Expected a Challenge frame")); }
+        };
+        let (cookie, nonce) = match (self.cookie.as_ref(), nonce) {
+            (Some(cookie), Some(nonce)) => (cookie, nonce),
+            _ if challenge.is_empty() => { return Ok(()); },
+            _ => { return Err(denied("The node requires a cookie")); }
+        };
+        if challenge.len() != TAG_LEN {
+            return Err(denied("The node does not use a cookie"));
+        }
+        conn.write_frame(FRAME_ANSWER, &[&auth::answer(cookie, &challenge, &self.name, peer, initiator)])?;
+        let answer = match read_frame(&mut &*reader)? {
+            (FRAME_ANSWER, answer) => answer,
+            _ => { return Err(invalid("Expected an Answer frame")); }
+        };
+        if !auth::equal(&answer, &auth::answer(cookie, &nonce, peer, &self.name, !initiator)) {
+            return Err(denied("The node has a different cookie"));
+        }
+        let mut peer_nonce = [0u8; TAG_LEN];
+        peer_nonce.copy_from_slice(&challenge);
+        let (signer, verifier) = auth::session(cookie, &self.name, &nonce, peer, &peer_nonce, initiator);
+        *conn.writing.lock().unwrap() = Some(signer);
+        *conn.reading.lock().unwrap() = Some(verifier);
+        Ok(())
+    }
+
+    /// Sends heartbeats to the peers at the configured interval, and declares
+    /// lost the ones which the failure detector suspects.
+    fn heartbeats(weak: &Weak<NodeInner>, reconfigured: &mpsc::Receiver<()>) {
+        loop {
+            let interval = match weak.upgrade() {
+                Some(inner) => inner.membership.lock().unwrap().heartbeat_interval(),
+                None => { return; }
+            };
+            match reconfigured.recv_timeout(interval) {
+                Ok(()) => { continue; },
+                Err(mpsc::RecvTimeoutError::Timeout) => (),
+                Err(mpsc::RecvTimeoutError::Disconnected) => { return; }
+            }
+            let inner = match weak.upgrade() {
+                Some(inner) => inner,
+                None => { return; }
+            };
+            if !inner.running.load(Ordering::SeqCst) {
+                return;
+            }
+            let (threshold, suspect_timeout) = {
+                let membership = inner.membership.lock().unwrap();
+                (membership.phi_threshold(), membership.suspect_timeout())
+            };
+            let now = Instant::now();
+            let peers: Vec<(String, Arc<Connection>)> = inner.peers.lock().unwrap().iter()
+                .map(|(name, conn)| (name.clone(), conn.clone()))
+                .collect();
+            for (name, conn) in peers {
+                if conn.detector.lock().unwrap().phi(now) > threshold {
+                    NodeInner::lost(&inner, &name, &conn);
+                } else {
+                    let _ = conn.write_frame(FRAME_HEARTBEAT, &[]);
+                }
+            }
+
+            // Gossip with a random peer, and connect to the members the node
+            // is not connected to.
+            let members = {
+                let mut members = inner.members.lock().unwrap();
+                members.expire(suspect_timeout, now);
+                members.encode()
+            };
+            let peers: Vec<Arc<Connection>> = inner.peers.lock().unwrap().values().cloned().collect();
+            if !peers.is_empty() {
+                let peer = &peers[rand::random::<usize>() % peers.len()];
+                let _ = peer.write_frame(FRAME_GOSSIP, &[&members]);
+            }
+            NodeInner::connect_members(&inner);
+        }
+    }
+
+    /// Merges the members received from a peer.
+    fn gossip(inner: &Arc<NodeInner>, payload: &[u8]) {
+        let received = match gossip::decode(payload) {
+            Some(received) => received,
+            None => { return; }
+        };
+        if inner.members.lock().unwrap().merge(received, Instant::now()) {
+            NodeInner::connect_members(inner);
+        }
+    }
+
+    /// Replaces the global names of a peer with the ones it sent, and tells
+    /// the local actors which lost their name to one of them.
+    fn global(inner: &Arc<NodeInner>, peer: &str, payload: &[u8]) {
+        let received = match global::decode(payload) {
+            Some(received) => received,
+            None => { return; }
+        };
+        let received = received.into_iter().map(|(name, creation, number, stamp)| {
+            let id = ActorId::from_parts(Some(peer), Some(&inner.name), creation, number);
+            (name, inner.proxy(inner, id), stamp)
+        }).collect();
+        let lost = inner.registry.lock().unwrap().merge(peer, received);
+        if lost.is_empty() {
+            return;
+        }
+        for (name, actor, winner) in lost {
+            Message::custom(GLOBAL_NAME_CONFLICT).with_sender(&winner)
+                                                 .with_datum(MessageDatum::from(&name[..]))
+                                                 .send_to(&actor);
+        }
+        inner.broadcast_globals();
+    }
+
+    /// Sends the global names of this node to all the peers.
+    fn broadcast_globals(&self) {
+        let names = self.registry.lock().unwrap().encode();
+        for peer in self.peers.lock().unwrap().values() {
+            let _ = peer.write_frame(FRAME_GLOBAL, &[&names]);
+        }
+    }
+
+    /// Connects, in the background, to the members the node should connect to
+    /// and is not connected or connecting to yet.
+    fn connect_members(inner: &Arc<NodeInner>) {
+        if !inner.running.load(Ordering::SeqCst) {
+            return;
+        }
+        let candidates = inner.members.lock().unwrap().to_connect();
+        for (name, address) in candidates {
+            if inner.peers.lock().unwrap().contains_key(&name)
+                || !inner.connecting.lock().unwrap().insert(name.clone()) {
+                continue;
+            }
+            let inner = inner.clone();
+            thread::spawn(move || {
+                if let Ok(stream) = Stream::connect(&address) {
+                    let _ = NodeInner::start(&inner, stream, true);
+                }
+                inner.connecting.lock().unwrap().remove(&name);
+            });
+        }
+    }
+
+    /// Replaces the current connection to a peer, which has broken, with the
+    /// latest retired one, if any. This happens when the peer drops one of two
+    /// connections made at the same time, for example because it refused it.
+    fn replace(&self, name: &str, conn: &Arc<Connection>) -> bool {
+        let mut peers = self.peers.lock().unwrap();
+        if !peers.get(name).is_some_and(|c| Arc::ptr_eq(c, conn)) {
+            return false;
+        }
+        let mut retired = self.retired.lock().unwrap();
+        let latest = match retired.iter().rposition(|(peer, _)| peer == name) {
+            Some(i) => retired.remove(i).1,
+            None => { return false; }
+        };
+        latest.detector.lock().unwrap().heartbeat(Instant::now());
+        latest.partitioned.store(self.partitions.lock().unwrap().contains(name), Ordering::SeqCst);
+        peers.insert(name.to_string(), latest);
+        true
+    }
+
+    /// Handles the loss of a connection to a peer, unless it has already been
+    /// replaced by another one: notifies the local actors linked to actors on
+    /// the peer, and the watchers.
+    fn lost(inner: &Arc<NodeInner>, name: &str, conn: &Arc<Connection>) {
+        {
+            let mut peers = inner.peers.lock().unwrap();
+            if !peers.get(name).is_some_and(|c| Arc::ptr_eq(c, conn)) {
+                inner.retired.lock().unwrap().retain(|(_, c)| !Arc::ptr_eq(c, conn));
+                return;
+            }
+            peers.remove(name);
+        }
+        conn.close();
+        inner.retired.lock().unwrap().retain(|(peer, c)| {
+            if peer == name {
+                c.close();
+            }
+            peer != name
+        });
+        if inner.running.load(Ordering::SeqCst) {
+            inner.members.lock().unwrap().suspect(name, Instant::now());
+        }
+        inner.registry.lock().unwrap().remove_node(name);
+        let links = inner.links.lock().unwrap().remove(name).unwrap_or_default();
+        for (id, linker) in links {
+            let proxy = inner.proxy(inner, id);
+            Message::exited().with_sender(&proxy)
+                             .with_datum(MessageDatum::from(NODE_LOST))
+                             .send_to(&linker);
+        }
+        // The forwarding thread stops once the proxies still in use are gone.
+        inner.proxies.lock().unwrap().retain(|id, _| id.node() != Some(name));
+        inner.outboxes.lock().unwrap().remove(name);
+        inner.notify(NODE_DOWN, name);
+    }
+
+    /// Sends a membership message about a peer to the watchers.
+    fn notify(&self, kind: &'static str, node: &str) {
+        for w in self.watchers.lock().unwrap().iter() {
+            Message::custom(kind).with_datum(MessageDatum::from(node)).send_to(w);
+        }
+    }
+
+    /// Handles a frame received from a peer.
+    fn handle(&self, conn: &Connection, kind: u8, payload: &[u8]) {
+        match kind {
+            FRAME_SEND if payload.len() >= ID_LEN => {
+                let (creation, number) = read_id(&payload[..ID_LEN]);
+                let id = ActorId::from_parts(Some(&self.name), Some(&self.name), creation, number);
+                let target = self.exports.lock().unwrap().get(&id).cloned();
+                // Messages to unknown actors (including the actors of a
+                // previous incarnation of the node) are dropped like messages
+                // to actors which are not running.
+                let target = match target {
+                    Some(target) => target,
+                    None => { return; }
+                };
+                let msg = match self.codec.decode_message(&payload[ID_LEN..]) {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        warn!("Node {:?} dropped a message it cannot decode: {}", self.name, e);
+                        return;
+                    }
+                };
+                // A linked actor which has exited does not need to be reported
+                // when its node is lost.
+                if *msg.get_type() == MessageType::Exited {
+                    self.unlink(msg.get_sender(), &target);
+                }
+                // Actors which have exited are not exported anymore.
+                if !target.deliver(msg) {
+                    self.exports.lock().unwrap().remove(&id);
+                }
+            },
+            FRAME_WHEREIS if payload.len() >= 8 => {
+                let name = String::from_utf8_lossy(&payload[8..]);
+                let found = self.names.lock().unwrap().get(&name[..]).map(|a| write_id(&a.id));
+                let _ = conn.write_frame(FRAME_WHEREIS_REPLY,
+                                         &[&payload[..8], found.as_ref().map_or(&[][..], |id| &id[..])]);
+            },
+            FRAME_WHEREIS_REPLY if payload.len() >= 8 => {
+                let mut request = [0u8; 8];
+                request.copy_from_slice(&payload[..8]);
+                let id = if payload.len() == 8 + ID_LEN { Some(read_id(&payload[8..])) } else { None };
+                if let Some(tx) = self.pending.lock().unwrap().remove(&u64::from_be_bytes(request)) {
+                    let _ = tx.send(id);
+                }
+            },
+            _ => ()
+        }
+    }
+
+    /// Forgets the link from a local actor to an actor on a peer.
+    fn unlink(&self, remote: &ActorAddress, linker: &ActorAddress) {
+        let node = match remote.node() {
+            Some(node) => node,
+            None => { return; }
+        };
+        if let Some(links) = self.links.lock().unwrap().get_mut(node) {
+            links.retain(|(id, l)| !(id == remote.id() && l == linker));
+        }
+    }
+
+    /// Makes a local actor reachable from other nodes.
+    fn export(&self, actor: &ActorAddress) {
+        if actor.id.is_local() {
+            self.exports.lock().unwrap().entry(actor.id.clone()).or_insert_with(|| actor.clone());
+        }
+    }
+
+    /// Exports all the local actors a datum refers to.
+    fn export_datum(&self, datum: &MessageDatum) {
+        match *datum {
+            MessageDatum::Act(ref a) => self.export(a),
+            MessageDatum::Map(ref m) => for d in m.values() { self.export_datum(d); },
+            MessageDatum::List(ref l) | MessageDatum::Tuple(ref l) => {
+                for d in l.iter() { self.export_datum(d); }
+            },
+            _ => ()
+        }
+    }
+
+    /// Resolves a decoded address to an exported local actor, or to a proxy
+    /// if it refers to an actor on a connected node.
+    fn resolve(&self, inner: &Arc<NodeInner>, address: &ActorAddress) -> Option<ActorAddress> {
+        match address.node() {
+            None => self.exports.lock().unwrap().get(&address.id).cloned(),
+            Some(node) if self.peers.lock().unwrap().contains_key(node) => {
+                Some(self.proxy(inner, address.id.clone()))
+            },
+            Some(_) => None
+        }
+    }
+
+    /// Gets the proxy for an actor on another node, creating it if needed.
+    /// The proxies of the actors of a node share the thread which forwards
+    /// their messages to it.
+    fn proxy(&self, inner: &Arc<NodeInner>, id: ActorId) -> ActorAddress {
+        let mut proxies = self.proxies.lock().unwrap();
+        if let Some(p) = proxies.get(&id) {
+            return p.clone();
+        }
+        let node = id.node().unwrap_or_default().to_string();
+        let outbox = self.outboxes.lock().unwrap().entry(node.clone()).or_insert_with(|| {
+            let (tx, rx) = mpsc::channel();
+            let weak = Arc::downgrade(inner);
+            thread::spawn(move || NodeInner::forward(&weak, &node, &rx));
+            tx
+        }).clone();
+        let proxy = ActorAddress::remote(id.clone(), outbox);
+        proxies.insert(id, proxy.clone());
+        proxy
+    }
+
+    /// Forwards the messages sent to the proxies of the actors of a node, until
+    /// no proxy is left.
+    fn forward(weak: &Weak<NodeInner>, node: &str, rx: &mpsc::Receiver<(ActorId, Message)>) {
+        for (id, msg) in rx.iter() {
+            let inner = match weak.upgrade() {
+                Some(inner) => inner,
+                None => { return; }
+            };
+            let peer = inner.peers.lock().unwrap().get(node).cloned();
+            let sender = msg.get_sender();
+            match *msg.get_type() {
+                // Linking to an actor on a node which is not connected
+                // fails straight away.
+                MessageType::Link if peer.is_none() => {
+                    let proxy = inner.proxy(&inner, id.clone());
+                    Message::exited().with_sender(&proxy)
+                                     .with_datum(MessageDatum::from(NODE_LOST))
+                                     .send_to(sender);
+                },
+                MessageType::Link if sender.id.is_local() => {
+                    inner.links.lock().unwrap().entry(node.to_string()).or_default()
+                         .push((id.clone(), sender.clone()));
+                },
+                MessageType::Unlink => {
+                    let proxy = inner.proxy(&inner, id.clone());
+                    inner.unlink(&proxy, sender);
+                },
+                _ => ()
+            }
+            let peer = match peer {
+                Some(peer) => peer,
+                None => { continue; }
+            };
+            inner.export(msg.get_sender());
+            inner.export_datum(msg.get_datum());
+            let bytes = inner.codec.encode_message(&msg);
+            let _ = peer.write_frame(FRAME_SEND, &[&write_id(&id), &bytes]);
+        }
+    }
+}
+
+/// The length of the creation number and the number of an ActorId in frames.
+/// The node is the one at the other end of the connection.
+const ID_LEN: usize = 12;
+
+fn write_id(id: &ActorId) -> [u8; ID_LEN] {
+    let mut out = [0u8; ID_LEN];
+    out[..4].copy_from_slice(&id.creation().to_be_bytes());
+    out[4..].copy_from_slice(&id.number().to_be_bytes());
+    out
+}
+
+fn read_id(bytes: &[u8]) -> LocalId {
+    let mut creation = [0u8; 4];
+    let mut number = [0u8; 8];
+    creation.copy_from_slice(&bytes[..4]);
+    number.copy_from_slice(&bytes[4..ID_LEN]);
+    (u32::from_be_bytes(creation), u64::from_be_bytes(number))
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+/// Gets the address carried by a node name, which is everything after the
+/// first '@'.
+fn name_address(name: &str) -> io::Result<&str> {
+    match name.split_once('@') {
+        Some((_, addr)) if !addr.is_empty() => Ok(addr),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                format!("Node name {:?} carries no address", name)))
+    }
+}
+
+/// Reads a frame, returning its kind byte and its payload.
+fn read_frame<R: Read>(stream: &mut R) -> io::Result<(u8, Vec<u8>)> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len == 0 || len > MAX_FRAME_LEN {
+        return Err(invalid("Invalid frame length"));
+    }
+    let mut frame = vec![0u8; len];
+    stream.read_exact(&mut frame)?;
+    let kind = frame.remove(0);
+    Ok((kind, frame))
+}
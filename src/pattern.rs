@@ -0,0 +1,331 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Patterns describe the messages an actor is interested in declaratively,
+//! instead of unwrapping nested datums by hand in match clauses.
+//!
+//! A Pattern matches the type of a message and the shape of its datum, and
+//! binds parts of the datum to names, which are then available to the actions
+//! through `Bindings`. Patterns are usually written with the `pattern!` macro:
+//!
+//! ```text
+//! pattern!(Custom(":celebrate"), { age: I64(a), name: Str(n) } if |b| b.i64("a") >= 18)
+//! ```
+//!
+//! The first argument is the message type, which can be `_` to match any. The
+//! optional second argument is the datum pattern, where:
+//!
+//! - `_` matches anything;
+//! - a name binds the datum to it;
+//! - `I64(p)`, `Str(p)`, ... match a datum of that variant whose content
+//!   matches `p`, which is `_`, a name or a literal value;
+//! - `Void` and `Nil` match those datums;
+//! - `{ key: p, "other key": p }` matches a Map having (at least) those keys;
+//! - `[p, p]` and `(p, p)` match a List and a Tuple of that length;
+//! - a literal string matches a Str equal to it.
+//!
+//! The optional guard after `if` is a closure taking the Bindings, which must
+//! return true for the pattern to match.
+//!
+//! Patterns are passed to `Actor::with_matcher` (rather than `with_match`,
+//! which only takes closures), and the actions added with
+//! `Actor::with_bound_action` get the bindings of the match.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use Actor;
+use ActorAddress;
+use ActionResult;
+use Message;
+use MessageDatum;
+use MessageType;
+
+/// The variants of MessageDatum, without their content.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DatumKind {
+    Void, I64, U64, F64, Str, Map, Act, Bool, Bytes, List, Tuple, Nil
+}
+
+impl DatumKind {
+    /// Gets the kind of a datum.
+    pub fn of(datum: &MessageDatum) -> DatumKind {
+        match *datum {
+            MessageDatum::Void => DatumKind::Void,
+            MessageDatum::I64(_) => DatumKind::I64,
+            MessageDatum::U64(_) => DatumKind::U64,
+            MessageDatum::F64(_) => DatumKind::F64,
+            MessageDatum::Str(_) => DatumKind::Str,
+            MessageDatum::Map(_) => DatumKind::Map,
+            MessageDatum::Act(_) => DatumKind::Act,
+            MessageDatum::Bool(_) => DatumKind::Bool,
+            MessageDatum::Bytes(_) => DatumKind::Bytes,
+            MessageDatum::List(_) => DatumKind::List,
+            MessageDatum::Tuple(_) => DatumKind::Tuple,
+            MessageDatum::Nil => DatumKind::Nil,
+        }
+    }
+}
+
+/// A DatumPattern describes the shape of a MessageDatum.
+#[derive(Clone, PartialEq, Debug)]
+pub enum DatumPattern {
+    /// Matches any datum.
+    Any,
+    /// Matches any datum, and binds it to the name.
+    Bind(String),
+    /// Matches a datum equal to the given one.
+    Equals(MessageDatum),
+    /// Matches a datum of the given kind which also matches the inner pattern.
+    Kind(DatumKind, Box<DatumPattern>),
+    /// Matches a Map which has all the keys, with values matching their
+    /// patterns. Other keys are ignored.
+    Map(Vec<(String, DatumPattern)>),
+    /// Matches a List with as many items as the patterns, each matching its
+    /// pattern.
+    List(Vec<DatumPattern>),
+    /// Matches a Tuple with as many items as the patterns, each matching its
+    /// pattern.
+    Tuple(Vec<DatumPattern>),
+}
+
+impl DatumPattern {
+    /// Checks whether the datum matches, adding the bound names to the
+    /// bindings.
+    fn bind(&self, datum: &MessageDatum, bindings: &mut Bindings) -> bool {
+        match *self {
+            DatumPattern::Any => true,
+            DatumPattern::Bind(ref name) => {
+                bindings.values.insert(name.clone(), datum.clone());
+                true
+            },
+            DatumPattern::Equals(ref d) => d == datum,
+            DatumPattern::Kind(kind, ref inner) => {
+                DatumKind::of(datum) == kind && inner.bind(datum, bindings)
+            },
+            DatumPattern::Map(ref entries) => {
+                match datum.as_map_ref() {
+                    Some(m) => entries.iter().all(|(key, p)| {
+                        m.get(key).is_some_and(|d| p.bind(d, bindings))
+                    }),
+                    None => false
+                }
+            },
+            DatumPattern::List(ref items) => {
+                datum.as_list_ref().is_some_and(|l| DatumPattern::bind_all(items, l, bindings))
+            },
+            DatumPattern::Tuple(ref items) => {
+                datum.as_tuple_ref().is_some_and(|t| DatumPattern::bind_all(items, t, bindings))
+            },
+        }
+    }
+
+    fn bind_all(patterns: &[DatumPattern], items: &[MessageDatum], bindings: &mut Bindings)
+        -> bool {
+        patterns.len() == items.len() &&
+            patterns.iter().zip(items.iter()).all(|(p, d)| p.bind(d, bindings))
+    }
+}
+
+/// The values bound to names by a successful match.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bindings {
+    values: HashMap<String, MessageDatum>
+}
+
+impl Bindings {
+    /// Gets the datum bound to the name.
+    pub fn get(&self, name: &str) -> Option<&MessageDatum> { self.values.get(name) }
+
+    /// Gets the I64 value bound to the name. Panics if there is none, as the
+    /// pattern guarantees that it is there.
+    pub fn i64(&self, name: &str) -> i64 {
+        self.get(name).and_then(MessageDatum::as_i64)
+            .unwrap_or_else(|| panic!("No I64 bound to {}", name))
+    }
+
+    /// Gets the Str value bound to the name. Panics if there is none, as the
+    /// pattern guarantees that it is there.
+    pub fn str(&self, name: &str) -> &str {
+        self.get(name).and_then(MessageDatum::as_str_ref)
+            .unwrap_or_else(|| panic!("No Str bound to {}", name))
+    }
+}
+
+type GuardFn = Arc<dyn Fn(&Bindings) -> bool + Send + Sync>;
+
+/// A Pattern matches the type and the datum of a Message. Patterns use a
+/// consuming builder pattern, and can be cheaply cloned.
+#[derive(Clone)]
+pub struct Pattern {
+    mt: Option<MessageType>,
+    datum: DatumPattern,
+    guards: Vec<GuardFn>
+}
+
+impl Default for Pattern {
+    fn default() -> Self { Pattern::new() }
+}
+
+impl Pattern {
+    /// Creates a Pattern matching any message.
+    pub fn new() -> Pattern {
+        Pattern { mt: None, datum: DatumPattern::Any, guards: Vec::new() }
+    }
+
+    /// Restricts the Pattern to messages of the given type.
+    pub fn with_type(mut self, mt: MessageType) -> Self {
+        self.mt = Some(mt);
+        self
+    }
+
+    /// Restricts the Pattern to messages whose datum matches.
+    pub fn with_datum(mut self, datum: DatumPattern) -> Self {
+        self.datum = datum;
+        self
+    }
+
+    /// Adds a guard to the Pattern. Guards are functions or closures taking
+    /// the bindings of a match, which must return true for the message to
+    /// match.
+    pub fn with_guard<T>(mut self, guard: T) -> Self
+        where T: 'static + Fn(&Bindings) -> bool + Send + Sync {
+        self.guards.push(Arc::new(guard));
+        self
+    }
+
+    /// Matches a message, returning the bindings on success.
+    pub fn matches(&self, msg: &Message) -> Option<Bindings> {
+        if self.mt.as_ref().is_some_and(|mt| mt != msg.get_type()) {
+            return None;
+        }
+        let mut bindings = Bindings::default();
+        if !self.datum.bind(msg.get_datum(), &mut bindings) {
+            return None;
+        }
+        if self.guards.iter().all(|g| g(&bindings)) { Some(bindings) } else { None }
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pattern")
+            .field("mt", &self.mt)
+            .field("datum", &self.datum)
+            .field("guards", &self.guards.len())
+            .finish()
+    }
+}
+
+/// A Matcher decides whether an actor is interested in a message, and binds
+/// parts of it for the actions. Match clauses (functions or closures taking a
+/// Message and the actor state) and Patterns are Matchers.
+pub trait Matcher<ActorState>: Send {
+    /// Matches a message, returning the bindings on success.
+    fn bind(&self, msg: &Message, state: &ActorState) -> Option<Bindings>;
+}
+
+/// Match clauses bind nothing.
+impl<ActorState, T> Matcher<ActorState> for T
+    where T: Fn(&Message, &ActorState) -> bool + Send {
+    fn bind(&self, msg: &Message, state: &ActorState) -> Option<Bindings> {
+        if self(msg, state) { Some(Bindings::default()) } else { None }
+    }
+}
+
+impl<ActorState> Matcher<ActorState> for Pattern {
+    fn bind(&self, msg: &Message, _: &ActorState) -> Option<Bindings> {
+        self.matches(msg)
+    }
+}
+
+impl<ActorState: 'static + Sized + Default + Send> Actor<ActorState> {
+    /// Adds a Matcher (such as a Pattern) to the Actor as a match clause.
+    /// Action clauses added with `with_bound_action` after it get the bindings
+    /// of the match.
+    pub fn with_matcher<T>(mut self, matcher: T) -> Self
+        where T: 'static + Matcher<ActorState> {
+        self.matches.push(Box::new(matcher));
+        self
+    }
+
+    /// Adds an action clause to the current match clause of the Actor. The
+    /// action clause works like the ones added with `with_action`, but also
+    /// takes the bindings of the match, which are empty unless the match
+    /// clause binds anything.
+    pub fn with_bound_action<T>(mut self, ac: T) -> Self
+        where T: 'static + Fn(&Bindings, &Message, &mut ActorState, &ActorAddress)
+                    -> ActionResult + Send {
+        while self.actions.len() < self.matches.len() {
+            self.actions.push(Vec::new());
+        }
+        self.actions[self.matches.len() -1].push(Box::new(ac));
+        self
+    }
+}
+
+/// Builds a Pattern. See the documentation of the pattern module for the
+/// syntax.
+#[macro_export]
+macro_rules! pattern {
+    (_) => { $crate::Pattern::new() };
+    ($mt:ident $(($name:expr))?) => {
+        $crate::Pattern::new().with_type($crate::MessageType::$mt $(($name))?)
+    };
+    (_, $head:tt $(($($arg:tt)*))? $(if $guard:expr)?) => {
+        $crate::Pattern::new()
+            .with_datum($crate::__datum_pattern!($head $(($($arg)*))?))
+            $(.with_guard($guard))?
+    };
+    ($mt:ident $(($name:expr))?, $head:tt $(($($arg:tt)*))? $(if $guard:expr)?) => {
+        $crate::pattern!($mt $(($name))?)
+            .with_datum($crate::__datum_pattern!($head $(($($arg)*))?))
+            $(.with_guard($guard))?
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __datum_pattern {
+    (_) => { $crate::DatumPattern::Any };
+    (Void) => { $crate::DatumPattern::Equals($crate::MessageDatum::Void) };
+    (Nil) => { $crate::DatumPattern::Equals($crate::MessageDatum::Nil) };
+    ($kind:ident (_)) => {
+        $crate::DatumPattern::Kind($crate::DatumKind::$kind,
+                                   Box::new($crate::DatumPattern::Any))
+    };
+    ($kind:ident ($name:ident)) => {
+        $crate::DatumPattern::Kind($crate::DatumKind::$kind,
+                                   Box::new($crate::DatumPattern::Bind(stringify!($name).to_string())))
+    };
+    ($kind:ident ($value:literal)) => {
+        $crate::DatumPattern::Equals($crate::MessageDatum::$kind(::std::convert::From::from($value)))
+    };
+    ({ $($key:tt : $head:tt $(($($arg:tt)*))?),* $(,)? }) => {
+        $crate::DatumPattern::Map(vec![
+            $(($crate::__datum_pattern_key!($key).to_string(),
+               $crate::__datum_pattern!($head $(($($arg)*))?))),*
+        ])
+    };
+    ([ $($head:tt $(($($arg:tt)*))?),* $(,)? ]) => {
+        $crate::DatumPattern::List(vec![$($crate::__datum_pattern!($head $(($($arg)*))?)),*])
+    };
+    (( $($head:tt $(($($arg:tt)*))?),* $(,)? )) => {
+        $crate::DatumPattern::Tuple(vec![$($crate::__datum_pattern!($head $(($($arg)*))?)),*])
+    };
+    ($name:ident) => { $crate::DatumPattern::Bind(stringify!($name).to_string()) };
+    ($value:literal) => { $crate::DatumPattern::Equals($crate::MessageDatum::from($value)) };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __datum_pattern_key {
+    ($key:ident) => { stringify!($key) };
+    ($key:literal) => { $key };
+}
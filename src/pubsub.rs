@@ -0,0 +1,91 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Topic-based publish/subscribe on top of process groups.
+//!
+//! Topics are hierarchical, with levels separated by '/', e.g.
+//! "sensors/kitchen/temperature". Subscriptions are topic patterns where a '+'
+//! level matches exactly one level and a trailing '#' level matches any number
+//! of levels (including none), e.g. "sensors/+/temperature" or "sensors/#".
+//!
+//! Every subscription pattern is a process group, so subscribers are removed
+//! automatically when they exit.
+
+use ActorAddress;
+use MessageBuilder;
+use ProcessGroups;
+
+/// Checks whether a topic matches a subscription pattern.
+pub fn topic_matches(pattern: &str, topic: &str) -> bool {
+    let mut pattern_levels = pattern.split('/');
+    let mut topic_levels = topic.split('/');
+    loop {
+        match (pattern_levels.next(), topic_levels.next()) {
+            (Some("#"), _) => { return pattern_levels.next().is_none(); },
+            (Some("+"), Some(_)) => (),
+            (Some(p), Some(t)) => { if p != t { return false; } },
+            (None, None) => { return true; },
+            _ => { return false; }
+        }
+    }
+}
+
+/// PubSub is the handle to a topic-based publish/subscribe system. It can be
+/// cheaply cloned, and all clones share the same subscriptions.
+#[derive(Clone)]
+pub struct PubSub {
+    subscriptions: ProcessGroups,
+}
+
+impl PubSub {
+    /// Creates a new publish/subscribe system with no subscriptions.
+    pub fn spawn() -> PubSub {
+        PubSub { subscriptions: ProcessGroups::spawn() }
+    }
+
+    /// Subscribes an actor to all the topics matching the pattern.
+    pub fn subscribe(&self, pattern: &str, subscriber: &ActorAddress) {
+        self.subscriptions.join(pattern, subscriber);
+    }
+
+    /// Removes a subscription of an actor. Other subscriptions of the same
+    /// actor are not affected.
+    pub fn unsubscribe(&self, pattern: &str, subscriber: &ActorAddress) {
+        self.subscriptions.leave(pattern, subscriber);
+    }
+
+    /// Gets the actors subscribed to the topic. An actor with several matching
+    /// subscriptions appears only once.
+    pub fn subscribers(&self, topic: &str) -> Vec<ActorAddress> {
+        let mut subscribers: Vec<ActorAddress> = Vec::new();
+        for pattern in self.subscriptions.which_groups() {
+            if !topic_matches(&pattern, topic) {
+                continue;
+            }
+            for s in self.subscriptions.members(&pattern) {
+                if !subscribers.contains(&s) {
+                    subscribers.push(s);
+                }
+            }
+        }
+        subscribers
+    }
+
+    /// Builds the message and sends it once to every actor subscribed to the
+    /// topic.
+    pub fn publish(&self, topic: &str, msg: &MessageBuilder) {
+        for s in self.subscribers(topic) {
+            msg.send_to(&s);
+        }
+    }
+
+    /// Shuts down the actor process which monitors the subscribers.
+    pub fn shutdown(&self) {
+        self.subscriptions.shutdown();
+    }
+}
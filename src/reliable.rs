@@ -0,0 +1,315 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reliable channels deliver messages from a producer to a consumer actor
+//! effectively once and in order, even if some of them are lost on the way,
+//! for instance while the connection between two nodes is down.
+//!
+//! Sending a message to an actor is at most once: the message is dropped if
+//! the actor is not running, or if it is on another node which is not
+//! reachable. A reliable channel numbers the messages it sends, and keeps
+//! each of them until the consumer side acknowledges it, sending it again
+//! after a timeout. The consumer side is a receiver actor in front of the
+//! consumer, which delivers the messages of each channel in sequence, drops
+//! the duplicates and the ones which arrive out of order (they will be sent
+//! again), and acknowledges the last message it delivered.
+//!
+//! The producer chooses how many messages can wait for an acknowledgement at
+//! once: when the window is full, sending fails until the consumer catches up.
+//! The `ReliableSender` handle tells how many messages have been sent and
+//! acknowledged, so that producers can apply flow control.
+//!
+//! The state of both sides lives in memory: messages are not delivered again
+//! if the sender is shut down. When the receiver is restarted, the sender is
+//! pointed at the new one with `ReliableSender::redirect`. Every message tells
+//! the oldest one which has not been acknowledged, where a receiver which does
+//! not know the channel starts: the messages which the old receiver delivered
+//! without acknowledging them are delivered twice.
+//!
+//! Message types are not added to the atom table from the wire (see the
+//! `atom` module), so the consumer's node must know the types it is sent. A
+//! message of an unknown type is dropped, but acknowledged, so that it does not
+//! hold up the channel.
+//!
+//! ```text
+//! let inbox = mecha::ReliableChannel::receiver(&consumer);
+//! let channel = mecha::ReliableChannel::new(&inbox).with_window(64).spawn();
+//! channel.send(mecha::Message::custom(":work").with_i64(1))?;
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use Actor;
+use ActorAddress;
+use ActorId;
+use Atom;
+use Message;
+use MessageBuilder;
+use MessageDatum;
+use MessageType;
+
+pub(crate) const RELIABLE_DATA: &str = ":mecha_reliable_data";
+pub(crate) const RELIABLE_ACK: &str = ":mecha_reliable_ack";
+pub(crate) const RELIABLE_TICK: &str = ":mecha_reliable_tick";
+
+/// The messages of a channel which have not been acknowledged yet, and the
+/// receiver they are sent to.
+#[derive(Default)]
+struct Outbox {
+    receiver: Option<ActorAddress>,
+    sent: u64,
+    acknowledged: u64,
+    pending: VecDeque<(u64, Message, Instant)>,
+}
+
+impl Outbox {
+    /// Sends a message of the channel to the receiver, with its sequence number
+    /// and the one of the oldest message which has not been acknowledged.
+    fn deliver(&self, controller: &ActorAddress, seq: u64, msg: &Message) {
+        let (call, mt) = match *msg.get_type() {
+            MessageType::Call(mt) => (true, mt),
+            MessageType::Custom(mt) => (false, mt),
+            _ => { return; }
+        };
+        let first = self.pending.front().map_or(seq, |&(s, _, _)| s.min(seq));
+        let data = vec![MessageDatum::from(controller), MessageDatum::from(seq), MessageDatum::from(first),
+                        MessageDatum::from(call), MessageDatum::from(mt), msg.get_datum().clone()];
+        Message::custom(RELIABLE_DATA).with_sender(msg.get_sender())
+                                      .with_tuple(data)
+                                      .send_to(self.receiver.as_ref().expect("Reliable channel has no receiver"));
+    }
+
+    fn acknowledge(&mut self, seq: u64) {
+        self.acknowledged = self.acknowledged.max(seq.min(self.sent));
+        while self.pending.front().is_some_and(|&(s, _, _)| s <= self.acknowledged) {
+            self.pending.pop_front();
+        }
+    }
+
+    /// Sends again the messages which have been waiting for too long.
+    fn redeliver(&mut self, controller: &ActorAddress, timeout: Duration) {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for &mut (seq, ref msg, ref mut sent_at) in self.pending.iter_mut() {
+            if now.duration_since(*sent_at) >= timeout {
+                due.push((seq, msg.clone()));
+                *sent_at = now;
+            }
+        }
+        for (seq, msg) in due {
+            self.deliver(controller, seq, &msg);
+        }
+    }
+}
+
+#[derive(Default)]
+struct ControllerState {
+    outbox: Arc<Mutex<Outbox>>,
+    timeout: Duration,
+}
+
+/// The last message of each channel a receiver delivered, by the id of the
+/// controller of the channel.
+#[derive(Default)]
+struct ReceiverState {
+    consumer: Option<ActorAddress>,
+    delivered: HashMap<ActorId, u64>,
+}
+
+impl ReceiverState {
+    fn receive(&mut self, msg: &Message, myself: &ActorAddress) {
+        let data = match msg.get_datum().as_tuple_ref() {
+            Some(data) if data.len() == 6 => data,
+            _ => {
+                warn!("Reliable receiver dropped a malformed message");
+                return;
+            }
+        };
+        let (controller, seq, first, call, mt) = match (data[0].as_act(), data[1].as_u64(), data[2].as_u64(),
+                                                        data[3].as_bool(), data[4].as_str_ref()) {
+            (Some(controller), Some(seq), Some(first), Some(call), Some(mt)) => (controller, seq, first, call, mt),
+            _ => {
+                warn!("Reliable receiver dropped a malformed message");
+                return;
+            }
+        };
+        // The messages before the oldest one which has not been acknowledged
+        // have been delivered, maybe by a receiver which was restarted.
+        let delivered = self.delivered.entry(controller.id().clone()).or_insert(0);
+        *delivered = (*delivered).max(first.saturating_sub(1));
+        if seq == *delivered + 1 {
+            match Atom::lookup(mt) {
+                Some(mt) => {
+                    let mut builder = if call { Message::call(mt.as_str()) } else { Message::custom(mt.as_str()) };
+                    builder.with_sender(msg.get_sender()).with_datum(data[5].clone())
+                           .send_to(self.consumer.as_ref().expect("Reliable receiver has no consumer"));
+                },
+                None => {
+                    warn!("Reliable receiver dropped message {} of unknown type {:?}", seq, mt);
+                },
+            }
+            *delivered = seq;
+        }
+        Message::custom(RELIABLE_ACK).with_sender(myself).with_u64(*delivered).send_to(&controller);
+    }
+}
+
+/// ReliableChannel provides an API for creating reliable channels. Like Actor,
+/// it uses a consuming builder pattern.
+pub struct ReliableChannel {
+    receiver: ActorAddress,
+    window: usize,
+    timeout: Duration,
+}
+
+impl ReliableChannel {
+    /// Initializes the ReliableChannel building process, specifying the
+    /// receiver of the channel, as spawned by `ReliableChannel::receiver`.
+    pub fn new(receiver: &ActorAddress) -> ReliableChannel {
+        ReliableChannel {
+            receiver: receiver.clone(),
+            window: 1000,
+            timeout: Duration::from_millis(500),
+        }
+    }
+
+    /// Sets how many messages can wait for an acknowledgement at once. The
+    /// default is 1000.
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    /// Sets how long a message waits for an acknowledgement before it is sent
+    /// again. The default is 500 milliseconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Consumes the ReliableChannel building blocks and spawns the actor
+    /// process which handles acknowledgements and redelivery, returning the
+    /// handle to send messages with.
+    pub fn spawn(self) -> ReliableSender {
+        let outbox = Arc::new(Mutex::new(Outbox { receiver: Some(self.receiver), ..Outbox::default() }));
+        let controller = Actor::new()
+            .with_state(ControllerState {
+                outbox: outbox.clone(),
+                timeout: self.timeout,
+            })
+            .with_init(|state, myself| {
+                Message::custom(RELIABLE_TICK).with_sender(myself).send_after(myself, state.timeout);
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(RELIABLE_TICK)))
+            .with_action(|msg, state, myself| {
+                if msg.get_sender() == myself {
+                    state.outbox.lock().unwrap().redeliver(myself, state.timeout);
+                    Message::custom(RELIABLE_TICK).with_sender(myself).send_after(myself, state.timeout);
+                }
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(RELIABLE_ACK)))
+            .with_action(|msg, state, _| {
+                if let Some(seq) = msg.get_datum().as_u64() {
+                    state.outbox.lock().unwrap().acknowledge(seq);
+                }
+                Ok(())
+            })
+            .spawn();
+        ReliableSender {
+            outbox,
+            controller,
+            window: self.window,
+        }
+    }
+
+    /// Spawns the receiver side of reliable channels, which delivers their
+    /// messages to the consumer, and returns its ActorAddress. Any number of
+    /// channels can send to the same receiver.
+    pub fn receiver(consumer: &ActorAddress) -> ActorAddress {
+        Actor::new()
+            .with_state(ReceiverState { consumer: Some(consumer.clone()), ..ReceiverState::default() })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(RELIABLE_DATA)))
+            .with_action(|msg, state, myself| {
+                state.receive(msg, myself);
+                Ok(())
+            })
+            .spawn()
+    }
+}
+
+/// ReliableSender is the handle to the producer side of a reliable channel. It
+/// can be cheaply cloned, and all clones send on the same channel.
+#[derive(Clone)]
+pub struct ReliableSender {
+    outbox: Arc<Mutex<Outbox>>,
+    controller: ActorAddress,
+    window: usize,
+}
+
+impl ReliableSender {
+    /// Builds a Custom or Call message and sends it on the channel, returning
+    /// its sequence number. Fails if the window is full, or if the message is
+    /// of another type.
+    pub fn send(&self, msg: &MessageBuilder) -> Result<u64, String> {
+        let msg = msg.build();
+        if !matches!(*msg.get_type(), MessageType::Call(_) | MessageType::Custom(_)) {
+            return Err("Only Custom and Call messages can be sent reliably".to_string());
+        }
+        let mut outbox = self.outbox.lock().unwrap();
+        if outbox.pending.len() >= self.window {
+            return Err("The window of the channel is full".to_string());
+        }
+        let seq = outbox.sent + 1;
+        outbox.deliver(&self.controller, seq, &msg);
+        outbox.sent = seq;
+        outbox.pending.push_back((seq, msg, Instant::now()));
+        Ok(seq)
+    }
+
+    /// Points the channel at another receiver, for instance after the receiver
+    /// was restarted, and sends it the messages which have not been
+    /// acknowledged.
+    pub fn redirect(&self, receiver: &ActorAddress) {
+        let mut outbox = self.outbox.lock().unwrap();
+        outbox.receiver = Some(receiver.clone());
+        outbox.redeliver(&self.controller, Duration::from_secs(0));
+    }
+
+    /// Gets the sequence number of the last message sent.
+    pub fn sent(&self) -> u64 {
+        self.outbox.lock().unwrap().sent
+    }
+
+    /// Gets the sequence number of the last message the consumer got. All the
+    /// messages before it have been delivered too.
+    pub fn acknowledged(&self) -> u64 {
+        self.outbox.lock().unwrap().acknowledged
+    }
+
+    /// Gets the number of messages waiting for an acknowledgement.
+    pub fn in_flight(&self) -> usize {
+        self.outbox.lock().unwrap().pending.len()
+    }
+
+    /// Gets the number of messages which can be sent before the window is
+    /// full.
+    pub fn capacity(&self) -> usize {
+        self.window - self.in_flight()
+    }
+
+    /// Shuts down the actor process which handles acknowledgements and
+    /// redelivery. Messages which have not been acknowledged are not sent
+    /// again.
+    pub fn shutdown(&self) {
+        Message::shutdown().send_to(&self.controller);
+    }
+}
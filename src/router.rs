@@ -0,0 +1,331 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A router is an actor which fronts a pool of identical workers, built by the
+//! same Actor factory, and forwards every Custom and Call message it receives
+//! to one of them. The original sender of the message is preserved, so
+//! workers can reply directly to it.
+//!
+//! The router is linked to all of its workers: a worker which exits is
+//! replaced by a new one. The pool can be resized at runtime by sending the
+//! router a `ROUTER_RESIZE` message, or with `Router::resize`; it can also be
+//! resized automatically according to an Autoscaling policy.
+//!
+//! ```text
+//! let router = mecha::Router::new(4, || mecha::Actor::new().with_state(...) ... )
+//!     .with_strategy(mecha::RoutingStrategy::SmallestMailbox)
+//!     .spawn();
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::Instant;
+
+use rand::{self, Rng};
+
+use Actor;
+use ActorAddress;
+use Autoscaling;
+use Message;
+use MessageDatum;
+use MessageType;
+
+/// The Custom message type which resizes the pool of a router. The datum is
+/// the new number of workers, as an U64. A router with no workers drops the
+/// messages it receives.
+pub const ROUTER_RESIZE: &str = ":mecha_router_resize";
+
+pub(crate) const ROUTER_WORKERS: &str = ":mecha_router_workers";
+pub(crate) const ROUTER_TICK: &str = ":mecha_router_tick";
+
+/// How many points each worker gets on the consistent hashing ring.
+const VIRTUAL_NODES: u64 = 32;
+
+/// How a router chooses the worker to forward a message to.
+#[derive(Clone, Debug, Default)]
+pub enum RoutingStrategy {
+    /// Each worker in turn.
+    #[default]
+    RoundRobin,
+    /// A random worker.
+    Random,
+    /// The worker with the fewest messages waiting in its mailbox.
+    SmallestMailbox,
+    /// The worker selected by hashing a key of the datum, so that messages
+    /// with the same key always go to the same worker (as long as the pool is
+    /// not resized). If the datum is a Map, the key is the value with the given
+    /// name; otherwise it is the whole datum.
+    ConsistentHash(&'static str),
+}
+
+type WorkerFactory = Box<dyn Fn(&ActorAddress) -> ActorAddress + Send>;
+
+#[derive(Default)]
+struct RouterState {
+    strategy: RoutingStrategy,
+    factory: Option<WorkerFactory>,
+    workers: Vec<ActorAddress>,
+    next: usize,
+    ring: BTreeMap<u64, usize>,
+    autoscaling: Option<Autoscaling>,
+    last_resize: Option<Instant>,
+}
+
+impl RouterState {
+    fn resize(&mut self, size: usize, myself: &ActorAddress) {
+        let size = match self.autoscaling {
+            Some(ref a) => a.clamp(size),
+            None => size
+        };
+        self.last_resize = Some(Instant::now());
+        while self.workers.len() > size {
+            // The router will not replace this worker when it gets the Exited
+            // message, since it is not in the pool anymore.
+            let w = self.workers.pop().unwrap();
+            Message::shutdown().with_sender(myself).send_to(&w);
+        }
+        while self.workers.len() < size {
+            let w = self.spawn_worker(myself);
+            self.workers.push(w);
+        }
+        self.ring = BTreeMap::new();
+        for slot in 0..size {
+            for v in 0..VIRTUAL_NODES {
+                let mut h = DefaultHasher::new();
+                (slot, v).hash(&mut h);
+                self.ring.insert(h.finish(), slot);
+            }
+        }
+        self.next = 0;
+    }
+
+    fn autoscale(&mut self, myself: &ActorAddress) {
+        let (size, interval) = match self.autoscaling {
+            Some(ref a) => (a.decide(&self.workers, self.last_resize), a.interval()),
+            None => { return; }
+        };
+        if let Some(size) = size {
+            self.resize(size, myself);
+        }
+        Message::custom(ROUTER_TICK).with_sender(myself).send_after(myself, interval);
+    }
+
+    fn spawn_worker(&self, myself: &ActorAddress) -> ActorAddress {
+        (self.factory.as_ref().expect("Router has no worker factory"))(myself)
+    }
+
+    fn replace(&mut self, worker: &ActorAddress, myself: &ActorAddress) {
+        if let Some(slot) = self.workers.iter().position(|w| w == worker) {
+            self.workers[slot] = self.spawn_worker(myself);
+        }
+    }
+
+    fn route(&mut self, msg: &Message) {
+        if self.workers.is_empty() {
+            warn!("Router dropped a message, as its pool is empty");
+            return;
+        }
+        let slot = match self.strategy {
+            RoutingStrategy::RoundRobin => {
+                let slot = self.next % self.workers.len();
+                self.next = slot + 1;
+                slot
+            },
+            RoutingStrategy::Random => {
+                rand::thread_rng().gen_range(0, self.workers.len())
+            },
+            RoutingStrategy::SmallestMailbox => {
+                let mut best = 0;
+                for (i, w) in self.workers.iter().enumerate() {
+                    if w.mailbox_len() < self.workers[best].mailbox_len() {
+                        best = i;
+                    }
+                }
+                best
+            },
+            RoutingStrategy::ConsistentHash(key) => {
+                let datum = msg.get_datum();
+                let key_datum = match *datum {
+                    MessageDatum::Map(ref m) => m.get(key).unwrap_or(datum),
+                    _ => datum
+                };
+                let mut h = DefaultHasher::new();
+                hash_datum(key_datum, &mut h);
+                let point = h.finish();
+                let slot = self.ring.range(point..).next()
+                    .or_else(|| self.ring.iter().next())
+                    .map(|(_, slot)| *slot);
+                slot.unwrap_or(0)
+            }
+        };
+        self.workers[slot].deliver(msg.clone());
+    }
+}
+
+/// Feeds a MessageDatum to a hasher. Maps are hashed independently of the
+/// iteration order of their entries.
+fn hash_datum<H: Hasher>(d: &MessageDatum, h: &mut H) {
+    match *d {
+        MessageDatum::Void => { 0u8.hash(h); },
+        MessageDatum::I64(x) => { 1u8.hash(h); x.hash(h); },
+        MessageDatum::U64(x) => { 2u8.hash(h); x.hash(h); },
+        MessageDatum::F64(x) => { 3u8.hash(h); x.to_bits().hash(h); },
+        MessageDatum::Str(ref x) => { 4u8.hash(h); x.hash(h); },
+        MessageDatum::Map(ref m) => {
+            5u8.hash(h);
+            let mut keys: Vec<&String> = m.keys().collect();
+            keys.sort();
+            for k in keys {
+                k.hash(h);
+                hash_datum(&m[k], h);
+            }
+        },
+        MessageDatum::Act(ref a) => { 6u8.hash(h); a.hash(h); },
+        MessageDatum::Bool(x) => { 7u8.hash(h); x.hash(h); },
+        MessageDatum::Bytes(ref x) => { 8u8.hash(h); x.hash(h); },
+        MessageDatum::List(ref l) => {
+            9u8.hash(h);
+            l.len().hash(h);
+            for d in l.iter() {
+                hash_datum(d, h);
+            }
+        },
+        MessageDatum::Tuple(ref t) => {
+            10u8.hash(h);
+            t.len().hash(h);
+            for d in t.iter() {
+                hash_datum(d, h);
+            }
+        },
+        MessageDatum::Nil => { 11u8.hash(h); },
+    }
+}
+
+/// Router provides an API for creating router actor processes. Like Actor, it
+/// uses a consuming builder pattern.
+pub struct Router {
+    size: usize,
+    strategy: RoutingStrategy,
+    factory: WorkerFactory,
+    autoscaling: Option<Autoscaling>,
+}
+
+impl Router {
+    /// Initializes the Router building process, specifying the initial number
+    /// of workers and the factory used to build each of them. While the pool
+    /// is empty, the messages sent to the router are dropped.
+    pub fn new<ActorState, F>(size: usize, factory: F) -> Router
+        where ActorState: 'static + Sized + Default + Send,
+              F: 'static + Fn() -> Actor<ActorState> + Send {
+        Router {
+            size,
+            strategy: RoutingStrategy::RoundRobin,
+            factory: Box::new(move |router| factory().spawn_link(router)),
+            autoscaling: None,
+        }
+    }
+
+    /// Sets the routing strategy. The default is round-robin.
+    pub fn with_strategy(mut self, strategy: RoutingStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets the autoscaling policy. Without one, the pool is only resized on
+    /// request.
+    pub fn with_autoscaling(mut self, autoscaling: Autoscaling) -> Self {
+        self.autoscaling = Some(autoscaling);
+        self
+    }
+
+    /// Consumes the Router building blocks and spawns the router actor process
+    /// (which in turn spawns the workers), returning its ActorAddress.
+    pub fn spawn(self) -> ActorAddress {
+        self.into_actor().spawn()
+    }
+
+    /// Consumes the Router building blocks and spawns the router actor
+    /// process, linking it to the provided actor.
+    pub fn spawn_link(self, uplink: &ActorAddress) -> ActorAddress {
+        self.into_actor().spawn_link(uplink)
+    }
+
+    fn into_actor(self) -> Actor<RouterState> {
+        let size = self.size;
+        Actor::new()
+            .with_state(RouterState {
+                strategy: self.strategy,
+                factory: Some(self.factory),
+                autoscaling: self.autoscaling,
+                ..RouterState::default()
+            })
+            .with_init(move |state, myself| {
+                state.resize(size, myself);
+                state.autoscale(myself);
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(ROUTER_TICK)))
+            .with_action(|msg, state, myself| {
+                if msg.get_sender() == myself {
+                    state.autoscale(myself);
+                }
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(ROUTER_RESIZE)))
+            .with_action(|msg, state, myself| {
+                match msg.get_datum().as_u64() {
+                    Some(size) => { state.resize(size as usize, myself); Ok(()) },
+                    None => Err("Router size must be an U64".to_string())
+                }
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Call(ROUTER_WORKERS)))
+            .with_action(|msg, state, myself| {
+                let workers: HashMap<String, MessageDatum> = state.workers.iter()
+                    .enumerate()
+                    .map(|(i, w)| (i.to_string(), MessageDatum::from(w)))
+                    .collect();
+                Message::reply().with_sender(myself)
+                                .with_map(workers)
+                                .send_to(msg.get_sender());
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Exited))
+            .with_action(|msg, state, myself| {
+                state.replace(msg.get_sender(), myself);
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(_) | MessageType::Call(_)))
+            .with_action(|msg, state, _| {
+                state.route(msg);
+                Ok(())
+            })
+            .with_terminate(|state, _| {
+                for w in state.workers.drain(..) {
+                    Message::shutdown().send_to(&w);
+                }
+            })
+    }
+
+    /// Resizes the pool of the router at the given address. Resizing it to 0
+    /// drops the messages sent to the router until it grows again.
+    pub fn resize(router: &ActorAddress, size: usize) {
+        Message::custom(ROUTER_RESIZE).with_u64(size as u64).send_to(router);
+    }
+
+    /// Gets the current workers of the router at the given address.
+    pub fn workers(router: &ActorAddress) -> Result<Vec<ActorAddress>, String> {
+        let workers = router.call(ROUTER_WORKERS, MessageDatum::Void)?;
+        let workers = workers.as_map().unwrap_or_default();
+        let mut slots: Vec<(usize, ActorAddress)> = workers.into_iter()
+            .filter_map(|(i, w)| Some((i.parse().ok()?, w.as_act()?)))
+            .collect();
+        slots.sort_by_key(|&(i, _)| i);
+        Ok(slots.into_iter().map(|(_, w)| w).collect())
+    }
+}
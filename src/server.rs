@@ -0,0 +1,182 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Servers are actors whose behaviour is described by the callbacks of a trait
+//! rather than by a list of loose match and action closures, in the spirit of
+//! Erlang's gen_server.
+//!
+//! A server is built on top of `Actor`, so it can be spawned, linked and shut
+//! down exactly like any other actor process:
+//!
+//! ```
+//! extern crate mecha;
+//!
+//! #[derive(Default)]
+//! struct Counter { count: i64 }
+//!
+//! impl mecha::Server for Counter {
+//!     fn handle_call(&mut self, _: &mecha::Message, _: &mecha::ActorAddress)
+//!         -> Result<mecha::MessageDatum, String> {
+//!         self.count += 1;
+//!         Ok(mecha::MessageDatum::from(self.count))
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let counter = mecha::Actor::from_server(Counter::default()).spawn();
+//!     let next = counter.call(":next", mecha::MessageDatum::Void).unwrap();
+//!     assert_eq!(next.as_i64(), Some(1));
+//!     mecha::Message::shutdown().send_to(&counter);
+//! }
+//! ```
+
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use Actor;
+use ActorAddress;
+use Message;
+use MessageDatum;
+use MessageType;
+use ActionResult;
+
+/// How long `ActorAddress::call` waits for a reply before giving up.
+pub(crate) const DEFAULT_CALL_TIMEOUT_MS: u64 = 5000;
+
+/// The Server trait describes an actor through callbacks.
+///
+/// - `init` is called in the actor process before any message is processed.
+/// - `handle_call` is called for Call messages; the returned datum is sent back
+///   to the caller as a Reply message.
+/// - `handle_cast` is called for Custom messages.
+/// - `handle_info` is called for any other message that is not handled by the
+///   actor process itself (for example Exited messages from linked actors).
+/// - `terminate` is called when the actor process exits, with the same reason
+///   that is sent to linked actors.
+///
+/// Returning an error from any of the callbacks makes the actor process exit
+/// with that error, as it would happen with an Actor action.
+pub trait Server: 'static + Send {
+    /// Initializes the server. The default implementation does nothing.
+    fn init(&mut self, _myself: &ActorAddress) -> ActionResult { Ok(()) }
+
+    /// Handles a Call message and returns the datum to reply with. The default
+    /// implementation fails, as a server receiving unexpected calls is broken.
+    fn handle_call(&mut self, msg: &Message, _myself: &ActorAddress)
+        -> Result<MessageDatum, String> {
+        Err(format!("Unhandled call {:?}", msg.get_type()))
+    }
+
+    /// Handles a Custom message. The default implementation ignores it.
+    fn handle_cast(&mut self, _msg: &Message, _myself: &ActorAddress) -> ActionResult {
+        Ok(())
+    }
+
+    /// Handles any other message. The default implementation ignores it.
+    fn handle_info(&mut self, _msg: &Message, _myself: &ActorAddress) -> ActionResult {
+        Ok(())
+    }
+
+    /// Cleans up when the server exits. The default implementation does
+    /// nothing.
+    fn terminate(&mut self, _reason: &MessageDatum) {}
+}
+
+/// The actor state of an actor process built from a Server. It only exists so
+/// that the Server itself does not have to implement Default.
+pub struct ServerState<S: Server>(Option<S>);
+
+impl<S: Server> Default for ServerState<S> {
+    fn default() -> Self { ServerState(None) }
+}
+
+impl<S: Server> ServerState<S> {
+    fn server(&mut self) -> &mut S {
+        self.0.as_mut().expect("Server state has not been provided")
+    }
+}
+
+impl<S: Server> Actor<ServerState<S>> {
+    /// Initializes the Actor building process from a Server. The resulting
+    /// Actor already has all the clauses needed to drive the Server callbacks,
+    /// and can be spawned (or further extended) as usual.
+    pub fn from_server(server: S) -> Self {
+        Actor::new()
+            .with_state(ServerState(Some(server)))
+            .with_init(|state, myself| state.server().init(myself))
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Call(_)))
+            .with_action(|msg, state, myself| {
+                match state.server().handle_call(msg, myself) {
+                    Ok(reply) => {
+                        Message::reply().with_sender(myself)
+                                        .with_datum(reply)
+                                        .send_to(msg.get_sender());
+                        Ok(())
+                    },
+                    Err(e) => {
+                        // The caller is not linked to us, but it deserves to
+                        // know why it will never get a reply.
+                        Message::exited().with_sender(myself)
+                                         .with_str(&e)
+                                         .send_to(msg.get_sender());
+                        Err(e)
+                    }
+                }
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(_)))
+            .with_action(|msg, state, myself| state.server().handle_cast(msg, myself))
+            .with_match(|msg, _| {
+                !matches!(*msg.get_type(), MessageType::Link | MessageType::Unlink | MessageType::Shutdown)
+            })
+            .with_action(|msg, state, myself| state.server().handle_info(msg, myself))
+            .with_terminate(|state, reason| state.server().terminate(reason))
+    }
+}
+
+impl ActorAddress {
+    /// Sends a Call message to the actor and waits for its Reply, for at most
+    /// the default call timeout.
+    pub fn call(&self, mt: &'static str, datum: MessageDatum) -> Result<MessageDatum, String> {
+        self.call_timeout(mt, datum, Duration::from_millis(DEFAULT_CALL_TIMEOUT_MS))
+    }
+
+    /// Sends a Call message to the actor and waits for its Reply, for at most
+    /// the provided timeout. An error is returned if the actor is not running,
+    /// if it exits while handling the call, or if the timeout expires.
+    pub fn call_timeout(&self, mt: &'static str, datum: MessageDatum, timeout: Duration)
+        -> Result<MessageDatum, String> {
+        let (tx, rx) = mpsc::channel();
+        let caller = ActorAddress::new(tx);
+        let msg = Message::call(mt).with_sender(&caller).with_datum(datum).build();
+        if !self.deliver(msg) {
+            return Err("Actor is not running".to_string());
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            let reply = match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(reply) => reply,
+                Err(_) => { return Err(format!("Timeout calling {}", mt)); }
+            };
+            if reply.get_sender() != self {
+                continue;
+            }
+            match *reply.get_type() {
+                MessageType::Reply => { return Ok(reply.get_datum().clone()); },
+                MessageType::Exited => {
+                    return Err(reply.get_datum().as_str().unwrap_or_default());
+                },
+                _ => ()
+            }
+        }
+    }
+
+    /// Sends a Custom message to the actor without waiting for anything.
+    pub fn cast(&self, mt: &'static str, datum: MessageDatum) {
+        Message::custom(mt).with_datum(datum).send_to(self);
+    }
+}
@@ -0,0 +1,361 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Sharding spreads a large number of entity actors, each identified by a key
+//! such as a user or an order id, across a cluster of nodes, in the spirit of
+//! Akka's cluster sharding.
+//!
+//! Every node which hosts entities of a type spawns a shard region for it, with
+//! the same type name and factory. The entity id of each message is extracted
+//! from its datum and hashed into one of a fixed number of shards, and every
+//! shard belongs to one of the regions. The regions find each other through the
+//! global registry (see the `global` module), and allocate the shards with
+//! rendezvous hashing: each shard goes to the region whose node scores highest
+//! for it. Every region computes the same allocation from the same regions,
+//! without coordinating, and when a region joins or goes away only the shards
+//! it gains or loses move.
+//!
+//! A region forwards every Custom and Call message it receives to the region
+//! owning the shard of the entity, preserving the original sender. The owning
+//! region spawns the entity actor the first time it gets a message for it,
+//! and passivates (shuts down) entities which have not received messages for
+//! a while. When the allocation changes, a region shuts down the entities of
+//! the shards it lost, and they are spawned again on demand on their new node:
+//! entities which must survive this should keep their state elsewhere.
+//!
+//! As every node can end up hosting any entity, the message types of the
+//! entities must be known on all of them: a region drops the messages whose
+//! type is not in its atom table (see the `atom` module).
+//!
+//! ```text
+//! let users = mecha::Sharding::new("user", |id| mecha::Actor::new() ... )
+//!     .with_passivation(Duration::from_secs(60))
+//!     .spawn(&node);
+//! mecha::Message::custom(":login").with_str("alice").send_to(&users);
+//! ```
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use Actor;
+use ActorAddress;
+use Atom;
+use Message;
+use MessageDatum;
+use MessageType;
+use Node;
+
+pub(crate) const SHARDING_TICK: &str = ":mecha_sharding_tick";
+pub(crate) const SHARDING_DELIVER: &str = ":mecha_sharding_deliver";
+pub(crate) const SHARDING_ENTITIES: &str = ":mecha_sharding_entities";
+
+/// The prefix of the global names of the shard regions, followed by the type
+/// name, a slash and the name of the node.
+const REGION_PREFIX: &str = ":mecha_sharding/";
+
+type EntityFactory = Box<dyn Fn(&str, &ActorAddress) -> ActorAddress + Send>;
+type IdExtractor = Box<dyn Fn(&MessageDatum) -> Option<String> + Send>;
+
+/// Extracts the entity id of a message when no other extractor is set: the
+/// `id` value of a Map datum, or the whole datum, if it is a string or an
+/// integer.
+fn default_id(datum: &MessageDatum) -> Option<String> {
+    let datum = match *datum {
+        MessageDatum::Map(ref m) => m.get("id")?,
+        _ => datum
+    };
+    match *datum {
+        MessageDatum::Str(ref s) => Some(s.to_string()),
+        MessageDatum::I64(i) => Some(i.to_string()),
+        MessageDatum::U64(u) => Some(u.to_string()),
+        _ => None
+    }
+}
+
+/// Hashes with 64-bit FNV-1a, which, unlike the hashers of the standard
+/// library, is the same on every node.
+fn fnv(parts: &[&[u8]]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for b in part.iter() {
+            h ^= u64::from(*b);
+            h = h.wrapping_mul(0x0100_0000_01b3);
+        }
+        // Separate the parts, so that ("ab", "c") and ("a", "bc") differ.
+        h ^= 0xff;
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+    h
+}
+
+#[derive(Default)]
+struct ShardingState {
+    type_name: String,
+    node: Option<Node>,
+    factory: Option<EntityFactory>,
+    extractor: Option<IdExtractor>,
+    shards: u64,
+    passivation: Option<Duration>,
+    interval: Duration,
+    /// The regions of the type, by the name of their node, sorted.
+    regions: Vec<(String, ActorAddress)>,
+    /// The local entities, by id, with the time they last got a message.
+    entities: HashMap<String, (ActorAddress, Instant)>,
+}
+
+impl ShardingState {
+    fn node(&self) -> &Node {
+        self.node.as_ref().expect("Sharding has no node")
+    }
+
+    fn region_name(&self, node: &str) -> String {
+        format!("{}{}/{}", REGION_PREFIX, self.type_name, node)
+    }
+
+    fn shard(&self, id: &str) -> u64 {
+        fnv(&[id.as_bytes()]) % self.shards
+    }
+
+    /// Gets the node of the region which owns a shard.
+    fn owner(&self, shard: u64) -> Option<&(String, ActorAddress)> {
+        self.regions.iter().max_by_key(|(node, _)| fnv(&[&shard.to_be_bytes(), node.as_bytes()]))
+    }
+
+    /// Looks up the regions of the type, and shuts down the entities of the
+    /// shards which moved away, and the entities which have been idle for too
+    /// long.
+    fn rebalance(&mut self, myself: &ActorAddress) {
+        let me = self.node().name().to_string();
+        if self.node().whereis_global(&self.region_name(&me)).is_none() {
+            self.node().register_global(&self.region_name(&me), myself);
+        }
+        let prefix = format!("{}{}/", REGION_PREFIX, self.type_name);
+        self.regions = self.node().global_names().into_iter()
+            .filter(|name| name.starts_with(&prefix))
+            .filter_map(|name| {
+                let region = self.node().whereis_global(&name)?;
+                Some((name[prefix.len()..].to_string(), region))
+            })
+            .collect();
+        let now = Instant::now();
+        let passivation = self.passivation;
+        let moved: Vec<String> = self.entities.iter()
+            .filter(|&(id, &(_, last))| {
+                self.owner(self.shard(id)).is_some_and(|o| o.0 != me)
+                    || passivation.is_some_and(|p| now.duration_since(last) >= p)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in moved {
+            if let Some((entity, _)) = self.entities.remove(&id) {
+                Message::shutdown().with_sender(myself).send_to(&entity);
+            }
+        }
+    }
+
+    /// Forwards a message from a client to the region owning the shard of its
+    /// entity.
+    fn route(&mut self, msg: &Message, myself: &ActorAddress) {
+        let id = match (self.extractor.as_ref().expect("Sharding has no extractor"))(msg.get_datum()) {
+            Some(id) => id,
+            None => {
+                warn!("Sharding {:?} dropped a message without an entity id", self.type_name);
+                return;
+            }
+        };
+        let owner = self.owner(self.shard(&id)).map(|o| o.1.clone());
+        match owner {
+            Some(ref region) if region != myself => {
+                let (call, mt) = match *msg.get_type() {
+                    MessageType::Call(mt) => (true, mt),
+                    MessageType::Custom(mt) => (false, mt),
+                    _ => { return; }
+                };
+                let envelope = vec![MessageDatum::from(call), MessageDatum::from(mt),
+                                    MessageDatum::from(&id[..]), msg.get_datum().clone()];
+                Message::custom(SHARDING_DELIVER).with_sender(msg.get_sender())
+                                                 .with_tuple(envelope)
+                                                 .send_to(region);
+            },
+            _ => self.deliver(&id, msg.clone(), myself)
+        }
+    }
+
+    /// Unwraps a message forwarded by another region, and delivers it to the
+    /// local entity.
+    fn unwrap(&mut self, msg: &Message, myself: &ActorAddress) {
+        let envelope = match msg.get_datum().as_tuple_ref() {
+            Some(envelope) if envelope.len() == 4 => envelope,
+            _ => {
+                warn!("Sharding {:?} dropped a malformed forwarded message", self.type_name);
+                return;
+            }
+        };
+        let (call, mt, id) = match (envelope[0].as_bool(), envelope[1].as_str_ref(), envelope[2].as_str_ref()) {
+            (Some(call), Some(mt), Some(id)) => (call, mt, id.to_string()),
+            _ => {
+                warn!("Sharding {:?} dropped a malformed forwarded message", self.type_name);
+                return;
+            }
+        };
+        // The message types of the entities must be known on every node, as
+        // they are not added to the atom table from the wire.
+        let mt = match Atom::lookup(mt) {
+            Some(mt) => mt.as_str(),
+            None => {
+                warn!("Sharding {:?} dropped a message of unknown type {:?}", self.type_name, mt);
+                return;
+            }
+        };
+        let mut builder = if call { Message::call(mt) } else { Message::custom(mt) };
+        let inner = builder.with_sender(msg.get_sender()).with_datum(envelope[3].clone()).build();
+        self.deliver(&id, inner, myself);
+    }
+
+    fn deliver(&mut self, id: &str, msg: Message, myself: &ActorAddress) {
+        let factory = self.factory.as_ref().expect("Sharding has no factory");
+        let entry = self.entities.entry(id.to_string())
+                                 .or_insert_with(|| (factory(id, myself), Instant::now()));
+        entry.1 = Instant::now();
+        entry.0.deliver(msg);
+    }
+
+    /// Forgets a local entity which has exited.
+    fn exited(&mut self, entity: &ActorAddress) {
+        self.entities.retain(|_, &mut (ref e, _)| e != entity);
+    }
+}
+
+/// Sharding provides an API for creating shard regions. Like Actor, it uses a
+/// consuming builder pattern.
+pub struct Sharding {
+    type_name: String,
+    factory: EntityFactory,
+    extractor: IdExtractor,
+    shards: u64,
+    passivation: Option<Duration>,
+    interval: Duration,
+}
+
+impl Sharding {
+    /// Initializes the Sharding building process, specifying the name of the
+    /// entity type in the cluster and the factory used to build each entity
+    /// from its id.
+    pub fn new<ActorState, F>(type_name: &str, factory: F) -> Sharding
+        where ActorState: 'static + Sized + Default + Send,
+              F: 'static + Fn(&str) -> Actor<ActorState> + Send {
+        Sharding {
+            type_name: type_name.to_string(),
+            factory: Box::new(move |id, region| factory(id).spawn_link(region)),
+            extractor: Box::new(default_id),
+            shards: 100,
+            passivation: Some(Duration::from_secs(120)),
+            interval: Duration::from_millis(100),
+        }
+    }
+
+    /// Sets how the entity id is extracted from the datum of a message. By
+    /// default, it is the `id` value of a Map datum, or the whole datum if it
+    /// is a string or an integer. Messages without an id are dropped.
+    pub fn with_extractor<F>(mut self, extractor: F) -> Self
+        where F: 'static + Fn(&MessageDatum) -> Option<String> + Send {
+        self.extractor = Box::new(extractor);
+        self
+    }
+
+    /// Sets the number of shards, which must be the same on all the nodes. It
+    /// should be a good deal larger than the number of nodes; the default is
+    /// 100.
+    pub fn with_shards(mut self, shards: u64) -> Self {
+        self.shards = shards.max(1);
+        self
+    }
+
+    /// Sets how long an entity can go without messages before it is
+    /// passivated, or None to keep entities running. The default is two
+    /// minutes.
+    pub fn with_passivation(mut self, idle: Option<Duration>) -> Self {
+        self.passivation = idle;
+        self
+    }
+
+    /// Sets how often the region looks for other regions and passivates idle
+    /// entities. The default is every 100 milliseconds.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Consumes the Sharding building blocks and spawns the shard region actor
+    /// process on the given node, returning its ActorAddress.
+    pub fn spawn(self, node: &Node) -> ActorAddress {
+        Actor::new()
+            .with_state(ShardingState {
+                type_name: self.type_name,
+                node: Some(node.clone()),
+                factory: Some(self.factory),
+                extractor: Some(self.extractor),
+                shards: self.shards,
+                passivation: self.passivation,
+                interval: self.interval,
+                ..ShardingState::default()
+            })
+            .with_init(|state, myself| {
+                state.rebalance(myself);
+                Message::custom(SHARDING_TICK).with_sender(myself).send_after(myself, state.interval);
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(SHARDING_TICK)))
+            .with_action(|msg, state, myself| {
+                if msg.get_sender() == myself {
+                    state.rebalance(myself);
+                    Message::custom(SHARDING_TICK).with_sender(myself).send_after(myself, state.interval);
+                }
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(SHARDING_DELIVER)))
+            .with_action(|msg, state, myself| {
+                state.unwrap(msg, myself);
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Call(SHARDING_ENTITIES)))
+            .with_action(|msg, state, myself| {
+                let mut ids: Vec<String> = state.entities.keys().cloned().collect();
+                ids.sort();
+                Message::reply().with_sender(myself)
+                                .with_list(ids.into_iter().map(MessageDatum::from).collect())
+                                .send_to(msg.get_sender());
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Exited))
+            .with_action(|msg, state, _| {
+                state.exited(msg.get_sender());
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(_) | MessageType::Call(_)))
+            .with_action(|msg, state, myself| {
+                state.route(msg, myself);
+                Ok(())
+            })
+            .with_terminate(|state, _| {
+                let me = state.node().name().to_string();
+                state.node().unregister_global(&state.region_name(&me));
+                for (_, (entity, _)) in state.entities.drain() {
+                    Message::shutdown().send_to(&entity);
+                }
+            })
+            .spawn()
+    }
+
+    /// Gets the ids of the entities running in the shard region at the given
+    /// address, sorted.
+    pub fn entities(region: &ActorAddress) -> Result<Vec<String>, String> {
+        let ids = region.call(SHARDING_ENTITIES, MessageDatum::Void)?;
+        Ok(ids.as_list().unwrap_or_default().iter().filter_map(|id| id.as_str()).collect())
+    }
+}
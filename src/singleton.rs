@@ -0,0 +1,218 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A cluster singleton is an actor of which exactly one instance runs across
+//! a cluster of nodes, in the spirit of Akka's cluster singleton.
+//!
+//! Every node which wants to use the singleton spawns a singleton manager with
+//! the same name and factory. The instance is registered with that name in the
+//! global registry (see the `global` module), and it is hosted by the node
+//! which registered it. When nobody holds the name, the manager on the leader
+//! of the cluster (see `Node::leader`) spawns a new instance; so when the host
+//! node fails, the instance is restarted on the leader of the remaining nodes
+//! as soon as they notice. An instance which exits gives up its name, and a new
+//! one is started on the leader in the same way. An instance which lost its
+//! name to another one, after a partition healed, is shut down.
+//!
+//! The address of the manager is a stable proxy for the singleton: every
+//! Custom and Call message sent to it is forwarded to the current instance,
+//! wherever it runs, preserving the original sender. While there is no
+//! instance, for instance during a hand-over, the messages are buffered and
+//! forwarded in order once a new one is up. Messages which were already
+//! forwarded to a node which failed are lost.
+//!
+//! ```text
+//! let counter = mecha::Singleton::new("counter", || mecha::Actor::new() ... )
+//!     .spawn(&node);
+//! mecha::Message::custom(":incr").send_to(&counter);
+//! ```
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use Actor;
+use ActorAddress;
+use Message;
+use MessageType;
+use Node;
+
+pub(crate) const SINGLETON_TICK: &str = ":mecha_singleton_tick";
+
+/// The default number of messages a manager buffers while there is no
+/// instance.
+const DEFAULT_BUFFER_SIZE: usize = 1000;
+
+type InstanceFactory = Box<dyn Fn(&ActorAddress) -> ActorAddress + Send>;
+
+#[derive(Default)]
+struct SingletonState {
+    name: String,
+    node: Option<Node>,
+    factory: Option<InstanceFactory>,
+    interval: Duration,
+    buffer_size: usize,
+    instance: Option<ActorAddress>,
+    buffer: VecDeque<Message>,
+}
+
+impl SingletonState {
+    fn node(&self) -> &Node {
+        self.node.as_ref().expect("Singleton has no node")
+    }
+
+    /// Checks who is hosting the singleton, starting or stopping the local
+    /// instance if needed, and forwards the buffered messages if possible.
+    fn check(&mut self, myself: &ActorAddress) {
+        let registered = self.node().whereis_global(&self.name);
+        match self.instance {
+            Some(ref instance) if registered.as_ref() != Some(instance) => {
+                // The instance lost its name to an instance on another node.
+                Message::shutdown().with_sender(myself).send_to(instance);
+                self.instance = None;
+            },
+            Some(_) => (),
+            None if registered.is_none() && self.node().leader().as_deref() == Some(self.node().name()) => {
+                let instance = (self.factory.as_ref().expect("Singleton has no factory"))(myself);
+                if self.node().register_global(&self.name, &instance) {
+                    self.instance = Some(instance);
+                } else {
+                    Message::shutdown().with_sender(myself).send_to(&instance);
+                }
+            },
+            None => ()
+        }
+        self.flush();
+    }
+
+    fn target(&self) -> Option<ActorAddress> {
+        self.instance.clone().or_else(|| self.node().whereis_global(&self.name))
+    }
+
+    fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        if let Some(target) = self.target() {
+            for msg in self.buffer.drain(..) {
+                target.deliver(msg);
+            }
+        }
+    }
+
+    fn forward(&mut self, msg: &Message) {
+        self.flush();
+        if self.buffer.is_empty() {
+            if let Some(target) = self.target() {
+                target.deliver(msg.clone());
+                return;
+            }
+        }
+        if self.buffer_size == 0 {
+            warn!("Singleton {:?} dropped a message, as it has no instance", self.name);
+            return;
+        }
+        if self.buffer.len() == self.buffer_size {
+            warn!("Singleton {:?} dropped a message, as its buffer is full", self.name);
+            self.buffer.pop_front();
+        }
+        self.buffer.push_back(msg.clone());
+    }
+
+    /// Forgets the local instance, which has exited, so that a new one is
+    /// started.
+    fn exited(&mut self, instance: &ActorAddress) {
+        if self.instance.as_ref() == Some(instance) {
+            self.instance = None;
+            self.node().unregister_global(&self.name);
+        }
+    }
+}
+
+/// Singleton provides an API for creating cluster singleton managers. Like
+/// Actor, it uses a consuming builder pattern.
+pub struct Singleton {
+    name: String,
+    factory: InstanceFactory,
+    interval: Duration,
+    buffer_size: usize,
+}
+
+impl Singleton {
+    /// Initializes the Singleton building process, specifying the name of the
+    /// singleton in the cluster and the factory used to build its instance.
+    pub fn new<ActorState, F>(name: &str, factory: F) -> Singleton
+        where ActorState: 'static + Sized + Default + Send,
+              F: 'static + Fn() -> Actor<ActorState> + Send {
+        Singleton {
+            name: name.to_string(),
+            factory: Box::new(move |manager| factory().spawn_link(manager)),
+            interval: Duration::from_millis(100),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    /// Sets how often the manager checks who is hosting the singleton. The
+    /// default is every 100 milliseconds.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets how many messages the manager buffers while there is no instance.
+    /// When the buffer is full, the oldest messages are dropped; with a size
+    /// of 0, every message sent while there is no instance is dropped.
+    pub fn with_buffer_size(mut self, size: usize) -> Self {
+        self.buffer_size = size;
+        self
+    }
+
+    /// Consumes the Singleton building blocks and spawns the manager actor
+    /// process on the given node, returning its ActorAddress, which is the
+    /// proxy for the singleton.
+    pub fn spawn(self, node: &Node) -> ActorAddress {
+        Actor::new()
+            .with_state(SingletonState {
+                name: self.name,
+                node: Some(node.clone()),
+                factory: Some(self.factory),
+                interval: self.interval,
+                buffer_size: self.buffer_size,
+                ..SingletonState::default()
+            })
+            .with_init(|state, myself| {
+                state.check(myself);
+                Message::custom(SINGLETON_TICK).with_sender(myself).send_after(myself, state.interval);
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(SINGLETON_TICK)))
+            .with_action(|msg, state, myself| {
+                if msg.get_sender() == myself {
+                    state.check(myself);
+                    Message::custom(SINGLETON_TICK).with_sender(myself).send_after(myself, state.interval);
+                }
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Exited))
+            .with_action(|msg, state, _| {
+                state.exited(msg.get_sender());
+                Ok(())
+            })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(_) | MessageType::Call(_)))
+            .with_action(|msg, state, _| {
+                state.forward(msg);
+                Ok(())
+            })
+            .with_terminate(|state, _| {
+                if let Some(instance) = state.instance.take() {
+                    state.node().unregister_global(&state.name);
+                    Message::shutdown().send_to(&instance);
+                }
+            })
+            .spawn()
+    }
+}
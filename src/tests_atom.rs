@@ -0,0 +1,86 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod atom {
+
+use Atom;
+use Codec;
+use CodecError;
+use Message;
+use MessageType;
+
+use std::collections::HashSet;
+use std::sync::mpsc;
+use std::thread;
+
+const PING: &str = ":atom_ping";
+
+#[test]
+fn test_interning() {
+    let name = format!(":atom_{}", "runtime");
+    assert_eq!(Atom::lookup(&name), None);
+    let a = Atom::new(&name);
+    let b = Atom::new(":atom_runtime");
+    assert_eq!(a, b);
+    assert!(::std::ptr::eq(a.as_str(), b.as_str()));
+    assert_eq!(Atom::lookup(":atom_runtime"), Some(a));
+    assert_eq!(a.to_string(), ":atom_runtime");
+
+    // A static name is interned without copying it.
+    let s = Atom::from_static(":atom_static");
+    assert!(::std::ptr::eq(s.as_str(), ":atom_static"));
+    assert_eq!(Atom::new(":atom_static"), s);
+
+    // Atoms are ordered by name, not by creation.
+    let z = Atom::new(":atom_z");
+    let y = Atom::new(":atom_y");
+    assert!(y < z);
+    let set: HashSet<Atom> = vec![a, b, s, y, z].into_iter().collect();
+    assert_eq!(set.len(), 4);
+}
+
+#[test]
+fn test_runtime_message_type() {
+    // The name is built at runtime, as if it came from a config file.
+    let mt = Atom::new(&[":atom", "_ping"].concat());
+    assert_eq!(MessageType::Custom(mt.as_str()), MessageType::Custom(PING));
+    assert!(MessageType::Custom(mt.as_str()) != MessageType::Call(PING));
+
+    let (tx, rx) = mpsc::channel();
+    let h = thread::spawn(move || {
+        let msg: Message = rx.recv().unwrap();
+        matches!(*msg.get_type(), MessageType::Custom(PING))
+    });
+    let msg = Message::custom(mt.as_str()).build();
+    // Decoded messages get their type names from the atom table too.
+    let codec = Codec::new();
+    let decoded = codec.decode_message(&codec.encode_message(&msg)).unwrap();
+    match *decoded.get_type() {
+        MessageType::Custom(name) => assert!(::std::ptr::eq(name, mt.as_str())),
+        _ => panic!("Unexpected message type")
+    }
+    tx.send(decoded).unwrap();
+    assert!(h.join().unwrap());
+}
+
+#[test]
+fn test_unknown_message_type() {
+    let codec = Codec::new();
+    let mut bytes = codec.encode_message(&Message::custom(":atom_known").build());
+    let at = bytes.windows(11).position(|w| w == b":atom_known").unwrap();
+    bytes[at..at + 11].copy_from_slice(b":atom_other");
+
+    // Names received from elsewhere are not added to the atom table.
+    assert_eq!(codec.decode_message(&bytes).err(), Some(CodecError::UnknownAtom(":atom_other".to_string())));
+    assert_eq!(Atom::lookup(":atom_other"), None);
+    Atom::from_static(":atom_other");
+    assert_eq!(*codec.decode_message(&bytes).unwrap().get_type(), MessageType::Custom(":atom_other"));
+}
+
+}
@@ -0,0 +1,180 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod auth {
+
+use MessageDatum;
+use Message;
+use ActorAddress;
+use Codec;
+use Node;
+use auth::{hmac, session};
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::time::Duration;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn frame(kind: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = ((payload.len() + 1) as u32).to_be_bytes().to_vec();
+    out.push(kind);
+    out.extend_from_slice(payload);
+    out
+}
+
+// Reads a frame, returning its kind and payload, or None if the connection
+// is closed.
+fn read(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).ok()?;
+    let mut frame = vec![0u8; u32::from_be_bytes(len) as usize];
+    stream.read_exact(&mut frame).ok()?;
+    let kind = frame.remove(0);
+    Some((kind, frame))
+}
+
+#[test]
+fn test_hmac() {
+    // Test cases 2 and 6 of RFC 4231.
+    assert_eq!(hex(&hmac(b"Jefe", &[b"what do ya want ", b"for nothing?"])),
+               "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    assert_eq!(hex(&hmac(&[0xaa; 131], &[b"Test Using Larger Than Block-Size Key - Hash Key First"])),
+               "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54");
+}
+
+#[test]
+fn test_frame_tags() {
+    let (na, nb) = ([1u8; 32], [2u8; 32]);
+    let (mut signer, _) = session(b"secret", "a", &na, "b", &nb, true);
+    let verifier = || session(b"secret", "b", &nb, "a", &na, false).1;
+    let frames: Vec<Vec<u8>> = [&b"one"[..], &b"two"[..]].iter().map(|p| {
+        let mut frame = p.to_vec();
+        frame.extend_from_slice(&signer.sign(1, &[p]));
+        frame
+    }).collect();
+
+    let mut v = verifier();
+    assert_eq!(v.verify(1, &frames[0]), Some(&b"one"[..]));
+    assert_eq!(v.verify(1, &frames[1]), Some(&b"two"[..]));
+
+    // Frames signed with another cookie, of another kind, reordered, replayed
+    // or altered do not verify.
+    assert!(session(b"other", "b", &nb, "a", &na, false).1.verify(1, &frames[0]).is_none());
+    assert!(session(b"secret", "b", &nb, "a", &na, true).1.verify(1, &frames[0]).is_none());
+    assert!(verifier().verify(2, &frames[0]).is_none());
+    assert!(verifier().verify(1, &frames[1]).is_none());
+    let mut v = verifier();
+    assert!(v.verify(1, &frames[0]).is_some());
+    assert!(v.verify(1, &frames[0]).is_none());
+    let mut altered = frames[0].clone();
+    altered[0] ^= 1;
+    assert!(verifier().verify(1, &altered).is_none());
+    assert!(verifier().verify(1, &[]).is_none());
+}
+
+#[test]
+fn test_cookies() {
+    let a = Node::listen_with_cookie("a_cookie", "127.0.0.1:0", "secret").unwrap();
+    let b = Node::listen_with_cookie("b_cookie", "127.0.0.1:0", "secret").unwrap();
+    let c = Node::listen_with_cookie("c_cookie", "127.0.0.1:0", "other").unwrap();
+    let d = Node::listen("d_cookie", "127.0.0.1:0").unwrap();
+
+    // Nodes with the same cookie connect and exchange messages.
+    assert_eq!(b.connect(a.address()).unwrap(), "a_cookie");
+    let (tx, rx) = mpsc::channel();
+    let target = ActorAddress::new(tx);
+    a.register("target", &target);
+    let remote = b.whereis("a_cookie", "target").unwrap();
+    for i in 0..100 {
+        Message::custom(":count").with_datum(MessageDatum::from(i as i64)).send_to(&remote);
+    }
+    for i in 0..100 {
+        let msg = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(msg.get_datum().as_i64(), Some(i));
+    }
+
+    // Nodes with another cookie, or without one, are rejected both ways.
+    assert!(c.connect(a.address()).is_err());
+    assert!(a.connect(c.address()).is_err());
+    assert!(d.connect(a.address()).is_err());
+    assert!(a.connect(d.address()).is_err());
+    assert_eq!(a.peers(), vec!["b_cookie".to_string()]);
+    assert!(c.peers().is_empty() && d.peers().is_empty());
+
+    for n in [a, b, c, d].iter() {
+        n.shutdown();
+    }
+}
+
+#[test]
+fn test_injection() {
+    let a = Node::listen_with_cookie("a_inject", "127.0.0.1:0", "secret").unwrap();
+    let (tx, rx) = mpsc::channel();
+    let target = ActorAddress::new(tx);
+    a.register("target", &target);
+
+    // A process which does not know the cookie cannot get a message through,
+    // whatever it answers to the challenge.
+    let mut payload = target.id().creation().to_be_bytes().to_vec();
+    payload.extend_from_slice(&target.id().number().to_be_bytes());
+    payload.extend_from_slice(&Codec::new().encode_message(&Message::shutdown().build()));
+    for challenge in [vec![], vec![7u8; 32]].iter() {
+        let mut evil = TcpStream::connect(a.address()).unwrap();
+        let mut bytes = frame(0, b"evil");
+        bytes.extend(frame(6, challenge));
+        bytes.extend(frame(7, &[0u8; 32]));
+        bytes.extend(frame(1, &payload));
+        let _ = evil.write_all(&bytes);
+    }
+    assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+    assert!(a.peers().is_empty());
+    a.shutdown();
+}
+
+#[test]
+fn test_reflection() {
+    let a = Node::listen_with_cookie("a_reflect", "127.0.0.1:0", "secret").unwrap();
+
+    // A process which does not know the cookie opens two connections, claiming
+    // to be the node itself, and sends the challenge of the first connection
+    // on the second one, to get the node to answer its own challenge.
+    let mut first = TcpStream::connect(a.address()).unwrap();
+    let mut second = TcpStream::connect(a.address()).unwrap();
+    first.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    second.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert_eq!(read(&mut first).map(|f| f.0), Some(0));
+    let challenge = read(&mut first).unwrap().1;
+    assert_eq!(challenge.len(), 32);
+    assert_eq!(read(&mut second).map(|f| f.0), Some(0));
+    assert_eq!(read(&mut second).map(|f| f.0), Some(6));
+    let mut bytes = frame(0, b"a_reflect");
+    bytes.extend(frame(6, &challenge));
+    let _ = second.write_all(&bytes);
+    let reflected = match read(&mut second) {
+        Some((7, answer)) => answer,
+        _ => vec![0u8; 32]
+    };
+    let mut bytes = frame(0, b"a_reflect");
+    bytes.extend(frame(6, &challenge));
+    bytes.extend(frame(7, &reflected));
+    let _ = first.write_all(&bytes);
+
+    // Neither connection is accepted.
+    while let Some((kind, _)) = read(&mut first) {
+        assert_ne!(kind, 5, "The node accepted its own answer");
+    }
+    assert!(a.peers().is_empty());
+    a.shutdown();
+}
+
+}
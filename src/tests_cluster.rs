@@ -0,0 +1,70 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+pub mod cluster {
+
+use Membership;
+use Node;
+
+use std::env;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A membership which notices failures within a fraction of a second.
+pub fn membership() -> Membership {
+    Membership::new().with_heartbeat_interval(Duration::from_millis(20))
+                     .with_acceptable_pause(Duration::from_millis(100))
+                     .with_suspect_timeout(Duration::from_millis(200))
+}
+
+/// Nodes named with the prefix and their index, which do not know each other.
+pub fn nodes(prefix: &str, size: usize) -> Vec<Node> {
+    (0..size).map(|i| {
+        Node::listen(&format!("{}{:02}", prefix, i), "127.0.0.1:0").unwrap()
+            .with_membership(membership())
+    }).collect()
+}
+
+/// Nodes which are all connected to each other, and all agree on the members.
+pub fn cluster(prefix: &str, size: usize) -> Vec<Node> {
+    let nodes = nodes(prefix, size);
+    for n in nodes.iter().skip(1) {
+        n.connect(nodes[0].address()).unwrap();
+    }
+    assert!(eventually(|| nodes.iter().all(|n| {
+        n.peers().len() == size - 1 && n.members().len() == size
+    })));
+    nodes
+}
+
+/// Waits until the condition holds, for at most ten seconds.
+pub fn eventually<F: Fn() -> bool>(condition: F) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    condition()
+}
+
+/// Runs the ignored test with the given path in a child process, with
+/// environment variables telling it what to do.
+pub fn child_process(test: &str, vars: &[(&str, &str)]) -> Child {
+    let mut command = Command::new(env::current_exe().unwrap());
+    command.args(["--exact", test, "--ignored", "--test-threads=1"]).stdout(Stdio::null());
+    for &(name, value) in vars {
+        command.env(name, value);
+    }
+    command.spawn().unwrap()
+}
+
+}
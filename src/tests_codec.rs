@@ -0,0 +1,128 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod codec {
+
+use MessageType;
+use MessageDatum;
+use Message;
+use ActorAddress;
+use Codec;
+use CodecError;
+use CODEC_VERSION;
+
+use quickcheck::{quickcheck, Arbitrary, Gen};
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+
+const GREET: &str = ":greet";
+
+#[derive(Clone, Debug)]
+struct AnyDatum(MessageDatum);
+
+impl Arbitrary for AnyDatum {
+    fn arbitrary(g: &mut Gen) -> AnyDatum { AnyDatum(any_datum(g, 4)) }
+}
+
+// Generates a datum with Maps, Lists and Tuples nested at most `depth` levels
+// deep.
+fn any_datum(g: &mut Gen, depth: usize) -> MessageDatum {
+    let variants = if depth == 0 { 9 } else { 12 };
+    match u8::arbitrary(g) % variants {
+        0 => MessageDatum::Void,
+        1 => MessageDatum::I64(i64::arbitrary(g)),
+        2 => MessageDatum::U64(u64::arbitrary(g)),
+        3 => MessageDatum::F64(f64::arbitrary(g)),
+        4 => MessageDatum::from(String::arbitrary(g)),
+        5 => MessageDatum::Act(ActorAddress::new(mpsc::channel().0)),
+        6 => MessageDatum::Bool(bool::arbitrary(g)),
+        7 => MessageDatum::Bytes(Vec::<u8>::arbitrary(g).into()),
+        8 => MessageDatum::Nil,
+        9 => MessageDatum::List((0..(usize::arbitrary(g) % 5)).map(|_| any_datum(g, depth - 1))
+                                                              .collect()),
+        10 => MessageDatum::Tuple((0..(usize::arbitrary(g) % 5)).map(|_| any_datum(g, depth - 1))
+                                                                .collect()),
+        _ => {
+            let mut m = HashMap::new();
+            for _ in 0..(usize::arbitrary(g) % 5) {
+                m.insert(String::arbitrary(g), any_datum(g, depth - 1));
+            }
+            MessageDatum::from(m)
+        }
+    }
+}
+
+fn has_nan(d: &MessageDatum) -> bool {
+    match *d {
+        MessageDatum::F64(x) => x.is_nan(),
+        MessageDatum::Map(ref m) => m.values().any(has_nan),
+        MessageDatum::List(ref l) | MessageDatum::Tuple(ref l) => l.iter().any(has_nan),
+        _ => false
+    }
+}
+
+#[test]
+fn test_datum_roundtrip() {
+    fn prop(d: AnyDatum) -> bool {
+        let codec = Codec::new().with_node("test@localhost");
+        let bytes = codec.encode_datum(&d.0);
+        let decoded = codec.decode_datum(&bytes).unwrap();
+        // NaNs never compare equal, but their encoding must survive.
+        codec.encode_datum(&decoded) == bytes && (has_nan(&d.0) || decoded == d.0)
+    }
+    quickcheck(prop as fn(AnyDatum) -> bool);
+}
+
+#[test]
+fn test_message_roundtrip() {
+    let (tx, rx) = mpsc::channel();
+    let me = ActorAddress::new(tx);
+    let codec = Codec::new().with_actor(&me);
+
+    let mut map = HashMap::new();
+    map.insert("name".to_string(), MessageDatum::from("Louie"));
+    map.insert("age".to_string(), MessageDatum::from(16i64));
+    let msg = Message::custom(GREET).with_sender(&me).with_map(map).build();
+    let decoded = codec.decode_message(&codec.encode_message(&msg)).unwrap();
+    assert_eq!(*decoded.get_type(), MessageType::Custom(GREET));
+    assert_eq!(decoded.get_datum(), msg.get_datum());
+
+    // The sender was registered, so it resolves to a working address.
+    Message::shutdown().send_to(decoded.get_sender());
+    assert_eq!(*rx.recv().unwrap().get_type(), MessageType::Shutdown);
+
+    let bytes = codec.encode_message(&Message::shutdown().build());
+    assert_eq!(*codec.decode_message(&bytes).unwrap().get_type(), MessageType::Shutdown);
+}
+
+#[test]
+fn test_decoding_errors() {
+    let codec = Codec::new();
+
+    let bytes = codec.encode_datum(&MessageDatum::from("truncated"));
+    assert_eq!(codec.decode_datum(&bytes[..bytes.len() - 1]).err(),
+               Some(CodecError::UnexpectedEnd));
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert_eq!(codec.decode_datum(&trailing).err(), Some(CodecError::TrailingBytes));
+    assert_eq!(codec.decode_datum(&[CODEC_VERSION + 1, 0]).err(),
+               Some(CodecError::UnsupportedVersion(CODEC_VERSION + 1)));
+    assert_eq!(codec.decode_datum(&[CODEC_VERSION, 42]).err(), Some(CodecError::InvalidTag(42)));
+
+    // Maps nested too deeply are rejected instead of overflowing the stack.
+    let mut deep = vec![CODEC_VERSION];
+    for _ in 0..100 {
+        deep.extend_from_slice(&[5, 1, 1, b'k']);
+    }
+    deep.push(0);
+    assert_eq!(codec.decode_datum(&deep).err(), Some(CodecError::TooDeep));
+}
+
+}
@@ -0,0 +1,71 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod datum {
+
+use MessageDatum;
+use Message;
+use ActorAddress;
+
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc};
+
+const BIG: &str = ":big";
+
+fn users() -> MessageDatum {
+    let mut louie = HashMap::new();
+    louie.insert("name".to_string(), MessageDatum::from("Louie"));
+    louie.insert("tags".to_string(), MessageDatum::from((1i64, "nephew")));
+    let mut m = HashMap::new();
+    m.insert("users".to_string(), MessageDatum::from(vec![MessageDatum::from(louie)]));
+    m.insert("raw".to_string(), MessageDatum::from(&b"bytes"[..]));
+    MessageDatum::from(m)
+}
+
+#[test]
+fn test_borrowing_accessors() {
+    let d = users();
+    assert_eq!(d.get_path(&["users", "0", "name"]).and_then(|n| n.as_str_ref()),
+               Some("Louie"));
+    assert_eq!(d.get_path(&["users", "0", "tags", "1"]), Some(&MessageDatum::from("nephew")));
+    assert_eq!(d.get_path(&["users", "1"]), None);
+    assert_eq!(d.get_path(&["users", "zero"]), None);
+    assert_eq!(d.get_path(&["users", "0", "name", "more"]), None);
+    assert_eq!(d.get_path(&[]), Some(&d));
+    assert_eq!(d.get("raw").and_then(|r| r.as_bytes_ref()), Some(&b"bytes"[..]));
+    assert_eq!(d.get("users").and_then(|u| u.as_list_ref()).map(|u| u.len()), Some(1));
+    assert_eq!(d.as_map_ref().map(|m| m.len()), Some(2));
+    assert_eq!(d.as_str_ref(), None);
+}
+
+#[test]
+fn test_shared_payload() {
+    let (tx, rx) = mpsc::channel();
+    let to = ActorAddress::new(tx);
+    let big: HashMap<String, MessageDatum> = (0..100)
+        .map(|i| (i.to_string(), MessageDatum::from("x".repeat(1000))))
+        .collect();
+    let datum = MessageDatum::from(big);
+    for _ in 0..3 {
+        Message::custom(BIG).with_datum(datum.clone()).send_to(&to);
+    }
+    // Every recipient sees the very same map.
+    let original = datum.as_map_ref().unwrap();
+    for _ in 0..3 {
+        let msg = rx.recv().unwrap();
+        let received = msg.get_datum().as_map_ref().unwrap();
+        assert!(::std::ptr::eq(original, received));
+    }
+    match datum {
+        MessageDatum::Map(ref m) => assert_eq!(Arc::strong_count(m), 1),
+        _ => unreachable!()
+    }
+}
+
+}
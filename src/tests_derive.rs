@@ -0,0 +1,124 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(all(test, feature = "derive"))]
+mod derive {
+
+use MessageType;
+use MessageDatum;
+use Message;
+use ActorAddress;
+use Actor;
+use MechaMessage;
+use TypedAddress;
+use TypedMessage;
+
+use std::sync::mpsc;
+
+// This is the derived version of the messages in test_talker.
+#[derive(MechaMessage, Clone, Debug, PartialEq)]
+enum Talk {
+    Greet(String),
+    Praise(String),
+    Celebrate { name: String, age: i64 },
+    #[mecha(name = ":bye")]
+    SayGoodbye,
+    Score(String, Option<u64>)
+}
+
+// Variants named after acronyms.
+#[derive(MechaMessage, Debug, PartialEq)]
+enum Fetch {
+    HTTPGet(String),
+    ParseJSONBody,
+    Retry3Times
+}
+
+#[test]
+fn test_derived_names() {
+    assert_eq!(Talk::GREET, ":greet");
+    assert_eq!(Talk::SAY_GOODBYE, ":bye");
+    assert_eq!(Talk::Celebrate { name: "Louie".to_string(), age: 16 }.message_type(),
+               ":celebrate");
+
+    let msg = Message::custom(Talk::CELEBRATE).with_i64(16).build();
+    match *msg.get_type() {
+        MessageType::Custom(Talk::CELEBRATE) => (),
+        _ => panic!("Unexpected message type")
+    }
+    // The datum has the wrong shape, so it is not a Talk.
+    assert!(!Talk::matches(&msg, &()));
+
+    assert_eq!(Talk::message_types(), &[":greet", ":praise", ":celebrate", ":bye", ":score"]);
+}
+
+#[test]
+fn test_derived_acronyms() {
+    assert_eq!(Fetch::HTTP_GET, ":http_get");
+    assert_eq!(Fetch::PARSE_JSON_BODY, ":parse_json_body");
+    assert_eq!(Fetch::RETRY3_TIMES, ":retry3_times");
+    assert_eq!(Fetch::HTTPGet("/".to_string()).message_type(), ":http_get");
+    assert_eq!(Fetch::from_datum(":parse_json_body", &MessageDatum::Void), Some(Fetch::ParseJSONBody));
+}
+
+#[test]
+fn test_derived_conversions() {
+    let talks = vec![
+        Talk::Greet("Huey".to_string()),
+        Talk::Celebrate { name: "Louie".to_string(), age: 16 },
+        Talk::SayGoodbye,
+        Talk::Score("Dewey".to_string(), Some(3)),
+        Talk::Score("Dewey".to_string(), None),
+    ];
+    for t in talks {
+        let msg = Message::custom(t.message_type()).with_datum(t.to_datum()).build();
+        assert!(Talk::matches(&msg, &()));
+        assert_eq!(Talk::from_message(&msg), Some(t));
+    }
+
+    // Struct variants are sent as maps, as test_talker does by hand.
+    let datum = Talk::Celebrate { name: "Louie".to_string(), age: 16 }.to_datum();
+    assert_eq!(datum.get("age").and_then(MessageDatum::as_i64), Some(16));
+    assert_eq!(datum.get("name").and_then(MessageDatum::as_str_ref), Some("Louie"));
+    assert_eq!(Talk::SayGoodbye.to_datum(), MessageDatum::Void);
+}
+
+#[test]
+fn test_derived_talker() {
+    let (tx, rx) = mpsc::channel();
+    let initiator = ActorAddress::new(tx);
+
+    let worker: TypedAddress<Talk> = Actor::new()
+        .with_state(Vec::new())
+        .with_match(Talk::matches)
+        .with_action(|msg, said: &mut Vec<String>, _| {
+            let talk = Talk::from_message(msg).unwrap();
+            said.push(match talk {
+                Talk::Greet(name) => format!("Hello {}", name),
+                Talk::Praise(name) => format!("{}, you're amazing", name),
+                Talk::Celebrate { name, age } =>
+                    format!("Here's to another {} years, {}", age, name),
+                Talk::SayGoodbye => return Err(said.join("; ")),
+                Talk::Score(..) => return Ok(())
+            });
+            Ok(())
+        })
+        .spawn_link_typed(&initiator);
+
+    worker.send(Talk::Greet("Huey".to_string()));
+    worker.send(Talk::Praise("Dewey".to_string()));
+    worker.send(Talk::Celebrate { name: "Louie".to_string(), age: 16 });
+    worker.send(Talk::SayGoodbye);
+
+    let msg = rx.recv().unwrap();
+    assert_eq!(*msg.get_type(), MessageType::Exited);
+    assert_eq!(msg.get_datum().as_str_ref(),
+               Some("Hello Huey; Dewey, you're amazing; Here's to another 16 years, Louie"));
+}
+
+}
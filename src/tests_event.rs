@@ -0,0 +1,175 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod event {
+
+use MessageType;
+use MessageDatum;
+use Message;
+use ActorAddress;
+use EventHandler;
+use EventManager;
+use EVENT_HANDLER_EXITED;
+
+use std::sync::mpsc;
+
+const LOG: &str = ":log";
+const ALERT: &str = ":alert";
+
+struct Logger { prefix: String, out: mpsc::Sender<String> }
+
+impl EventHandler for Logger {
+    fn init(&mut self, previous: &MessageDatum) -> Result<(), String> {
+        if let Some(p) = previous.as_str() {
+            self.prefix = format!("{}{}", p, self.prefix);
+        }
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: &Message) -> Result<(), String> {
+        let line = format!("{}{}", self.prefix, event.get_datum().as_str().unwrap());
+        self.out.send(line).unwrap();
+        Ok(())
+    }
+
+    fn terminate(&mut self, _: &MessageDatum) -> MessageDatum {
+        MessageDatum::from(self.prefix.clone())
+    }
+}
+
+struct Alerter;
+
+impl EventHandler for Alerter {
+    fn handle_event(&mut self, event: &Message) -> Result<(), String> {
+        match *event.get_type() {
+            MessageType::Custom(ALERT) => Err("Pager is broken".to_string()),
+            _ => Ok(())
+        }
+    }
+}
+
+struct Panicker;
+
+impl EventHandler for Panicker {
+    fn handle_event(&mut self, _: &Message) -> Result<(), String> {
+        panic!("Pager caught fire");
+    }
+
+    fn terminate(&mut self, _: &MessageDatum) -> MessageDatum {
+        panic!("Pager is still on fire");
+    }
+}
+
+#[test]
+fn test_handlers() {
+    let (tx, rx) = mpsc::channel();
+    let initiator = ActorAddress::new(tx);
+    let (ltx, lrx) = mpsc::channel();
+
+    let manager = EventManager::spawn_link(&initiator);
+    manager.add_handler("logger", Logger { prefix: "A: ".to_string(), out: ltx.clone() });
+    manager.notify(LOG, MessageDatum::from("one"));
+    assert_eq!(lrx.recv().unwrap(), "A: one");
+
+    manager.swap_handler("logger", "new_logger",
+                         Logger { prefix: "B: ".to_string(), out: ltx.clone() });
+    manager.notify(LOG, MessageDatum::from("two"));
+    assert_eq!(lrx.recv().unwrap(), "A: B: two");
+    assert_eq!(manager.which_handlers().unwrap(), vec!["new_logger".to_string()]);
+
+    manager.remove_handler("new_logger");
+    manager.notify(LOG, MessageDatum::from("three"));
+    assert_eq!(manager.which_handlers().unwrap(), Vec::<String>::new());
+    assert!(lrx.try_recv().is_err());
+
+    Message::shutdown().send_to(manager.address());
+    assert_eq!(*rx.recv().unwrap().get_type(), MessageType::Exited);
+}
+
+#[test]
+fn test_crashing_handler() {
+    let (tx, rx) = mpsc::channel();
+    let supervisor = ActorAddress::new(tx);
+    let (ltx, lrx) = mpsc::channel();
+
+    let manager = EventManager::spawn();
+    manager.add_sup_handler("alerter", Alerter, &supervisor);
+    manager.add_handler("logger", Logger { prefix: String::new(), out: ltx });
+
+    manager.notify(ALERT, MessageDatum::from("disk full"));
+    assert_eq!(lrx.recv().unwrap(), "disk full");
+
+    let msg = rx.recv().unwrap();
+    assert_eq!(*msg.get_type(), MessageType::Custom(EVENT_HANDLER_EXITED));
+    assert_eq!(msg.get_sender(), manager.address());
+    let info = msg.get_datum().as_map().unwrap();
+    assert_eq!(info.get("handler").unwrap().as_str(), Some("alerter".to_string()));
+    assert_eq!(info.get("reason").unwrap().as_str(), Some("Pager is broken".to_string()));
+
+    // The manager and the other handler are still alive.
+    manager.notify(LOG, MessageDatum::from("still here"));
+    assert_eq!(lrx.recv().unwrap(), "still here");
+    assert_eq!(manager.which_handlers().unwrap(), vec!["logger".to_string()]);
+
+    Message::shutdown().send_to(manager.address());
+}
+
+#[test]
+fn test_panicking_handler() {
+    let (tx, rx) = mpsc::channel();
+    let supervisor = ActorAddress::new(tx);
+    let (ltx, lrx) = mpsc::channel();
+
+    let manager = EventManager::spawn();
+    manager.add_sup_handler("panicker", Panicker, &supervisor);
+    manager.add_handler("logger", Logger { prefix: String::new(), out: ltx });
+
+    // A panic is handled like an error, even if terminating panics too.
+    manager.notify(LOG, MessageDatum::from("smoke"));
+    assert_eq!(lrx.recv().unwrap(), "smoke");
+    let msg = rx.recv().unwrap();
+    assert_eq!(*msg.get_type(), MessageType::Custom(EVENT_HANDLER_EXITED));
+    let info = msg.get_datum().as_map().unwrap();
+    assert_eq!(info.get("handler").unwrap().as_str(), Some("panicker".to_string()));
+    assert_eq!(info.get("reason").unwrap().as_str(),
+               Some("Event handler panicked: Pager caught fire".to_string()));
+
+    manager.notify(LOG, MessageDatum::from("still here"));
+    assert_eq!(lrx.recv().unwrap(), "still here");
+    assert_eq!(manager.which_handlers().unwrap(), vec!["logger".to_string()]);
+
+    Message::shutdown().send_to(manager.address());
+}
+
+#[test]
+fn test_duplicate_id() {
+    let (tx, rx) = mpsc::channel();
+    let supervisor = ActorAddress::new(tx);
+    let (ltx, lrx) = mpsc::channel();
+
+    let manager = EventManager::spawn();
+    manager.add_handler("pager", Logger { prefix: String::new(), out: ltx });
+    manager.add_sup_handler("pager", Alerter, &supervisor);
+
+    let msg = rx.recv().unwrap();
+    assert_eq!(*msg.get_type(), MessageType::Custom(EVENT_HANDLER_EXITED));
+    let info = msg.get_datum().as_map().unwrap();
+    assert_eq!(info.get("handler").unwrap().as_str(), Some("pager".to_string()));
+    assert_eq!(info.get("reason").unwrap().as_str(),
+               Some("Event handler \"pager\" is already installed".to_string()));
+
+    // The installed handler is left alone.
+    manager.notify(ALERT, MessageDatum::from("disk full"));
+    assert_eq!(lrx.recv().unwrap(), "disk full");
+    assert_eq!(manager.which_handlers().unwrap(), vec!["pager".to_string()]);
+
+    Message::shutdown().send_to(manager.address());
+}
+
+}
@@ -0,0 +1,120 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod fsm {
+
+use MessageType;
+use MessageDatum;
+use Message;
+use ActorAddress;
+use Fsm;
+use Transition;
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+const LOCKED: &str = ":locked";
+const OPEN: &str = ":open";
+
+const UNLOCK: &str = ":unlock";
+const PUSH: &str = ":push";
+
+#[derive(Default)]
+struct DoorData { events: Option<mpsc::Sender<String>>, pushes: i64 }
+
+impl DoorData {
+    fn report(&self, event: &str) {
+        self.events.as_ref().unwrap().send(event.to_string()).unwrap();
+    }
+}
+
+fn door(events: mpsc::Sender<String>, uplink: &ActorAddress) -> ActorAddress {
+    Fsm::new(LOCKED).with_data(DoorData { events: Some(events), pushes: 0 })
+        .with_handler(LOCKED, MessageType::Custom(UNLOCK), |_, _, _| {
+            Ok(Transition::Next(OPEN))
+        })
+        .with_handler(OPEN, MessageType::Custom(PUSH), |_, data, _| {
+            data.pushes += 1;
+            data.report(&format!("push {}", data.pushes));
+            Ok(Transition::Next(LOCKED))
+        })
+        .with_enter(LOCKED, |data, _| { data.report("enter locked"); Ok(()) })
+        .with_enter(OPEN, |data, _| { data.report("enter open"); Ok(()) })
+        .with_exit(OPEN, |data, _| { data.report("exit open"); Ok(()) })
+        .with_timeout(OPEN, Duration::from_millis(200), |data, _| {
+            data.report("timeout");
+            Ok(Transition::Next(LOCKED))
+        })
+        .spawn_link(uplink)
+}
+
+#[test]
+fn test_postponed_messages() {
+    let (tx, rx) = mpsc::channel();
+    let initiator = ActorAddress::new(tx);
+    let (etx, erx) = mpsc::channel();
+
+    let door = door(etx, &initiator);
+    assert_eq!(erx.recv().unwrap(), "enter locked");
+
+    // Pushing a locked door does nothing until it is unlocked.
+    Message::custom(PUSH).send_to(&door);
+    Message::custom(UNLOCK).send_to(&door);
+    assert_eq!(erx.recv().unwrap(), "enter open");
+    assert_eq!(erx.recv().unwrap(), "push 1");
+    assert_eq!(erx.recv().unwrap(), "exit open");
+    assert_eq!(erx.recv().unwrap(), "enter locked");
+
+    // The timeout of the first visit to the open state must not fire.
+    assert!(erx.recv_timeout(Duration::from_millis(400)).is_err());
+
+    Message::shutdown().send_to(&door);
+    let msg = rx.recv().unwrap();
+    assert_eq!(*msg.get_type(), MessageType::Exited);
+    match *msg.get_datum() {
+        MessageDatum::Void => (),
+        _ => { panic!("Unexpected message datum"); }
+    }
+}
+
+#[test]
+fn test_state_timeout() {
+    let (tx, rx) = mpsc::channel();
+    let initiator = ActorAddress::new(tx);
+    let (etx, erx) = mpsc::channel();
+
+    let door = door(etx, &initiator);
+    assert_eq!(erx.recv().unwrap(), "enter locked");
+
+    Message::custom(UNLOCK).send_to(&door);
+    assert_eq!(erx.recv().unwrap(), "enter open");
+    assert_eq!(erx.recv().unwrap(), "timeout");
+    assert_eq!(erx.recv().unwrap(), "exit open");
+    assert_eq!(erx.recv().unwrap(), "enter locked");
+
+    Message::shutdown().send_to(&door);
+    assert_eq!(*rx.recv().unwrap().get_type(), MessageType::Exited);
+}
+
+#[test]
+fn test_send_after() {
+    let (tx, rx) = mpsc::channel();
+    let me = ActorAddress::new(tx);
+
+    // Delayed messages arrive by deadline, whatever order they are sent in.
+    for &ms in [80u64, 20, 50, 20].iter() {
+        Message::custom(PUSH).with_u64(ms).send_after(&me, Duration::from_millis(ms));
+    }
+    let delays: Vec<u64> = (0..4).map(|_| {
+        rx.recv_timeout(Duration::from_secs(5)).unwrap().get_datum().as_u64().unwrap()
+    }).collect();
+    assert_eq!(delays, vec![20, 20, 50, 80]);
+}
+
+}
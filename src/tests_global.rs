@@ -0,0 +1,125 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod global {
+
+use ActorAddress;
+use GLOBAL_NAME_CONFLICT;
+use Message;
+use MessageDatum;
+use MessageType;
+use global::{decode, Registry};
+use tests_cluster::cluster::{cluster, eventually};
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_registry() {
+    let (tx, _rx) = mpsc::channel();
+    let first = ActorAddress::new(tx.clone());
+    let second = ActorAddress::new(tx);
+    let mut a = Registry::new("a");
+    assert!(a.register("x", &first, 10));
+    assert!(!a.register("x", &second, 20));
+    assert!(a.register("y", &second, 20));
+    assert_eq!(a.names(), vec!["x".to_string(), "y".to_string()]);
+
+    let names = decode(&a.encode()).unwrap();
+    assert_eq!(names.len(), 2);
+    assert!(names.contains(&("x".to_string(), first.id().creation(), first.id().number(), 10)));
+    assert!(decode(&[0, 0, 0, 1, 0]).is_none());
+
+    // Names registered earlier win, whichever node they come from, and the
+    // local actors which lose are reported.
+    let lost = a.merge("b", vec![("x".to_string(), second.clone(), 5),
+                                 ("y".to_string(), first.clone(), 30),
+                                 ("z".to_string(), first.clone(), 30)]);
+    assert_eq!(lost.len(), 1);
+    assert_eq!(lost[0].0, "x");
+    assert_eq!(lost[0].1.id(), first.id());
+    assert_eq!(a.whereis("x").unwrap().id(), second.id());
+    assert_eq!(a.whereis("y").unwrap().id(), second.id());
+    assert_eq!(decode(&a.encode()).unwrap().len(), 1);
+
+    // Nodes can only unregister their own names, and the names of a node go
+    // away with it.
+    assert!(!a.unregister("x"));
+    assert!(a.unregister("y"));
+    a.remove_node("b");
+    assert!(a.names().is_empty());
+}
+
+#[test]
+fn test_global_names() {
+    let nodes = cluster("glob", 3);
+    let (tx, rx) = mpsc::channel();
+    let target = ActorAddress::new(tx);
+    assert!(nodes[0].register_global("service", &target));
+    assert!(eventually(|| nodes.iter().all(|n| n.whereis_global("service").is_some())));
+    for (i, n) in nodes.iter().enumerate() {
+        Message::custom(":hello").with_datum(MessageDatum::from(i as i64))
+                                 .send_to(&n.whereis_global("service").unwrap());
+        let msg = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(msg.get_datum().as_i64(), Some(i as i64));
+    }
+
+    // Names are unique across the cluster.
+    let (tx, _other_rx) = mpsc::channel();
+    let other = ActorAddress::new(tx);
+    assert!(!nodes[2].register_global("service", &other));
+    assert!(!nodes[1].register_global("remote", &nodes[1].whereis_global("service").unwrap()));
+
+    nodes[0].unregister_global("service");
+    assert!(eventually(|| nodes.iter().all(|n| n.global_names().is_empty())));
+    assert!(nodes[2].register_global("service", &other));
+    assert!(eventually(|| nodes.iter().all(|n| n.global_names() == vec!["service".to_string()])));
+
+    // The names of a node go away when it does.
+    nodes[2].shutdown();
+    assert!(eventually(|| nodes[..2].iter().all(|n| n.global_names().is_empty())));
+    for n in nodes[..2].iter() {
+        n.shutdown();
+    }
+}
+
+#[test]
+fn test_conflicts() {
+    let nodes = cluster("confl", 2);
+    nodes[0].partition(nodes[1].name());
+    assert!(eventually(|| nodes.iter().all(|n| n.peers().is_empty())));
+
+    // Both sides of the partition register the same name.
+    let (tx_a, rx_a) = mpsc::channel();
+    let first = ActorAddress::new(tx_a);
+    let (tx_b, rx_b) = mpsc::channel();
+    let second = ActorAddress::new(tx_b);
+    assert!(nodes[0].register_global("leader", &first));
+    thread::sleep(Duration::from_millis(10));
+    assert!(nodes[1].register_global("leader", &second));
+
+    // When the partition heals, the first registration wins, and the loser is
+    // told about it.
+    nodes[0].heal(nodes[1].name());
+    assert!(eventually(|| nodes.iter().all(|n| {
+        n.whereis_global("leader").is_some_and(|a| a.id().number() == first.id().number())
+    })));
+    let msg = rx_b.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(*msg.get_type(), MessageType::Custom(GLOBAL_NAME_CONFLICT));
+    assert_eq!(*msg.get_datum(), MessageDatum::from("leader"));
+    assert_eq!(msg.get_sender().id().number(), first.id().number());
+    Message::custom(":hello").send_to(&nodes[1].whereis_global("leader").unwrap());
+    assert_eq!(*rx_a.recv_timeout(Duration::from_secs(5)).unwrap().get_type(), MessageType::Custom(":hello"));
+    for n in nodes.iter() {
+        n.shutdown();
+    }
+}
+
+}
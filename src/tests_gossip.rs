@@ -0,0 +1,153 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod gossip {
+
+use MemberStatus;
+use Node;
+use gossip::{decode, Members};
+use tests_cluster::cluster::{eventually, nodes};
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn statuses(node: &Node) -> Vec<(String, MemberStatus)> {
+    node.members().iter().map(|m| (m.name().to_string(), m.status())).collect()
+}
+
+#[test]
+fn test_merge() {
+    let now = Instant::now();
+    let mut a = Members::new("a", "addr_a");
+    let mut b = Members::new("b", "addr_b");
+    assert!(a.merge(decode(&b.encode()).unwrap(), now));
+    assert!(!a.merge(decode(&b.encode()).unwrap(), now));
+    assert_eq!(a.list()[1].address(), "addr_b");
+    assert_eq!(a.to_connect(), vec![("b".to_string(), "addr_b".to_string())]);
+    assert!(b.to_connect().is_empty());
+
+    // A suspicion spreads, and is refuted by the suspect with a newer
+    // incarnation, which wins over it.
+    a.suspect("b", now);
+    assert!(b.merge(decode(&a.encode()).unwrap(), now));
+    assert_eq!(b.list()[1].incarnation(), 1);
+    assert!(a.merge(decode(&b.encode()).unwrap(), now));
+    assert_eq!(a.list()[1].status(), MemberStatus::Up);
+
+    // Suspects which are not refuted in time are declared down.
+    a.suspect("b", now);
+    a.expire(Duration::from_secs(1), now + Duration::from_millis(500));
+    assert_eq!(a.list()[1].status(), MemberStatus::Suspect);
+    a.expire(Duration::from_secs(1), now + Duration::from_secs(1));
+    assert_eq!(a.list()[1].status(), MemberStatus::Down);
+
+    // Leaving is final.
+    b.leave();
+    assert!(a.merge(decode(&b.encode()).unwrap(), now));
+    assert_eq!(a.list()[1].status(), MemberStatus::Left);
+    assert!(a.to_connect().is_empty());
+    assert!(decode(&[0, 0, 0, 1, 0]).is_none());
+}
+
+#[test]
+fn test_convergence() {
+    let nodes = nodes("conv", 8);
+    // Every node only knows about the first one.
+    for n in nodes.iter().skip(1) {
+        n.connect(nodes[0].address()).unwrap();
+    }
+    assert!(eventually(|| nodes.iter().all(|n| {
+        n.peers().len() == 7
+            && n.members().iter().all(|m| m.status() == MemberStatus::Up)
+            && n.members().len() == 8
+    })));
+    let addresses: Vec<String> = nodes[7].members().iter().map(|m| m.address().to_string()).collect();
+    assert_eq!(addresses, nodes.iter().map(|n| n.address().to_string()).collect::<Vec<_>>());
+    for n in nodes.iter() {
+        n.shutdown();
+    }
+}
+
+#[test]
+fn test_leave_and_suspect() {
+    let nodes = nodes("fail", 4);
+    for n in nodes.iter().skip(1) {
+        n.connect(nodes[0].address()).unwrap();
+    }
+    assert!(eventually(|| nodes.iter().all(|n| n.peers().len() == 3)));
+
+    // A node which leaves is not suspected.
+    nodes[3].leave();
+    assert!(eventually(|| nodes[..3].iter().all(|n| {
+        statuses(n)[3] == ("fail03".to_string(), MemberStatus::Left) && n.peers().len() == 2
+    })));
+
+    // A node which is cut off is suspected, then declared down.
+    for n in nodes[..2].iter() {
+        nodes[2].partition(n.name());
+    }
+    assert!(eventually(|| nodes[..2].iter().all(|n| statuses(n)[2].1 == MemberStatus::Down)));
+    assert!(eventually(|| statuses(&nodes[2])[0].1 == MemberStatus::Down));
+
+    // Once the partition heals, the nodes reconnect, and the node refutes its
+    // death.
+    for n in nodes[..2].iter() {
+        nodes[2].heal(n.name());
+    }
+    assert!(eventually(|| nodes[..3].iter().all(|n| {
+        n.peers().len() == 2 && statuses(n)[..3].iter().all(|s| s.1 == MemberStatus::Up)
+    })));
+    assert!(nodes[0].members()[2].incarnation() > 0);
+    for n in nodes[..3].iter() {
+        n.shutdown();
+    }
+}
+
+// Connects to a node as a node with the given name, without a cookie, and
+// waits until the node has taken the connection as its connection to it.
+fn fake_peer(node: &Node, name: &str) -> TcpStream {
+    let mut stream = TcpStream::connect(node.address()).unwrap();
+    let mut bytes = Vec::new();
+    for &(kind, payload) in [(0u8, name.as_bytes()), (6u8, &b""[..])].iter() {
+        bytes.extend_from_slice(&((payload.len() + 1) as u32).to_be_bytes());
+        bytes.push(kind);
+        bytes.extend_from_slice(payload);
+    }
+    stream.write_all(&bytes).unwrap();
+    // The node sends its members once it has taken the connection.
+    loop {
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len).unwrap();
+        let mut frame = vec![0u8; u32::from_be_bytes(len) as usize];
+        stream.read_exact(&mut frame).unwrap();
+        if frame[0] == 5 {
+            return stream;
+        }
+    }
+}
+
+#[test]
+fn test_dropped_connection() {
+    let node = Node::listen("dropped", "127.0.0.1:0").unwrap();
+    let first = fake_peer(&node, "dropped_peer");
+    let second = fake_peer(&node, "dropped_peer");
+
+    // When the peer drops the newer of two connections, the node goes back to
+    // the older one instead of losing the peer.
+    second.shutdown(Shutdown::Both).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(node.peers(), vec!["dropped_peer".to_string()]);
+    drop(first);
+    assert!(eventually(|| node.peers().is_empty()));
+    node.shutdown();
+}
+
+}
@@ -0,0 +1,116 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod group {
+
+use MessageType;
+use Message;
+use ActorAddress;
+use Actor;
+use ProcessGroups;
+use PubSub;
+use topic_matches;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const NEWS: &str = ":news";
+
+// Spawns an actor which reports every Custom message it receives, tagged with
+// the provided name.
+fn listener(name: &'static str, out: &mpsc::Sender<String>) -> ActorAddress {
+    let out = out.clone();
+    Actor::new().with_state(name)
+        .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(_)))
+        .with_action(move |msg, name, _| {
+            out.send(format!("{} {}", name, msg.get_datum().as_str().unwrap()))
+               .unwrap();
+            Ok(())
+        })
+        .spawn()
+}
+
+fn received(rx: &mpsc::Receiver<String>, count: usize) -> Vec<String> {
+    let mut lines: Vec<String> = (0..count).map(|_| rx.recv().unwrap()).collect();
+    lines.sort();
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    lines
+}
+
+#[test]
+fn test_broadcast() {
+    let (tx, rx) = mpsc::channel();
+    let groups = ProcessGroups::spawn();
+    let huey = listener("huey", &tx);
+    let dewey = listener("dewey", &tx);
+
+    groups.join("nephews", &huey);
+    groups.join("nephews", &dewey);
+    groups.join("nephews", &dewey);
+    groups.join("others", &dewey);
+    groups.broadcast("nephews", Message::custom(NEWS).with_str("hi"));
+    assert_eq!(received(&rx, 2), vec!["dewey hi", "huey hi"]);
+
+    groups.leave("nephews", &dewey);
+    groups.broadcast("nephews", Message::custom(NEWS).with_str("bye"));
+    assert_eq!(received(&rx, 1), vec!["huey bye"]);
+
+    // Members are removed from all their groups when they exit.
+    Message::shutdown().send_to(&dewey);
+    for _ in 0..50 {
+        if groups.members("others").is_empty() { break; }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(groups.which_groups(), vec!["nephews".to_string()]);
+
+    Message::shutdown().send_to(&huey);
+    groups.shutdown();
+}
+
+#[test]
+fn test_topic_matches() {
+    assert!(topic_matches("a/b/c", "a/b/c"));
+    assert!(!topic_matches("a/b/c", "a/b"));
+    assert!(!topic_matches("a/b", "a/b/c"));
+    assert!(topic_matches("a/+/c", "a/b/c"));
+    assert!(!topic_matches("a/+/c", "a/b/d"));
+    assert!(!topic_matches("a/+", "a/b/c"));
+    assert!(topic_matches("a/#", "a/b/c"));
+    assert!(topic_matches("a/#", "a"));
+    assert!(topic_matches("#", "a/b"));
+    assert!(!topic_matches("a/#/c", "a/b/c"));
+}
+
+#[test]
+fn test_publish() {
+    let (tx, rx) = mpsc::channel();
+    let pubsub = PubSub::spawn();
+    let kitchen = listener("kitchen", &tx);
+    let all = listener("all", &tx);
+
+    pubsub.subscribe("sensors/kitchen/+", &kitchen);
+    pubsub.subscribe("sensors/#", &all);
+    pubsub.subscribe("sensors/+/temperature", &all);
+
+    pubsub.publish("sensors/kitchen/temperature", Message::custom(NEWS).with_str("21"));
+    assert_eq!(received(&rx, 2), vec!["all 21", "kitchen 21"]);
+    pubsub.publish("sensors/garage/door", Message::custom(NEWS).with_str("open"));
+    assert_eq!(received(&rx, 1), vec!["all open"]);
+
+    pubsub.unsubscribe("sensors/#", &all);
+    pubsub.publish("sensors/garage/door", Message::custom(NEWS).with_str("closed"));
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+    Message::shutdown().send_to(&kitchen);
+    Message::shutdown().send_to(&all);
+    pubsub.shutdown();
+}
+
+}
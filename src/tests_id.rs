@@ -0,0 +1,78 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod id {
+
+use ActorAddress;
+use ActorId;
+use Codec;
+use MessageDatum;
+
+use std::collections::HashSet;
+use std::sync::mpsc;
+
+fn fake() -> ActorAddress {
+    let (tx, _) = mpsc::channel();
+    ActorAddress::new(tx)
+}
+
+#[test]
+fn test_display_and_parse() {
+    let a = fake();
+    let id = a.id().clone();
+    assert!(id.is_local());
+    assert_eq!(id.to_string(), format!("#{}.{}", id.creation(), id.number()));
+    assert_eq!(a.to_string(), id.to_string());
+    assert_eq!(id.to_string().parse::<ActorId>(), Ok(id.clone()));
+
+    // Node names can contain anything, including '#'.
+    let remote = id.qualified("b#1@host:4370");
+    assert_eq!(remote.node(), Some("b#1@host:4370"));
+    assert_eq!(remote.to_string().parse::<ActorId>(), Ok(remote.clone()));
+    assert!(remote != id);
+    assert_eq!(remote.qualified("c"), remote);
+
+    for bad in &["", "#", "a#1", "a#.1", "a#1.", "a#x.1", "a#1.-1", "a#99999999999.1"] {
+        assert!(bad.parse::<ActorId>().is_err(), "{:?} should not parse", bad);
+    }
+}
+
+#[test]
+fn test_ordering_and_hashing() {
+    let (a, b) = (fake(), fake());
+    assert!(a < b);
+    assert!(a.id() < &a.id().qualified("n"));
+    let set: HashSet<ActorId> = vec![a.id().clone(), b.id().clone(), a.clone().id().clone()]
+        .into_iter().collect();
+    assert_eq!(set.len(), 2);
+}
+
+#[test]
+fn test_codec_identity() {
+    let a = fake();
+    // A node writes local ids with its own name, and reads them back as local.
+    let node_a = Codec::new().with_node("a");
+    let bytes = node_a.encode_datum(&MessageDatum::from(&a));
+    let decoded = node_a.decode_datum(&bytes).unwrap().as_act().unwrap();
+    assert_eq!(decoded, a);
+
+    // Another node reads them as ids of node a, and writes them back as such.
+    let node_b = Codec::new().with_node("b");
+    let remote = node_b.decode_datum(&bytes).unwrap().as_act().unwrap();
+    assert_eq!(*remote.id(), a.id().qualified("a"));
+    let back = node_b.encode_datum(&MessageDatum::from(&remote));
+    assert_eq!(node_a.decode_datum(&back).unwrap().as_act().unwrap(), a);
+
+    // The JSON encoding uses the same form.
+    let json = MessageDatum::from(&remote).to_json();
+    assert_eq!(json, format!("{{\"$act\":\"{}\"}}", remote.id()));
+    assert_eq!(MessageDatum::from_json(&json).unwrap().as_act().unwrap(), remote);
+}
+
+}
@@ -0,0 +1,136 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod json {
+
+use MessageDatum;
+use ActorAddress;
+use JsonErrorKind;
+
+use std::collections::HashMap;
+use std::f64;
+use std::sync::{mpsc, Arc};
+
+fn map(entries: Vec<(&str, MessageDatum)>) -> MessageDatum {
+    let m: HashMap<String, MessageDatum> = entries.into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+    MessageDatum::from(m)
+}
+
+fn roundtrip(d: &MessageDatum) -> MessageDatum {
+    MessageDatum::from_json(&d.to_json()).unwrap()
+}
+
+#[test]
+fn test_json_roundtrip() {
+    let actor = ActorAddress::new(mpsc::channel().0);
+    let values = vec![
+        MessageDatum::Void,
+        MessageDatum::I64(-42),
+        MessageDatum::I64(i64::MIN),
+        MessageDatum::U64(42),
+        MessageDatum::U64(u64::MAX),
+        MessageDatum::F64(3.0),
+        MessageDatum::F64(-0.1),
+        MessageDatum::F64(1e300),
+        MessageDatum::F64(f64::INFINITY),
+        MessageDatum::F64(f64::NEG_INFINITY),
+        MessageDatum::from("quotes \" backslash \\ newline \n bell \u{7} snowman \u{2603}"),
+        MessageDatum::from(&actor),
+        MessageDatum::Bool(true),
+        MessageDatum::Bool(false),
+        MessageDatum::Nil,
+        MessageDatum::from(Some(1i64)),
+        MessageDatum::List(Arc::new([])),
+        MessageDatum::from(vec![MessageDatum::Nil, MessageDatum::from(vec![MessageDatum::Void])]),
+        MessageDatum::from((1i64, "two", 3.0)),
+        MessageDatum::Tuple(Arc::new([])),
+        map(vec![("$tuple", MessageDatum::List(Arc::new([])))]),
+        map(vec![]),
+        map(vec![("$u64", MessageDatum::I64(1))]),
+        map(vec![("$map", map(vec![("$act", MessageDatum::from("x"))]))]),
+        map(vec![("$map", MessageDatum::U64(1)), ("a", MessageDatum::Void)]),
+        map(vec![
+            ("name", MessageDatum::from("Louie")),
+            ("age", MessageDatum::I64(16)),
+            ("owner", map(vec![("act", MessageDatum::from(&actor))])),
+        ]),
+    ];
+    for v in &values {
+        assert_eq!(&roundtrip(v), v, "{}", v.to_json());
+    }
+    assert!(roundtrip(&MessageDatum::F64(f64::NAN)).as_f64().unwrap().is_nan());
+    for len in 0..8 {
+        let bytes: Vec<u8> = (0..len).map(|i| (i * 97) as u8).collect();
+        assert_eq!(roundtrip(&MessageDatum::from(bytes.clone())).as_bytes(), Some(bytes));
+    }
+
+    assert_eq!(MessageDatum::U64(7).to_json(), r#"{"$u64":7}"#);
+    assert_eq!(MessageDatum::from(&b"mecha"[..]).to_json(), r#"{"$bytes":"bWVjaGE="}"#);
+    assert_eq!(MessageDatum::from((true, None::<i64>)).to_json(),
+               r#"{"$tuple":[true,{"$nil":null}]}"#);
+    assert_eq!(map(vec![("b", MessageDatum::F64(2.0)), ("a", MessageDatum::I64(1))]).to_json(),
+               r#"{"a":1,"b":2.0}"#);
+}
+
+#[test]
+fn test_json_parsing() {
+    let parsed = MessageDatum::from_json(r#"
+        {
+            "server": { "host": "localhost", "port": 8080, "ratio": 0.5e1 },
+            "big": 18446744073709551615,
+            "escapes": "é😀\/",
+            "nothing": null,
+            "flags": [true, false, [] ]
+        }"#).unwrap();
+    assert_eq!(parsed, map(vec![
+        ("server", map(vec![
+            ("host", MessageDatum::from("localhost")),
+            ("port", MessageDatum::I64(8080)),
+            ("ratio", MessageDatum::F64(5.0)),
+        ])),
+        ("big", MessageDatum::U64(u64::MAX)),
+        ("escapes", MessageDatum::from("\u{e9}\u{1f600}/")),
+        ("nothing", MessageDatum::Void),
+        ("flags", MessageDatum::from(vec![MessageDatum::from(true), MessageDatum::from(false),
+                                          MessageDatum::List(Arc::new([]))])),
+    ]));
+}
+
+#[test]
+fn test_json_errors() {
+    let error = |json: &str| MessageDatum::from_json(json).unwrap_err();
+
+    let e = error(r#"{"server": {"host": "localhost", "port": 08}}"#);
+    assert_eq!(*e.kind(), JsonErrorKind::InvalidNumber);
+    assert_eq!(e.path(), "$.server.port");
+    assert_eq!(e.offset(), 41);
+
+    let e = error(r#"{"a": {"odd key": {"$u64": -1}}}"#);
+    assert_eq!(*e.kind(), JsonErrorKind::InvalidTag("$u64".to_string()));
+    assert_eq!(e.path(), r#"$.a["odd key"]["$u64"]"#);
+
+    let e = error(r#"{"flags": [true, false, nope]}"#);
+    assert_eq!(*e.kind(), JsonErrorKind::UnexpectedChar('n'));
+    assert_eq!(e.path(), "$.flags.2");
+    assert_eq!(*error(r#"{"$bytes": "abc"}"#).kind(),
+               JsonErrorKind::InvalidTag("$bytes".to_string()));
+    assert_eq!(*error(r#"[1, 2"#).kind(), JsonErrorKind::UnexpectedEnd);
+    assert_eq!(*error(r#"{"$act": "not an id"}"#).kind(),
+               JsonErrorKind::InvalidTag("$act".to_string()));
+    assert_eq!(*error(r#"{"a": 1"#).kind(), JsonErrorKind::UnexpectedEnd);
+    assert_eq!(*error(r#"{"a" 1}"#).kind(), JsonErrorKind::UnexpectedChar('1'));
+    assert_eq!(*error(r#""\x""#).kind(), JsonErrorKind::InvalidEscape);
+    assert_eq!(*error("1 2").kind(), JsonErrorKind::TrailingCharacters);
+    assert_eq!(*error(&"{\"a\":".repeat(100)).kind(), JsonErrorKind::TooDeep);
+    assert_eq!(*error(&"[".repeat(100)).kind(), JsonErrorKind::TooDeep);
+}
+
+}
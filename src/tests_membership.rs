@@ -0,0 +1,114 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod membership {
+
+use MessageType;
+use MessageDatum;
+use Message;
+use ActorAddress;
+use Actor;
+use Membership;
+use Node;
+use NODE_DOWN;
+use NODE_LOST;
+use NODE_UP;
+use membership::PhiAccrual;
+use tests_cluster::cluster::membership;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn expect(rx: &mpsc::Receiver<Message>, mt: MessageType, datum: MessageDatum) -> Message {
+    let msg = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(*msg.get_type(), mt);
+    assert_eq!(*msg.get_datum(), datum);
+    msg
+}
+
+#[test]
+fn test_phi_accrual() {
+    let settings = Membership::new().with_heartbeat_interval(Duration::from_millis(100))
+                                    .with_acceptable_pause(Duration::from_millis(0));
+    let start = Instant::now();
+    let mut detector = PhiAccrual::new(&settings, start);
+    for i in 1..=10 {
+        detector.heartbeat(start + Duration::from_millis(100 * i));
+    }
+    let last = start + Duration::from_millis(1000);
+
+    // Suspicion grows the longer the next heartbeat is overdue.
+    let on_time = detector.phi(last + Duration::from_millis(100));
+    let late = detector.phi(last + Duration::from_millis(200));
+    let very_late = detector.phi(last + Duration::from_millis(1000));
+    assert!(on_time < 1.0);
+    assert!(on_time < late && late < very_late);
+    assert!(very_late > settings.phi_threshold());
+
+    // A heartbeat clears the suspicion.
+    detector.heartbeat(last + Duration::from_millis(1000));
+    assert!(detector.phi(last + Duration::from_millis(1050)) < 1.0);
+}
+
+#[test]
+fn test_partition() {
+    let a = Node::listen("a_part", "127.0.0.1:0").unwrap().with_membership(membership());
+    let b = Node::listen("b_part", "127.0.0.1:0").unwrap().with_membership(membership());
+    let (wa_tx, wa_rx) = mpsc::channel();
+    let (wb_tx, wb_rx) = mpsc::channel();
+    a.watch(&ActorAddress::new(wa_tx));
+    b.watch(&ActorAddress::new(wb_tx));
+    a.connect(b.address()).unwrap();
+    expect(&wa_rx, MessageType::Custom(NODE_UP), MessageDatum::from("b_part"));
+    expect(&wb_rx, MessageType::Custom(NODE_UP), MessageDatum::from("a_part"));
+
+    let worker = Actor::new().with_match(|_, _: &()| false).spawn();
+    b.register("worker", &worker);
+    let remote = a.whereis("b_part", "worker").unwrap();
+    let (tx, rx) = mpsc::channel();
+    let me = ActorAddress::new(tx);
+    Message::link().with_sender(&me).send_to(&remote);
+
+    // Heartbeats keep the suspicion of the peers low.
+    thread::sleep(Duration::from_millis(300));
+    assert!(a.phi("b_part").unwrap() < 1.0);
+    assert!(b.phi("a_part").unwrap() < 1.0);
+    assert_eq!(a.peers(), vec!["b_part".to_string()]);
+
+    // Partitioning one side is enough for both to lose each other, and the
+    // linked actor is told its link has gone.
+    a.partition("b_part");
+    let msg = expect(&rx, MessageType::Exited, MessageDatum::from(NODE_LOST));
+    assert_eq!(msg.get_sender(), &remote);
+    expect(&wa_rx, MessageType::Custom(NODE_DOWN), MessageDatum::from("b_part"));
+    expect(&wb_rx, MessageType::Custom(NODE_DOWN), MessageDatum::from("a_part"));
+    assert!(a.peers().is_empty());
+    assert!(a.phi("b_part").is_none());
+
+    // Linking while the node is unreachable fails straight away.
+    Message::link().with_sender(&me).send_to(&remote);
+    expect(&rx, MessageType::Exited, MessageDatum::from(NODE_LOST));
+    assert!(a.connect(b.address()).is_err());
+
+    // Once healed, the nodes can connect again, and a link ends normally when
+    // the remote actor exits.
+    a.heal("b_part");
+    a.connect(b.address()).unwrap();
+    expect(&wa_rx, MessageType::Custom(NODE_UP), MessageDatum::from("b_part"));
+    Message::link().with_sender(&me).send_to(&remote);
+    Message::shutdown().send_to(&remote);
+    let msg = expect(&rx, MessageType::Exited, MessageDatum::Void);
+    assert_eq!(msg.get_sender(), &remote);
+
+    a.shutdown();
+    b.shutdown();
+}
+
+}
@@ -0,0 +1,144 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod node {
+
+use MessageType;
+use MessageDatum;
+use Message;
+use ActorAddress;
+use Actor;
+use Node;
+use Server;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const PING: &str = ":ping";
+const PONG: &str = ":pong";
+const NEXT: &str = ":next";
+
+fn pair(a: &str, b: &str) -> (Node, Node) {
+    let a = Node::listen(a, "127.0.0.1:0").unwrap();
+    let b = Node::listen(b, "127.0.0.1:0").unwrap();
+    assert_eq!(a.connect(b.address()).unwrap(), b.name());
+    (a, b)
+}
+
+// Replies to pings with a pong carrying the same datum.
+fn ponger() -> ActorAddress {
+    Actor::new()
+        .with_match(|msg, _| *msg.get_type() == MessageType::Custom(PING))
+        .with_action(|msg, _: &mut (), myself| {
+            Message::custom(PONG).with_sender(myself)
+                                 .with_datum(msg.get_datum().clone())
+                                 .send_to(msg.get_sender());
+            Ok(())
+        })
+        .spawn()
+}
+
+#[test]
+fn test_remote_send() {
+    let (a, b) = pair("a_send", "b_send");
+    let p = ponger();
+    b.register("ponger", &p);
+
+    // Both nodes run in this process, but the proxy refers to the actor on
+    // node b, not to the local one.
+    let remote = a.whereis("b_send", "ponger").unwrap();
+    assert_eq!(*remote.id(), p.id().qualified("b_send"));
+    assert!(remote != p);
+    assert_eq!(remote.node(), Some("b_send"));
+    assert!(a.whereis("b_send", "nobody").is_none());
+
+    let (tx, rx) = mpsc::channel();
+    let me = ActorAddress::new(tx);
+    Message::custom(PING).with_sender(&me).with_datum(MessageDatum::from((1i64, "one")))
+                         .send_to(&remote);
+    let msg = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(*msg.get_type(), MessageType::Custom(PONG));
+    assert_eq!(msg.get_sender(), &remote);
+    assert_eq!(*msg.get_datum(), MessageDatum::from((1i64, "one")));
+
+    // Addresses in datums are forwarded too, and resolve back to the local
+    // actor when they come back.
+    Message::custom(PING).with_sender(&me).with_act(&me).send_to(&remote);
+    let msg = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(msg.get_datum().as_act().unwrap(), me);
+    assert_eq!(msg.get_datum().as_act().unwrap().node(), None);
+
+    a.shutdown();
+    b.shutdown();
+}
+
+struct Counter { count: i64 }
+
+impl Server for Counter {
+    fn handle_call(&mut self, _: &Message, _: &ActorAddress) -> Result<MessageDatum, String> {
+        self.count += 1;
+        Ok(MessageDatum::from(self.count))
+    }
+}
+
+#[test]
+fn test_remote_call_and_link() {
+    let (a, b) = pair("a_call", "b_call");
+    let counter = Actor::from_server(Counter { count: 0 }).spawn();
+    a.register("counter", &counter);
+
+    // Looking up from the node which accepted the connection works too.
+    let remote = b.whereis("a_call", "counter").unwrap();
+    assert_eq!(remote.call(NEXT, MessageDatum::Void).unwrap().as_i64(), Some(1));
+    assert_eq!(remote.call(NEXT, MessageDatum::Void).unwrap().as_i64(), Some(2));
+
+    // Linking to a remote actor gets an Exited message back when it exits.
+    let (tx, rx) = mpsc::channel();
+    let me = ActorAddress::new(tx);
+    Message::link().with_sender(&me).send_to(&remote);
+    Message::shutdown().send_to(&remote);
+    let msg = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(*msg.get_type(), MessageType::Exited);
+    assert_eq!(*msg.get_sender().id(), counter.id().qualified("a_call"));
+
+    a.shutdown();
+    assert!(b.whereis_timeout("a_call", "counter", Duration::from_millis(200)).is_none());
+    b.shutdown();
+}
+
+#[test]
+fn test_proxies() {
+    let (a, b) = pair("a_proxies", "b_proxies");
+    b.register("ponger", &ponger());
+    let remote = a.whereis("b_proxies", "ponger").unwrap();
+
+    // Node b gets a proxy for each actor of node a which pings its ponger.
+    let (tx, rx) = mpsc::channel();
+    for i in 0..20 {
+        Message::custom(PING).with_sender(&ActorAddress::new(tx.clone()))
+                             .with_datum(MessageDatum::from(i as i64))
+                             .send_to(&remote);
+    }
+    assert_eq!(rx.iter().take(20).count(), 20);
+    assert!(b.proxies() >= 20);
+
+    // They are dropped when node a goes away.
+    a.shutdown();
+    for _ in 0..250 {
+        if b.proxies() == 0 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(b.proxies(), 0);
+    b.shutdown();
+}
+
+}
@@ -0,0 +1,134 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod pattern {
+
+use MessageType;
+use MessageDatum;
+use Message;
+use MessageBuilder;
+use ActorAddress;
+use Actor;
+use DatumKind;
+use DatumPattern;
+use Pattern;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+const CELEBRATE: &str = ":celebrate";
+const SCORE: &str = ":score";
+
+fn celebrate(name: &str, age: i64) -> MessageBuilder {
+    let mut map = HashMap::new();
+    map.insert("age".to_string(), MessageDatum::from(age));
+    map.insert("name".to_string(), MessageDatum::from(name));
+    map.insert("extra".to_string(), MessageDatum::Nil);
+    let mut builder = Message::custom(CELEBRATE);
+    builder.with_map(map);
+    builder
+}
+
+#[test]
+fn test_pattern_macro() {
+    let p = pattern!(Custom(CELEBRATE), { age: I64(_), name: Str(n) });
+    let b = p.matches(&celebrate("Louie", 16).build()).unwrap();
+    assert_eq!(b.str("n"), "Louie");
+    assert_eq!(b.get("age"), None);
+    assert!(p.matches(&Message::custom(CELEBRATE).with_str("Louie").build()).is_none());
+    assert!(p.matches(&Message::call(CELEBRATE).with_datum(
+        celebrate("Louie", 16).build().get_datum().clone()).build()).is_none());
+
+    // The macro builds the same pattern as the builder.
+    let built = Pattern::new()
+        .with_type(MessageType::Custom(CELEBRATE))
+        .with_datum(DatumPattern::Map(vec![
+            ("age".to_string(), DatumPattern::Kind(DatumKind::I64, Box::new(DatumPattern::Any))),
+            ("name".to_string(),
+             DatumPattern::Kind(DatumKind::Str, Box::new(DatumPattern::Bind("n".to_string())))),
+        ]));
+    assert_eq!(format!("{:?}", built), format!("{:?}", p));
+
+    // Literals, guards, nesting, lists and tuples.
+    let adult = pattern!(Custom(CELEBRATE), { age: I64(a), "name": "Louie" } if |b| b.i64("a") >= 18);
+    assert!(adult.matches(&celebrate("Louie", 16).build()).is_none());
+    assert!(adult.matches(&celebrate("Dewey", 18).build()).is_none());
+    assert_eq!(adult.matches(&celebrate("Louie", 18).build()).unwrap().i64("a"), 18);
+
+    let score = pattern!(Custom(SCORE), (Str(_), [I64(1), rest], Nil));
+    let msg = Message::custom(SCORE).with_tuple(vec![
+        MessageDatum::from("Huey"),
+        MessageDatum::from(vec![MessageDatum::from(1i64), MessageDatum::from(2.5)]),
+        MessageDatum::Nil
+    ]).build();
+    assert_eq!(score.matches(&msg).unwrap().get("rest"), Some(&MessageDatum::from(2.5)));
+
+    assert!(pattern!(_).matches(&msg).is_some());
+    assert!(pattern!(Shutdown).matches(&Message::shutdown().build()).is_some());
+    assert!(pattern!(_, Void).matches(&Message::shutdown().build()).is_some());
+    assert!(pattern!(_, Void).matches(&msg).is_none());
+}
+
+#[test]
+fn test_pattern_actor() {
+    let (tx, rx) = mpsc::channel();
+    let initiator = ActorAddress::new(tx);
+
+    let celebration = pattern!(Custom(CELEBRATE), { age: I64(age), name: Str(name) });
+    let worker = Actor::new().with_state(Vec::new())
+        .with_matcher(celebration)
+        .with_bound_action(|b, _, said: &mut Vec<String>, _| {
+            said.push(format!("Here's to another {} years, {}", b.i64("age"), b.str("name")));
+            Ok(())
+        })
+        .with_matcher(pattern!(Custom(SCORE)))
+        .with_action(|_, said, _| Err(said.join("; ")))
+        .spawn_link(&initiator);
+
+    Message::custom(CELEBRATE).with_str("Huey").send_to(&worker);
+    celebrate("Louie", 16).send_to(&worker);
+    celebrate("Dewey", 17).send_to(&worker);
+    Message::custom(SCORE).send_to(&worker);
+
+    let msg = rx.recv().unwrap();
+    assert_eq!(*msg.get_type(), MessageType::Exited);
+    assert_eq!(msg.get_datum().as_str_ref(),
+               Some("Here's to another 16 years, Louie; Here's to another 17 years, Dewey"));
+}
+
+#[test]
+fn test_single_match() {
+    let (tx, rx) = mpsc::channel();
+    let initiator = ActorAddress::new(tx);
+
+    // The guard runs once per message, and the actions get its bindings.
+    let guarded = Arc::new(AtomicUsize::new(0));
+    let counter = guarded.clone();
+    let adult = pattern!(Custom(CELEBRATE), { age: I64(a) } if move |b| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        b.i64("a") >= 18
+    });
+    let worker = Actor::new().with_state(0i64)
+        .with_matcher(adult)
+        .with_bound_action(|b, _, total: &mut i64, _| {
+            *total += b.i64("a");
+            Ok(())
+        })
+        .with_bound_action(|b, _, total, _| Err(format!("{} {}", b.i64("a"), total)))
+        .spawn_link(&initiator);
+
+    celebrate("Scrooge", 75).send_to(&worker);
+    let msg = rx.recv().unwrap();
+    assert_eq!(msg.get_datum().as_str_ref(), Some("75 75"));
+    assert_eq!(guarded.load(Ordering::SeqCst), 1);
+}
+
+}
@@ -0,0 +1,195 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod reliable {
+
+use Actor;
+use ActorAddress;
+use Atom;
+use Message;
+use MessageType;
+use Node;
+use ReliableChannel;
+use tests_cluster::cluster::{child_process, eventually, membership};
+
+use std::env;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The environment variable which tells the child receiver which node to join.
+const PARENT_VAR: &str = "MECHA_TEST_RELIABLE_PARENT";
+
+fn expect_in_order(rx: &mpsc::Receiver<Message>, count: i64) {
+    for i in 0..count {
+        let msg = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(*msg.get_type(), MessageType::Custom(":item"));
+        assert_eq!(msg.get_datum().as_i64(), Some(i));
+    }
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+}
+
+#[test]
+fn test_lossy_link() {
+    let (tx, rx) = mpsc::channel();
+    let receiver = ReliableChannel::receiver(&ActorAddress::new(tx));
+
+    // An actor in between drops a third of the messages, and sends another
+    // third twice.
+    let lossy = Actor::new()
+        .with_state(0u64)
+        .with_match(|_, _| true)
+        .with_action(move |msg, n, _| {
+            *n += 1;
+            for _ in 0..(*n % 3) {
+                receiver.deliver(msg.clone());
+            }
+            Ok(())
+        })
+        .spawn();
+    let channel = ReliableChannel::new(&lossy).with_timeout(Duration::from_millis(20)).spawn();
+    for i in 0..30 {
+        assert_eq!(channel.send(Message::custom(":item").with_i64(i)), Ok(i as u64 + 1));
+    }
+    expect_in_order(&rx, 30);
+    assert!(eventually(|| channel.acknowledged() == 30 && channel.in_flight() == 0));
+    assert_eq!(channel.sent(), 30);
+    channel.shutdown();
+}
+
+#[test]
+fn test_window() {
+    // Nothing is acknowledged without a receiver, so the window fills up.
+    let (tx, _rx) = mpsc::channel();
+    let nowhere = ActorAddress::new(tx);
+    let channel = ReliableChannel::new(&nowhere).with_window(3).spawn();
+    assert_eq!(channel.capacity(), 3);
+    for _ in 0..3 {
+        assert!(channel.send(Message::custom(":item").with_i64(0)).is_ok());
+    }
+    assert_eq!(channel.capacity(), 0);
+    assert!(channel.send(Message::custom(":item").with_i64(0)).is_err());
+    assert!(channel.send(&Message::shutdown()).is_err());
+    assert_eq!((channel.sent(), channel.acknowledged(), channel.in_flight()), (3, 0, 3));
+    channel.shutdown();
+}
+
+#[test]
+fn test_partition() {
+    let a = Node::listen("a_reliable", "127.0.0.1:0").unwrap().with_membership(membership());
+    let b = Node::listen("b_reliable", "127.0.0.1:0").unwrap().with_membership(membership());
+    let (tx, rx) = mpsc::channel();
+    b.register("inbox", &ReliableChannel::receiver(&ActorAddress::new(tx)));
+    a.connect(b.address()).unwrap();
+    let inbox = a.whereis("b_reliable", "inbox").unwrap();
+    let channel = ReliableChannel::new(&inbox).with_timeout(Duration::from_millis(50)).spawn();
+    for i in 0..5 {
+        channel.send(Message::custom(":item").with_i64(i)).unwrap();
+    }
+    assert!(eventually(|| channel.acknowledged() == 5));
+
+    // The messages sent while the nodes cannot reach each other are delivered
+    // once they reconnect, exactly once and in order.
+    a.partition("b_reliable");
+    assert!(eventually(|| a.peers().is_empty()));
+    for i in 5..10 {
+        channel.send(Message::custom(":item").with_i64(i)).unwrap();
+    }
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(channel.acknowledged(), 5);
+    a.heal("b_reliable");
+    assert!(eventually(|| channel.acknowledged() == 10));
+    expect_in_order(&rx, 10);
+    channel.shutdown();
+    a.shutdown();
+    b.shutdown();
+}
+
+#[test]
+fn test_redirect() {
+    let (tx, _rx) = mpsc::channel();
+    let first = ReliableChannel::receiver(&ActorAddress::new(tx));
+    let channel = ReliableChannel::new(&first).with_timeout(Duration::from_millis(20)).spawn();
+    for i in 0..3 {
+        channel.send(Message::custom(":item").with_i64(i)).unwrap();
+    }
+    assert!(eventually(|| channel.acknowledged() == 3));
+
+    // A new receiver does not know the channel, and starts from the oldest
+    // message which has not been acknowledged.
+    Message::shutdown().send_to(&first);
+    for i in 3..6 {
+        channel.send(Message::custom(":item").with_i64(i)).unwrap();
+    }
+    let (tx, rx) = mpsc::channel();
+    channel.redirect(&ReliableChannel::receiver(&ActorAddress::new(tx)));
+    for i in 3..6 {
+        let msg = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(msg.get_datum().as_i64(), Some(i));
+    }
+    assert!(eventually(|| channel.acknowledged() == 6));
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    channel.shutdown();
+}
+
+// Runs in the child process started by test_child_process: forwards the items
+// it receives reliably to the collector of the parent, until the parent node
+// goes away.
+#[test]
+#[ignore]
+fn child_receiver() {
+    let parent = match env::var(PARENT_VAR) {
+        Ok(parent) => parent,
+        Err(_) => { return; }
+    };
+    // The child only receives items, so it declares them.
+    Atom::from_static(":item");
+    let node = Node::listen("xrel_child", "127.0.0.1:0").unwrap().with_membership(membership());
+    node.connect(&parent).unwrap();
+    let collector = node.whereis("xrel_parent", "collector").unwrap();
+    let consumer = Actor::new()
+        .with_match(|_, _| true)
+        .with_action(move |msg, _: &mut (), _| {
+            collector.deliver(msg.clone());
+            Ok(())
+        })
+        .spawn();
+    node.register("inbox", &ReliableChannel::receiver(&consumer));
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while !node.peers().is_empty() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(20));
+    }
+    node.shutdown();
+}
+
+#[test]
+fn test_child_process() {
+    let node = Node::listen("xrel_parent", "127.0.0.1:0").unwrap().with_membership(membership());
+    let (tx, rx) = mpsc::channel();
+    node.register("collector", &ActorAddress::new(tx));
+    let mut child = child_process("tests_reliable::reliable::child_receiver", &[(PARENT_VAR, node.address())]);
+    assert!(eventually(|| node.whereis("xrel_child", "inbox").is_some()));
+    let inbox = node.whereis("xrel_child", "inbox").unwrap();
+
+    // The child does not know the first message type: the message is dropped,
+    // but acknowledged, and the items after it go through.
+    let channel = ReliableChannel::new(&inbox).with_timeout(Duration::from_millis(50)).spawn();
+    channel.send(&Message::custom(":mystery")).unwrap();
+    for i in 0..10 {
+        channel.send(Message::custom(":item").with_i64(i)).unwrap();
+    }
+    expect_in_order(&rx, 10);
+    assert!(eventually(|| channel.acknowledged() == 11));
+
+    channel.shutdown();
+    node.shutdown();
+    assert!(child.wait().unwrap().success());
+}
+
+}
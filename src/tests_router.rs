@@ -0,0 +1,179 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod router {
+
+use MessageType;
+use MessageDatum;
+use Message;
+use ActorAddress;
+use Actor;
+use Stateless;
+use Router;
+use RoutingStrategy;
+use Autoscaling;
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const WORK: &str = ":work";
+const CRASH: &str = ":crash";
+const SLOW: &str = ":slow";
+
+// Builds a worker which answers every WORK message by sending its own address
+// back to the sender, and fails on CRASH messages.
+fn worker() -> Actor<Stateless> {
+    Actor::new().with_state(Stateless)
+        .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(WORK)))
+        .with_action(|msg, _, myself| {
+            Message::custom(WORK).with_sender(myself)
+                                 .with_datum(msg.get_datum().clone())
+                                 .send_to(msg.get_sender());
+            Ok(())
+        })
+        .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(CRASH)))
+        .with_action(|_, _, _| Err("Crashed".to_string()))
+}
+
+fn handled_by(router: &ActorAddress, datum: MessageDatum) -> ActorAddress {
+    let (tx, rx) = mpsc::channel();
+    let initiator = ActorAddress::new(tx);
+    Message::custom(WORK).with_sender(&initiator).with_datum(datum).send_to(router);
+    rx.recv().unwrap().get_sender().clone()
+}
+
+#[test]
+fn test_round_robin() {
+    let router = Router::new(3, worker).spawn();
+    let workers = Router::workers(&router).unwrap();
+    assert_eq!(workers.len(), 3);
+    for i in 0..6 {
+        assert_eq!(handled_by(&router, MessageDatum::Void), workers[i % 3]);
+    }
+    Message::shutdown().send_to(&router);
+}
+
+#[test]
+fn test_consistent_hash() {
+    let router = Router::new(4, worker)
+        .with_strategy(RoutingStrategy::ConsistentHash("user"))
+        .spawn();
+    let request = |user: &str, n: i64| {
+        let mut m = HashMap::new();
+        m.insert("user".to_string(), MessageDatum::from(user));
+        m.insert("n".to_string(), MessageDatum::from(n));
+        MessageDatum::from(m)
+    };
+    let mut seen = Vec::new();
+    for user in ["huey", "dewey", "louie", "scrooge", "donald"].iter() {
+        let first = handled_by(&router, request(user, 0));
+        for n in 1..5 {
+            assert_eq!(handled_by(&router, request(user, n)), first);
+        }
+        if !seen.contains(&first) {
+            seen.push(first);
+        }
+    }
+    // Different keys are spread across the pool.
+    assert!(seen.len() > 1);
+    Message::shutdown().send_to(&router);
+}
+
+#[test]
+fn test_replace_and_resize() {
+    let (tx, rx) = mpsc::channel();
+    let initiator = ActorAddress::new(tx);
+    let router = Router::new(2, worker)
+        .with_strategy(RoutingStrategy::SmallestMailbox)
+        .spawn_link(&initiator);
+    let before = Router::workers(&router).unwrap();
+
+    // A crashing worker is replaced, the router survives.
+    Message::custom(CRASH).send_to(&router);
+    let mut after = Router::workers(&router).unwrap();
+    for _ in 0..50 {
+        if after != before { break; }
+        thread::sleep(Duration::from_millis(10));
+        after = Router::workers(&router).unwrap();
+    }
+    assert_eq!(after.len(), 2);
+    assert_eq!(after.iter().filter(|w| before.contains(w)).count(), 1);
+
+    Router::resize(&router, 5);
+    assert_eq!(Router::workers(&router).unwrap().len(), 5);
+    Router::resize(&router, 1);
+    let workers = Router::workers(&router).unwrap();
+    assert_eq!(workers.len(), 1);
+    assert_eq!(handled_by(&router, MessageDatum::Void), workers[0]);
+
+    Message::shutdown().send_to(&router);
+    assert_eq!(*rx.recv().unwrap().get_type(), MessageType::Exited);
+}
+
+#[test]
+fn test_mailbox_stats() {
+    let (tx, rx) = mpsc::channel();
+    let initiator = ActorAddress::new(tx);
+    let worker = slow_worker().spawn_link(&initiator);
+    for _ in 0..5 {
+        Message::custom(SLOW).send_to(&worker);
+    }
+    assert!(worker.mailbox_len() >= 4);
+    Message::shutdown().send_to(&worker);
+    assert_eq!(*rx.recv().unwrap().get_type(), MessageType::Exited);
+    assert!(worker.mailbox_latency() >= Duration::from_millis(20));
+}
+
+fn slow_worker() -> Actor<Stateless> {
+    Actor::new().with_state(Stateless)
+        .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(SLOW)))
+        .with_action(|_, _, _| {
+            thread::sleep(Duration::from_millis(20));
+            Ok(())
+        })
+}
+
+fn wait_for_size(router: &ActorAddress, check: &dyn Fn(usize) -> bool) -> usize {
+    let mut size = 0;
+    for _ in 0..200 {
+        size = Router::workers(router).unwrap().len();
+        if check(size) { break; }
+        thread::sleep(Duration::from_millis(10));
+    }
+    size
+}
+
+#[test]
+fn test_autoscaling() {
+    let router = Router::new(1, slow_worker)
+        .with_strategy(RoutingStrategy::SmallestMailbox)
+        .with_autoscaling(Autoscaling::new(1, 3)
+                              .with_mailbox_threshold(2.0)
+                              .with_interval(Duration::from_millis(10))
+                              .with_cooldown(Duration::from_millis(30)))
+        .spawn();
+    assert_eq!(Router::workers(&router).unwrap().len(), 1);
+
+    // Keep the pool busy: it grows up to its maximum size, and no further.
+    for _ in 0..60 {
+        Message::custom(SLOW).send_to(&router);
+    }
+    assert_eq!(wait_for_size(&router, &|size| size == 3), 3);
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(Router::workers(&router).unwrap().len(), 3);
+
+    // Once everything has been processed, the pool shrinks back.
+    assert_eq!(wait_for_size(&router, &|size| size == 1), 1);
+
+    Message::shutdown().send_to(&router);
+}
+
+}
@@ -0,0 +1,113 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod server {
+
+use MessageType;
+use MessageDatum;
+use Message;
+use ActorAddress;
+use Actor;
+use Server;
+
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+const COUNT: &str = ":count";
+const RESET: &str = ":reset";
+const BOOM: &str = ":boom";
+const CHATTER: &str = ":chatter";
+
+// This is the Server version of the CounterApi in the chapter 5 tests.
+struct Counter { count: i64, terminated: mpsc::Sender<i64> }
+
+impl Server for Counter {
+    fn handle_call(&mut self, msg: &Message, _: &ActorAddress) -> Result<MessageDatum, String> {
+        match *msg.get_type() {
+            MessageType::Call(COUNT) => {
+                self.count += 1;
+                Ok(MessageDatum::from(self.count))
+            },
+            _ => Err("Kaboom".to_string())
+        }
+    }
+
+    fn handle_cast(&mut self, msg: &Message, _: &ActorAddress) -> Result<(), String> {
+        if let MessageType::Custom(RESET) = *msg.get_type() {
+            self.count = msg.get_datum().as_i64().unwrap_or(0);
+        }
+        Ok(())
+    }
+
+    fn terminate(&mut self, _: &MessageDatum) {
+        self.terminated.send(self.count).unwrap();
+    }
+}
+
+#[test]
+fn test_call_cast() {
+    let (tx, rx) = mpsc::channel();
+    let initiator = ActorAddress::new(tx);
+    let (ttx, trx) = mpsc::channel();
+
+    let counter = Actor::from_server(Counter { count: 0, terminated: ttx })
+        .spawn_link(&initiator);
+
+    assert_eq!(counter.call(COUNT, MessageDatum::Void).unwrap().as_i64(), Some(1));
+    assert_eq!(counter.call(COUNT, MessageDatum::Void).unwrap().as_i64(), Some(2));
+    counter.cast(RESET, MessageDatum::from(10i64));
+    assert_eq!(counter.call(COUNT, MessageDatum::Void).unwrap().as_i64(), Some(11));
+
+    Message::shutdown().send_to(&counter);
+
+    let msg = rx.recv().unwrap();
+    assert_eq!(*msg.get_type(), MessageType::Exited);
+    assert!(msg.get_datum().as_str().is_none());
+    assert_eq!(trx.recv().unwrap(), 11);
+}
+
+#[test]
+fn test_failing_call() {
+    let (tx, rx) = mpsc::channel();
+    let initiator = ActorAddress::new(tx);
+    let (ttx, _trx) = mpsc::channel();
+
+    let counter = Actor::from_server(Counter { count: 0, terminated: ttx })
+        .spawn_link(&initiator);
+
+    assert_eq!(counter.call(BOOM, MessageDatum::Void).err(), Some("Kaboom".to_string()));
+
+    let msg = rx.recv().unwrap();
+    assert_eq!(*msg.get_type(), MessageType::Exited);
+    assert_eq!(msg.get_datum().as_str(), Some("Kaboom".to_string()));
+    assert!(counter.call_timeout(COUNT, MessageDatum::Void, Duration::from_millis(100)).is_err());
+}
+
+#[test]
+fn test_call_deadline() {
+    // The server never replies, but keeps sending other messages to the
+    // caller, which must not extend the call.
+    let chatty = Actor::new()
+        .with_match(|msg, _: &()| matches!(*msg.get_type(), MessageType::Call(_)))
+        .with_action(|msg, _, myself| {
+            for i in 1..=20 {
+                Message::custom(CHATTER).with_sender(myself)
+                                        .send_after(msg.get_sender(), Duration::from_millis(50 * i));
+            }
+            Ok(())
+        })
+        .spawn();
+
+    let start = Instant::now();
+    assert!(chatty.call_timeout(COUNT, MessageDatum::Void, Duration::from_millis(200)).is_err());
+    assert!(start.elapsed() < Duration::from_millis(500));
+    Message::shutdown().send_to(&chatty);
+}
+
+}
@@ -0,0 +1,183 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod sharding {
+
+use Actor;
+use Atom;
+use ActorAddress;
+use Message;
+use MessageDatum;
+use MessageType;
+use Node;
+use Sharding;
+use tests_cluster::cluster::{child_process, cluster, eventually, membership};
+
+use std::collections::HashMap;
+use std::env;
+use std::slice;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The environment variable which tells the child region which node to join.
+const PARENT_VAR: &str = "MECHA_TEST_SHARDING_PARENT";
+
+#[derive(Default)]
+struct Counter {
+    id: String,
+    count: i64,
+}
+
+// Regions of counters, which answer each increment with their id and count.
+fn counters(passivation: Option<Duration>) -> Sharding {
+    Sharding::new("counter", |id| {
+        Actor::new()
+            .with_state(Counter { id: id.to_string(), count: 0 })
+            .with_match(|msg, _| matches!(*msg.get_type(), MessageType::Custom(":incr")))
+            .with_action(|msg, state, myself| {
+                state.count += 1;
+                Message::custom(":count").with_sender(myself)
+                                         .with_tuple(vec![MessageDatum::from(&state.id[..]),
+                                                          MessageDatum::from(state.count)])
+                                         .send_to(msg.get_sender());
+                Ok(())
+            })
+    }).with_shards(30).with_passivation(passivation).with_interval(Duration::from_millis(20))
+}
+
+// Waits until every node knows the given number of regions, and the regions
+// have had the time to notice.
+fn settle(nodes: &[Node], regions: usize) {
+    assert!(eventually(|| nodes.iter().all(|n| n.global_names().len() == regions)));
+    thread::sleep(Duration::from_millis(100));
+}
+
+// Increments every counter once, through the regions in turn, and returns the
+// counts.
+fn increment(regions: &[ActorAddress], ids: &[String]) -> HashMap<String, i64> {
+    let (tx, rx) = mpsc::channel();
+    let me = ActorAddress::new(tx);
+    for (i, id) in ids.iter().enumerate() {
+        let mut datum = HashMap::new();
+        datum.insert("id".to_string(), MessageDatum::from(&id[..]));
+        Message::custom(":incr").with_sender(&me).with_map(datum).send_to(&regions[i % regions.len()]);
+    }
+    (0..ids.len()).map(|_| {
+        let msg = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        let reply = msg.get_datum().as_tuple().unwrap();
+        (reply[0].as_str().unwrap(), reply[1].as_i64().unwrap())
+    }).collect()
+}
+
+#[test]
+fn test_sharding() {
+    let mut nodes = cluster("shard", 3);
+    let mut regions: Vec<ActorAddress> = nodes.iter().map(|n| counters(None).spawn(n)).collect();
+    settle(&nodes, 3);
+    let ids: Vec<String> = (0..60).map(|i| format!("user{}", i)).collect();
+
+    // Every entity lives on one node, whichever region its messages go
+    // through, and the entities are spread across the nodes.
+    for round in 1..4 {
+        assert!(increment(&regions, &ids).values().all(|&count| count == round));
+    }
+    let hosted: Vec<Vec<String>> = regions.iter().map(|r| Sharding::entities(r).unwrap()).collect();
+    assert!(hosted.iter().all(|h| !h.is_empty()));
+    assert_eq!(hosted.iter().map(|h| h.len()).sum::<usize>(), ids.len());
+
+    // When a node goes away, only its entities move, and they start over.
+    nodes.pop().unwrap().shutdown();
+    regions.pop();
+    settle(&nodes, 2);
+    for (id, count) in increment(&regions, &ids) {
+        assert_eq!(count, if hosted[2].contains(&id) { 1 } else { 4 }, "{}", id);
+    }
+
+    // When a node joins, it takes over some of the shards, and the entities of
+    // those shards are shut down on their old nodes.
+    let joining = Node::listen("shard03", "127.0.0.1:0").unwrap().with_membership(membership());
+    joining.connect(nodes[0].address()).unwrap();
+    regions.push(counters(None).spawn(&joining));
+    nodes.push(joining);
+    settle(&nodes, 3);
+    assert!(regions[..2].iter().map(|r| Sharding::entities(r).unwrap().len()).sum::<usize>() < ids.len());
+    let counts = increment(&regions, &ids);
+    let moved = Sharding::entities(&regions[2]).unwrap();
+    assert!(!moved.is_empty());
+    for (id, count) in counts {
+        assert_eq!(count == 1, moved.contains(&id), "{}", id);
+    }
+    for n in nodes.iter() {
+        n.shutdown();
+    }
+}
+
+#[test]
+fn test_passivation() {
+    let node = Node::listen("passivate", "127.0.0.1:0").unwrap();
+    let region = [counters(Some(Duration::from_millis(100))).spawn(&node)];
+    let ids = vec!["a".to_string(), "b".to_string()];
+    increment(&region, &ids);
+    assert_eq!(increment(&region, &ids)["a"], 2);
+    assert_eq!(Sharding::entities(&region[0]).unwrap(), ids);
+
+    // Messages without an entity id are dropped.
+    Message::custom(":incr").with_bool(true).send_to(&region[0]);
+
+    // Idle entities are passivated, and spawned again on demand.
+    assert!(eventually(|| Sharding::entities(&region[0]).unwrap().is_empty()));
+    assert_eq!(increment(&region, &ids)["a"], 1);
+    node.shutdown();
+}
+
+// Runs in the child process started by test_child_process: hosts a region of
+// counters until the parent node goes away.
+#[test]
+#[ignore]
+fn child_region() {
+    let parent = match env::var(PARENT_VAR) {
+        Ok(parent) => parent,
+        Err(_) => { return; }
+    };
+    // The counters only receive increments, so the child declares them.
+    Atom::from_static(":incr");
+    let node = Node::listen("xshard_child", "127.0.0.1:0").unwrap().with_membership(membership());
+    counters(None).spawn(&node);
+    node.connect(&parent).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while !node.peers().is_empty() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(20));
+    }
+    node.shutdown();
+}
+
+#[test]
+fn test_child_process() {
+    // The parent only receives counts.
+    Atom::from_static(":count");
+    let node = Node::listen("xshard_parent", "127.0.0.1:0").unwrap().with_membership(membership());
+    let region = [counters(None).spawn(&node)];
+    let mut child = child_process("tests_sharding::sharding::child_region", &[(PARENT_VAR, node.address())]);
+    settle(slice::from_ref(&node), 2);
+
+    // The messages for the entities of the child go through the region of the
+    // parent, and the child region tells which entities it hosts.
+    let ids: Vec<String> = (0..20).map(|i| format!("user{}", i)).collect();
+    assert!(increment(&region, &ids).values().all(|&count| count == 1));
+    let remote = node.whereis_global(":mecha_sharding/counter/xshard_child").unwrap();
+    let hosted = Sharding::entities(&remote).unwrap();
+    assert!(!hosted.is_empty());
+    assert_eq!(hosted.len() + Sharding::entities(&region[0]).unwrap().len(), ids.len());
+
+    node.shutdown();
+    assert!(child.wait().unwrap().success());
+}
+
+}
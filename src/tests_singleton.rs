@@ -0,0 +1,127 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod singleton {
+
+use Actor;
+use ActorAddress;
+use Message;
+use MessageDatum;
+use MessageType;
+use Singleton;
+use tests_cluster::cluster::{cluster, eventually};
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+// A singleton which answers pings with a pong, from itself, carrying the datum
+// of the ping.
+fn pinger(name: &str) -> Singleton {
+    Singleton::new(name, || {
+        Actor::new()
+            .with_match(|msg, _: &()| matches!(*msg.get_type(), MessageType::Custom(":ping")))
+            .with_action(|msg, _, myself| {
+                Message::custom(":pong").with_sender(myself)
+                                        .with_datum(msg.get_datum().clone())
+                                        .send_to(msg.get_sender());
+                Ok(())
+            })
+    }).with_interval(Duration::from_millis(20))
+}
+
+fn ping(proxy: &ActorAddress, me: &ActorAddress, rx: &mpsc::Receiver<Message>, i: i64) -> Message {
+    Message::custom(":ping").with_sender(me).with_datum(MessageDatum::from(i)).send_to(proxy);
+    let msg = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(*msg.get_datum(), MessageDatum::from(i));
+    msg
+}
+
+#[test]
+fn test_leader() {
+    let nodes = cluster("lead", 3);
+    assert!(eventually(|| nodes.iter().all(|n| n.leader() == Some("lead00".to_string()))));
+    nodes[0].shutdown();
+    assert!(eventually(|| nodes[1..].iter().all(|n| n.leader() == Some("lead01".to_string()))));
+    for n in nodes[1..].iter() {
+        n.shutdown();
+    }
+}
+
+#[test]
+fn test_singleton() {
+    let nodes = cluster("single", 3);
+    let proxies: Vec<ActorAddress> = nodes.iter().map(|n| pinger("pinger").spawn(n)).collect();
+
+    // The instance runs on the leader, and is reachable through every proxy.
+    assert!(eventually(|| nodes.iter().all(|n| n.whereis_global("pinger").is_some())));
+    let instance = nodes[0].whereis_global("pinger").unwrap();
+    assert!(instance.node().is_none());
+    let (tx, rx) = mpsc::channel();
+    let me = ActorAddress::new(tx);
+    for (i, proxy) in proxies.iter().enumerate() {
+        let pong = ping(proxy, &me, &rx, i as i64);
+        assert_eq!(pong.get_sender().id().number(), instance.id().number());
+    }
+
+    // An instance which exits is restarted on the leader.
+    Message::shutdown().send_to(&instance);
+    assert!(eventually(|| nodes[0].whereis_global("pinger").is_some_and(|i| i != instance)));
+
+    // When its node fails, the instance moves to the new leader.
+    nodes[0].shutdown();
+    assert!(eventually(|| nodes[1].whereis_global("pinger").is_some_and(|i| i.node().is_none())));
+    assert!(eventually(|| {
+        match (nodes[1].whereis_global("pinger"), nodes[2].whereis_global("pinger")) {
+            (Some(a), Some(b)) => a.id().number() == b.id().number(),
+            _ => false
+        }
+    }));
+    let instance = nodes[1].whereis_global("pinger").unwrap();
+    for (i, proxy) in proxies[1..].iter().enumerate() {
+        let pong = ping(proxy, &me, &rx, i as i64);
+        assert_eq!(pong.get_sender().id().number(), instance.id().number());
+    }
+    for n in nodes[1..].iter() {
+        n.shutdown();
+    }
+}
+
+#[test]
+fn test_buffering() {
+    let nodes = cluster("buffer", 2);
+    assert!(eventually(|| nodes.iter().all(|n| n.leader() == Some("buffer00".to_string()))));
+
+    // Only the leader starts the instance, so until it has a manager, the
+    // messages sent to the other proxy are buffered.
+    let proxy = pinger("buffered").spawn(&nodes[1]);
+    let unbuffered = pinger("buffered").with_buffer_size(0).spawn(&nodes[1]);
+    let (tx, rx) = mpsc::channel();
+    let me = ActorAddress::new(tx);
+    Message::custom(":ping").with_sender(&me).with_datum(MessageDatum::from(-1i64)).send_to(&unbuffered);
+    for i in 0..10 {
+        Message::custom(":ping").with_sender(&me).with_datum(MessageDatum::from(i as i64)).send_to(&proxy);
+    }
+    assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+    assert!(nodes[1].whereis_global("buffered").is_none());
+
+    pinger("buffered").spawn(&nodes[0]);
+    for i in 0..10 {
+        let msg = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(*msg.get_datum(), MessageDatum::from(i as i64));
+    }
+
+    // Without a buffer, the messages sent while there was no instance are
+    // dropped.
+    ping(&unbuffered, &me, &rx, 10);
+    for n in nodes.iter() {
+        n.shutdown();
+    }
+}
+
+}
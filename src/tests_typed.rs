@@ -0,0 +1,153 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod typed {
+
+use MessageType;
+use MessageDatum;
+use Message;
+use ActorAddress;
+use Actor;
+use Atom;
+use ProcessGroups;
+use TypedAddress;
+use TypedMessage;
+
+use std::sync::mpsc;
+
+const GREET: &str = ":greet";
+const CELEBRATE: &str = ":celebrate";
+
+#[derive(Debug, PartialEq)]
+enum Greeting {
+    Greet(String),
+    Celebrate { name: String, age: i64 }
+}
+
+impl TypedMessage for Greeting {
+    fn message_type(&self) -> &'static str {
+        match *self {
+            Greeting::Greet(_) => GREET,
+            Greeting::Celebrate { .. } => CELEBRATE
+        }
+    }
+
+    fn to_datum(&self) -> MessageDatum {
+        match *self {
+            Greeting::Greet(ref name) => MessageDatum::from(name.as_str()),
+            Greeting::Celebrate { ref name, age } => MessageDatum::from((name.as_str(), age))
+        }
+    }
+
+    fn from_datum(mt: &str, datum: &MessageDatum) -> Option<Greeting> {
+        match mt {
+            GREET => datum.as_str().map(Greeting::Greet),
+            CELEBRATE => {
+                let t = datum.as_tuple_ref()?;
+                Some(Greeting::Celebrate {
+                    name: t.first()?.as_str()?,
+                    age: t.get(1)?.as_i64()?
+                })
+            },
+            _ => None
+        }
+    }
+}
+
+// A message which no test builds, so that its type is only declared by the
+// actors handling it.
+struct Shout;
+
+impl TypedMessage for Shout {
+    fn message_type(&self) -> &'static str { ":typed_shout" }
+    fn to_datum(&self) -> MessageDatum { MessageDatum::Void }
+    fn from_datum(mt: &str, _: &MessageDatum) -> Option<Shout> {
+        if mt == ":typed_shout" { Some(Shout) } else { None }
+    }
+    fn message_types() -> &'static [&'static str] { &[":typed_shout"] }
+}
+
+// Greets by sending a string to the reply actor.
+fn greeter(reply_to: &ActorAddress) -> TypedAddress<Greeting> {
+    Actor::new()
+        .with_state(Some(reply_to.clone()))
+        .with_typed_handler(|msg: Greeting, reply_to: &mut Option<ActorAddress>, myself| {
+            let reply = match msg {
+                Greeting::Greet(name) => format!("Hello {}", name),
+                Greeting::Celebrate { name, age } => format!("Happy {} {}", age, name)
+            };
+            Message::custom(GREET).with_sender(myself).with_str(&reply)
+                .send_to(reply_to.as_ref().unwrap());
+            Ok(())
+        })
+        .spawn_typed()
+}
+
+#[test]
+fn test_typed_send() {
+    let (tx, rx) = mpsc::channel();
+    let initiator = ActorAddress::new(tx);
+
+    let g = greeter(&initiator);
+    g.send(Greeting::Celebrate { name: "Louie".to_string(), age: 16 });
+    let msg = rx.recv().unwrap();
+    assert_eq!(*msg.get_type(), MessageType::Custom(GREET));
+    assert_eq!(msg.get_sender(), g.address());
+    assert_eq!(msg.get_datum().as_str(), Some("Happy 16 Louie".to_string()));
+
+    // Typed addresses can be linked like untyped ones.
+    Message::link().with_sender(&initiator).send_to(g.address());
+    Message::shutdown().send_to(g.as_ref());
+    assert_eq!(*rx.recv().unwrap().get_type(), MessageType::Exited);
+}
+
+#[test]
+fn test_untyped_messages_are_ignored() {
+    let (tx, rx) = mpsc::channel();
+    let initiator = ActorAddress::new(tx);
+
+    let g = greeter(&initiator);
+    // A malformed datum does not convert, so it is left in the mailbox.
+    Message::custom(CELEBRATE).with_i64(16).send_to(g.address());
+    g.send(Greeting::Greet("Dewey".to_string()));
+    let msg = rx.recv().unwrap();
+    assert_eq!(msg.get_datum().as_str(), Some("Hello Dewey".to_string()));
+}
+
+#[test]
+fn test_untyped_interop() {
+    let (tx, rx) = mpsc::channel();
+    let me = ActorAddress::new(tx);
+    let groups = ProcessGroups::spawn();
+
+    let g = greeter(&me);
+    groups.join("greeters", g.as_ref());
+    let datum = MessageDatum::from(&g);
+
+    // Getting the address back from an untyped source.
+    let member: TypedAddress<Greeting> = TypedAddress::new(groups.members("greeters")[0].clone());
+    assert_eq!(member, g);
+    assert_eq!(TypedAddress::<Greeting>::new(datum.as_act().unwrap()), g);
+
+    member.send(Greeting::Greet("Huey".to_string()));
+    let msg = rx.recv().unwrap();
+    assert_eq!(msg.get_datum().as_str(), Some("Hello Huey".to_string()));
+    assert_eq!(Greeting::from_message(&Message::custom(GREET).with_str("Huey").build()),
+               Some(Greeting::Greet("Huey".to_string())));
+}
+
+#[test]
+fn test_declared_types() {
+    assert!(Atom::lookup(":typed_shout").is_none());
+    let listener = Actor::new().with_typed_handler(|_: Shout, _: &mut (), _| Ok(())).spawn();
+    assert!(Atom::lookup(":typed_shout").is_some());
+    Message::shutdown().send_to(&listener);
+}
+
+}
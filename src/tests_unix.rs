@@ -0,0 +1,178 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(all(test, unix))]
+mod unix {
+
+use Atom;
+use MessageType;
+use MessageDatum;
+use Message;
+use ActorAddress;
+use Node;
+
+use std::env;
+use std::io;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+const HELLO: &str = ":hello";
+const SQUARE: &str = ":square";
+const SQUARED: &str = ":squared";
+const STOP: &str = ":stop";
+
+// The environment variables which tell the child test which node to join,
+// its index, and the process id used in the socket paths.
+const PARENT_VAR: &str = "MECHA_TEST_UNIX_PARENT";
+const CHILD_VAR: &str = "MECHA_TEST_UNIX_CHILD";
+const PID_VAR: &str = "MECHA_TEST_UNIX_PID";
+
+fn socket_name(label: &str, pid: u32) -> String {
+    let path = env::temp_dir().join(format!("mecha-{}-{}.sock", pid, label));
+    format!("{}@unix:{}", label, path.display())
+}
+
+// Runs in the child processes started by test_child_processes: joins the
+// parent node, says hello, and squares numbers until told to stop.
+#[test]
+#[ignore]
+fn child_node() {
+    let parent = match env::var(PARENT_VAR) {
+        Ok(parent) => parent,
+        Err(_) => { return; }
+    };
+    let index: i64 = env::var(CHILD_VAR).unwrap().parse().unwrap();
+    let pid = env::var(PID_VAR).unwrap().parse().unwrap();
+    // The child only receives these, so it declares them.
+    Atom::from_static(SQUARE);
+    Atom::from_static(STOP);
+    let node = Node::start(&socket_name(&format!("child{}", index), pid)).unwrap();
+    node.connect_node(&parent).unwrap();
+    let collector = node.whereis(parent.as_str(), "collector").unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let me = ActorAddress::new(tx);
+    Message::custom(HELLO).with_sender(&me)
+                          .with_datum(MessageDatum::from((index, node.name())))
+                          .send_to(&collector);
+    while let Ok(msg) = rx.recv_timeout(Duration::from_secs(10)) {
+        match *msg.get_type() {
+            MessageType::Custom(SQUARE) => {
+                let n = msg.get_datum().as_i64().unwrap();
+                Message::custom(SQUARED).with_sender(&me)
+                                        .with_datum(MessageDatum::from(vec![
+                                            MessageDatum::from(index),
+                                            MessageDatum::from(n * n)
+                                        ]))
+                                        .send_to(msg.get_sender());
+            },
+            MessageType::Custom(STOP) => { break; },
+            _ => {}
+        }
+    }
+    node.shutdown();
+}
+
+#[test]
+fn test_child_processes() {
+    let pid = ::std::process::id();
+    // Likewise, the parent only receives these.
+    Atom::from_static(HELLO);
+    Atom::from_static(SQUARED);
+    let parent = Node::start(&socket_name("parent", pid)).unwrap();
+    assert!(parent.address().starts_with("unix:"));
+    let (tx, rx) = mpsc::channel();
+    let collector = ActorAddress::new(tx);
+    parent.register("collector", &collector);
+
+    let children: Vec<_> = (0..2).map(|i| {
+        Command::new(env::current_exe().unwrap())
+            .args(["--exact", "tests_unix::unix::child_node", "--ignored", "--test-threads=1"])
+            .env(PARENT_VAR, parent.name())
+            .env(CHILD_VAR, i.to_string())
+            .env(PID_VAR, pid.to_string())
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap()
+    }).collect();
+
+    // Both children say hello, with their index and node name.
+    let mut senders = [None, None];
+    for _ in 0..2 {
+        let msg = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(*msg.get_type(), MessageType::Custom(HELLO));
+        let hello = msg.get_datum().as_tuple_ref().unwrap();
+        let index = hello[0].as_i64().unwrap() as usize;
+        assert_eq!(hello[1].as_str_ref(), Some(&socket_name(&format!("child{}", index), pid)[..]));
+        assert_eq!(msg.get_sender().node(), hello[1].as_str_ref());
+        senders[index] = Some(msg.get_sender().clone());
+    }
+    let mut peers = parent.peers();
+    peers.sort();
+    assert_eq!(peers, vec![socket_name("child0", pid), socket_name("child1", pid)]);
+
+    for (i, child) in senders.iter().enumerate() {
+        Message::custom(SQUARE).with_sender(&collector)
+                               .with_datum(MessageDatum::from(i as i64 + 3))
+                               .send_to(child.as_ref().unwrap());
+    }
+    let mut squares = vec![];
+    for _ in 0..2 {
+        let msg = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(*msg.get_type(), MessageType::Custom(SQUARED));
+        let reply = msg.get_datum().as_list().unwrap();
+        squares.push((reply[0].as_i64().unwrap(), reply[1].as_i64().unwrap()));
+    }
+    squares.sort();
+    assert_eq!(squares, vec![(0, 9), (1, 16)]);
+
+    for child in senders.iter() {
+        Message::custom(STOP).send_to(child.as_ref().unwrap());
+    }
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+    parent.shutdown();
+}
+
+#[test]
+fn test_node_names() {
+    let pid = ::std::process::id();
+    assert_eq!(Node::start("plain").err().map(|e| e.kind()), Some(io::ErrorKind::InvalidInput));
+
+    // Nodes on Unix domain sockets and on TCP can connect to each other.
+    let a = Node::start(&socket_name("a_names", pid)).unwrap();
+    let b = Node::listen("b_names", "127.0.0.1:0").unwrap();
+    let c = Node::listen("c_names", "127.0.0.1:0").unwrap();
+    b.connect_node(a.name()).unwrap();
+    assert_eq!(c.connect(a.address()).unwrap(), a.name());
+    assert_eq!(a.connect(c.address()).unwrap(), "c_names");
+    let mut peers = a.peers();
+    peers.sort();
+    assert_eq!(peers, vec!["b_names".to_string(), "c_names".to_string()]);
+
+    // The name must match the node listening at its address.
+    let wrong = a.name().replace("a_names", "z_names");
+    assert!(c.connect_node(&wrong).is_err());
+
+    // The socket is removed when the node shuts down.
+    let path = a.address()["unix:".len()..].to_string();
+    a.shutdown();
+    for _ in 0..100 {
+        if ::std::fs::metadata(&path).is_err() {
+            break;
+        }
+        ::std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(::std::fs::metadata(&path).is_err());
+    b.shutdown();
+    c.shutdown();
+}
+
+}
@@ -0,0 +1,109 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod value {
+
+use MessageDatum;
+use Message;
+use ActorAddress;
+use to_datum;
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+
+const CONFIGURE: &str = ":configure";
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Server {
+    host: String,
+    port: u16,
+    ratio: f64,
+    secure: bool,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+enum Mode {
+    Idle,
+    Fixed(u32),
+    Range(i64, i64),
+    Named { name: String },
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Config {
+    server: Server,
+    tags: Vec<String>,
+    modes: Vec<Mode>,
+    backup: Option<Server>,
+    weights: HashMap<u32, f64>,
+    pair: (char, ()),
+}
+
+fn config() -> Config {
+    let mut weights = HashMap::new();
+    weights.insert(1, 0.5);
+    weights.insert(7, 2.0);
+    Config {
+        server: Server { host: "localhost".to_string(), port: 8080, ratio: 0.5, secure: true },
+        tags: vec!["a".to_string(), "b".to_string()],
+        modes: vec![Mode::Idle, Mode::Fixed(3), Mode::Range(-1, 1),
+                    Mode::Named { name: "x".to_string() }],
+        backup: None,
+        weights,
+        pair: ('c', ()),
+    }
+}
+
+#[test]
+fn test_value_roundtrip() {
+    let (tx, rx) = mpsc::channel();
+    let me = ActorAddress::new(tx);
+    Message::custom(CONFIGURE).with_value(&config()).unwrap().send_to(&me);
+
+    let msg = rx.recv().unwrap();
+    let datum = msg.get_datum();
+    let server = datum.as_map().unwrap()["server"].as_map().unwrap();
+    assert_eq!(server["port"], MessageDatum::U64(8080));
+    assert_eq!(server["host"], MessageDatum::from("localhost"));
+    assert_eq!(datum.as_map().unwrap()["backup"], MessageDatum::Nil);
+    assert_eq!(server["secure"], MessageDatum::Bool(true));
+    assert_eq!(datum.as_map().unwrap()["tags"],
+               MessageDatum::from(vec![MessageDatum::from("a"), MessageDatum::from("b")]));
+    assert_eq!(datum.as_map().unwrap()["pair"],
+               MessageDatum::from(("c", MessageDatum::Void)));
+    assert_eq!(datum.decode::<Config>().unwrap(), config());
+}
+
+#[test]
+fn test_value_errors() {
+    let mut config = to_datum(&config()).unwrap().as_map().unwrap();
+    let mut server = config["server"].as_map().unwrap();
+    server.insert("port".to_string(), MessageDatum::from("eighty"));
+    config.insert("server".to_string(), MessageDatum::from(server));
+    let e = MessageDatum::from(config).decode::<Config>().unwrap_err();
+    assert_eq!(e.path(), "$.server.port");
+    assert!(e.message().contains("eighty"), "{}", e);
+
+    let mut m = HashMap::new();
+    m.insert("host".to_string(), MessageDatum::from("localhost"));
+    let e = MessageDatum::from(m).decode::<Server>().unwrap_err();
+    assert_eq!(e.path(), "$");
+    assert!(e.message().contains("missing field"), "{}", e);
+
+    let datum = to_datum(&vec![Mode::Fixed(1), Mode::Range(1, 2)]).unwrap();
+    assert!(datum.decode::<Vec<Mode>>().is_ok());
+    let e = datum.decode::<Vec<HashMap<String, i64>>>().unwrap_err();
+    assert_eq!(e.path(), "$.1.Range");
+
+    let mut bad_keys = HashMap::new();
+    bad_keys.insert(vec![1], 1);
+    assert!(to_datum(&bad_keys).is_err());
+}
+
+}
@@ -0,0 +1,79 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The timer delivers the messages sent with `MessageBuilder::send_after`.
+//!
+//! A single thread, started the first time a message is scheduled, keeps the
+//! pending messages in a heap ordered by deadline, and sleeps until the
+//! earliest one is due or a new one is scheduled. Messages with the same
+//! deadline are delivered in the order they were scheduled.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::mpsc;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Instant;
+
+use ActorAddress;
+use Message;
+
+struct Timer {
+    deadline: Instant,
+    seq: u64,
+    to: ActorAddress,
+    msg: Message,
+}
+
+// The heap is a max-heap, so the earliest deadline compares as the greatest.
+impl Ord for Timer {
+    fn cmp(&self, other: &Timer) -> Ordering {
+        (other.deadline, other.seq).cmp(&(self.deadline, self.seq))
+    }
+}
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Timer) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+impl PartialEq for Timer {
+    fn eq(&self, other: &Timer) -> bool { self.cmp(other) == Ordering::Equal }
+}
+impl Eq for Timer {}
+
+fn timer() -> &'static mpsc::Sender<(Instant, ActorAddress, Message)> {
+    static TIMER: OnceLock<mpsc::Sender<(Instant, ActorAddress, Message)>> = OnceLock::new();
+    TIMER.get_or_init(|| {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || timer_loop(rx));
+        tx
+    })
+}
+
+fn timer_loop(rx: mpsc::Receiver<(Instant, ActorAddress, Message)>) {
+    let mut heap = BinaryHeap::new();
+    let mut seq = 0;
+    loop {
+        let now = Instant::now();
+        while heap.peek().is_some_and(|t: &Timer| t.deadline <= now) {
+            let t = heap.pop().unwrap();
+            t.to.deliver(t.msg);
+        }
+        let next = match heap.peek() {
+            Some(t) => rx.recv_timeout(t.deadline - now).map_err(|_| ()),
+            None => rx.recv().map_err(|_| ()),
+        };
+        if let Ok((deadline, to, msg)) = next {
+            seq += 1;
+            heap.push(Timer { deadline, seq, to, msg });
+        }
+    }
+}
+
+/// Delivers the message to the actor once the deadline has passed.
+pub fn schedule(deadline: Instant, to: &ActorAddress, msg: Message) {
+    let _ = timer().send((deadline, to.clone(), msg));
+}
@@ -0,0 +1,172 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The transports nodes use to talk to each other. The transport is chosen by
+//! the address: `unix:<path>` is a Unix domain socket (on Unix platforms), and
+//! anything else is a TCP `host:port` address.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+
+/// The prefix of the addresses of Unix domain sockets.
+pub const UNIX_PREFIX: &str = "unix:";
+
+/// A listening socket.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    /// Binds to the address. A stale Unix domain socket (one nobody listens
+    /// on anymore) is replaced.
+    pub fn bind(address: &str) -> io::Result<Listener> {
+        if let Some(path) = address.strip_prefix(UNIX_PREFIX) {
+            #[cfg(unix)]
+            {
+                if fs::metadata(path).is_ok() && UnixStream::connect(path).is_err() {
+                    fs::remove_file(path)?;
+                }
+                return Ok(Listener::Unix(UnixListener::bind(path)?, PathBuf::from(path)));
+            }
+            #[cfg(not(unix))]
+            {
+                let _ = path;
+                return Err(unsupported());
+            }
+        }
+        Ok(Listener::Tcp(TcpListener::bind(address)?))
+    }
+
+    /// Gets the address the socket listens on, in the form accepted by
+    /// `bind` and `Stream::connect`.
+    pub fn address(&self) -> io::Result<String> {
+        match *self {
+            Listener::Tcp(ref l) => Ok(l.local_addr()?.to_string()),
+            #[cfg(unix)]
+            Listener::Unix(_, ref path) => Ok(format!("{}{}", UNIX_PREFIX, path.display())),
+        }
+    }
+
+    /// Waits for a connection.
+    pub fn accept(&self) -> io::Result<Stream> {
+        match *self {
+            Listener::Tcp(ref l) => {
+                let (s, _) = l.accept()?;
+                let _ = s.set_nodelay(true);
+                Ok(Stream::Tcp(s))
+            },
+            #[cfg(unix)]
+            Listener::Unix(ref l, _) => Ok(Stream::Unix(l.accept()?.0)),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, ref path) = *self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// A connected socket.
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    /// Connects to the address.
+    pub fn connect(address: &str) -> io::Result<Stream> {
+        if let Some(path) = address.strip_prefix(UNIX_PREFIX) {
+            #[cfg(unix)]
+            {
+                return Ok(Stream::Unix(UnixStream::connect(path)?));
+            }
+            #[cfg(not(unix))]
+            {
+                let _ = path;
+                return Err(unsupported());
+            }
+        }
+        let s = TcpStream::connect(address)?;
+        let _ = s.set_nodelay(true);
+        Ok(Stream::Tcp(s))
+    }
+
+    pub fn try_clone(&self) -> io::Result<Stream> {
+        match *self {
+            Stream::Tcp(ref s) => Ok(Stream::Tcp(s.try_clone()?)),
+            #[cfg(unix)]
+            Stream::Unix(ref s) => Ok(Stream::Unix(s.try_clone()?)),
+        }
+    }
+
+    /// Describes the other end of the socket, for logging.
+    pub fn peer(&self) -> String {
+        match *self {
+            Stream::Tcp(ref s) => s.peer_addr().map_or_else(|_| "an unknown address".to_string(),
+                                                             |a| a.to_string()),
+            #[cfg(unix)]
+            Stream::Unix(_) => "a Unix domain socket".to_string(),
+        }
+    }
+
+    /// Closes the socket in both directions, which also wakes up the threads
+    /// reading from it.
+    pub fn close(&self) {
+        let _ = match *self {
+            Stream::Tcp(ref s) => s.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            Stream::Unix(ref s) => s.shutdown(Shutdown::Both),
+        };
+    }
+}
+
+impl Read for &Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match **self {
+            Stream::Tcp(ref s) => { let mut s = s; s.read(buf) },
+            #[cfg(unix)]
+            Stream::Unix(ref s) => { let mut s = s; s.read(buf) },
+        }
+    }
+}
+
+impl Write for &Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match **self {
+            Stream::Tcp(ref s) => { let mut s = s; s.write(buf) },
+            #[cfg(unix)]
+            Stream::Unix(ref s) => { let mut s = s; s.write(buf) },
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match **self {
+            Stream::Tcp(ref s) => { let mut s = s; s.flush() },
+            #[cfg(unix)]
+            Stream::Unix(ref s) => { let mut s = s; s.flush() },
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "Unix domain sockets are not supported")
+}
@@ -0,0 +1,232 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Typed addresses are a statically typed layer on top of ActorAddress, which
+//! only allow sending the messages an actor accepts.
+//!
+//! The accepted messages are described by a user type (usually an enum) that
+//! implements `TypedMessage`, which converts each message to and from a Custom
+//! message type and a MessageDatum. Mistakes such as sending the wrong Custom
+//! type to an actor are then caught by the compiler, instead of being noticed
+//! when messages pile up unmatched in its mailbox.
+//!
+//! A TypedAddress is just an ActorAddress with a type attached, so it can be
+//! turned back into one for linking, process groups or anything else which
+//! does not care about the type of the messages.
+//!
+//! The fields of the messages can be converted from a MessageDatum with the
+//! `FromDatum` trait, which is what `#[derive(MechaMessage)]` (in the
+//! mecha_derive crate, behind the "derive" feature) relies on.
+//!
+//! ```text
+//! let greeter: TypedAddress<Greeting> = Actor::new()
+//!     .with_typed_handler(|msg: Greeting, state, myself| { ... })
+//!     .spawn_typed();
+//! greeter.send(Greeting::Hello("Louie".to_string()));
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+use Actor;
+use Atom;
+use ActorAddress;
+use ActionResult;
+use Message;
+use MessageDatum;
+use MessageType;
+
+/// The TypedMessage trait describes how a user type is sent as a Custom
+/// message, and how it is recovered from one.
+pub trait TypedMessage: Sized + Send + 'static {
+    /// Gets the name of the Custom message type this message is sent as.
+    fn message_type(&self) -> &'static str;
+
+    /// Converts the message to the datum it is sent with.
+    fn to_datum(&self) -> MessageDatum;
+
+    /// Converts a Custom message type and its datum back to a message, or
+    /// returns None if they do not describe one.
+    fn from_datum(mt: &str, datum: &MessageDatum) -> Option<Self>;
+
+    /// Gets the names of all the Custom message types of this type. Actors
+    /// which handle the messages with `with_typed_handler` declare them in the
+    /// atom table, so that their node can receive them from other processes.
+    /// The default is none, which leaves them to be declared by hand.
+    fn message_types() -> &'static [&'static str] { &[] }
+
+    /// Converts a received Message back to a message, or returns None if it
+    /// is not one.
+    fn from_message(msg: &Message) -> Option<Self> {
+        match *msg.get_type() {
+            MessageType::Custom(mt) => Self::from_datum(mt, msg.get_datum()),
+            _ => None
+        }
+    }
+}
+
+/// The FromDatum trait converts a MessageDatum back to a value that has been
+/// converted to one with `MessageDatum::from`, returning None if the datum has
+/// the wrong shape.
+pub trait FromDatum: Sized {
+    /// Converts the datum to a value, if possible.
+    fn from_datum(datum: &MessageDatum) -> Option<Self>;
+}
+
+impl FromDatum for MessageDatum {
+    fn from_datum(datum: &MessageDatum) -> Option<Self> { Some(datum.clone()) }
+}
+impl FromDatum for i64 {
+    fn from_datum(datum: &MessageDatum) -> Option<Self> { datum.as_i64() }
+}
+impl FromDatum for u64 {
+    fn from_datum(datum: &MessageDatum) -> Option<Self> { datum.as_u64() }
+}
+impl FromDatum for f64 {
+    fn from_datum(datum: &MessageDatum) -> Option<Self> { datum.as_f64() }
+}
+impl FromDatum for bool {
+    fn from_datum(datum: &MessageDatum) -> Option<Self> { datum.as_bool() }
+}
+impl FromDatum for String {
+    fn from_datum(datum: &MessageDatum) -> Option<Self> { datum.as_str() }
+}
+impl FromDatum for Vec<u8> {
+    fn from_datum(datum: &MessageDatum) -> Option<Self> { datum.as_bytes() }
+}
+impl FromDatum for Vec<MessageDatum> {
+    fn from_datum(datum: &MessageDatum) -> Option<Self> { datum.as_list() }
+}
+impl FromDatum for HashMap<String, MessageDatum> {
+    fn from_datum(datum: &MessageDatum) -> Option<Self> { datum.as_map() }
+}
+impl FromDatum for ActorAddress {
+    fn from_datum(datum: &MessageDatum) -> Option<Self> { datum.as_act() }
+}
+impl<M: TypedMessage> FromDatum for TypedAddress<M> {
+    fn from_datum(datum: &MessageDatum) -> Option<Self> { datum.as_act().map(TypedAddress::new) }
+}
+/// Nil converts to None, like `MessageDatum::from(None)`.
+impl<T: FromDatum> FromDatum for Option<T> {
+    fn from_datum(datum: &MessageDatum) -> Option<Self> {
+        match *datum {
+            MessageDatum::Nil => Some(None),
+            _ => T::from_datum(datum).map(Some)
+        }
+    }
+}
+
+/// A TypedAddress is an ActorAddress which only accepts messages of type M. It
+/// can be cheaply cloned and passed around, like an ActorAddress.
+pub struct TypedAddress<M> {
+    address: ActorAddress,
+    accepts: PhantomData<fn(M)>
+}
+
+impl<M: TypedMessage> TypedAddress<M> {
+    /// Creates a TypedAddress from an ActorAddress. Nothing checks that the
+    /// actor really accepts messages of type M, so this should only be used
+    /// for addresses that come from an untyped source (a process group, a
+    /// MessageDatum, ...).
+    pub fn new(address: ActorAddress) -> TypedAddress<M> {
+        TypedAddress { address, accepts: PhantomData }
+    }
+
+    /// Sends a message to the actor.
+    pub fn send(&self, msg: M) {
+        Message::custom(msg.message_type()).with_datum(msg.to_datum()).send_to(&self.address);
+    }
+
+    /// Sends a message to the actor, specifying its sender.
+    pub fn send_from(&self, msg: M, sender: &ActorAddress) {
+        Message::custom(msg.message_type())
+            .with_sender(sender)
+            .with_datum(msg.to_datum())
+            .send_to(&self.address);
+    }
+
+    /// Gets the untyped address of the actor.
+    pub fn address(&self) -> &ActorAddress { &self.address }
+
+    /// Consumes the TypedAddress and returns the untyped address of the actor.
+    pub fn into_address(self) -> ActorAddress { self.address }
+}
+
+impl<M> Clone for TypedAddress<M> {
+    fn clone(&self) -> Self {
+        TypedAddress { address: self.address.clone(), accepts: PhantomData }
+    }
+}
+
+impl<M> fmt::Debug for TypedAddress<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("TypedAddress").field(&self.address).finish()
+    }
+}
+
+/// TypedAddresses are equal if they identify the same actor process, like
+/// ActorAddresses.
+impl<M> PartialEq for TypedAddress<M> {
+    fn eq(&self, other: &TypedAddress<M>) -> bool { self.address == other.address }
+}
+impl<M> Eq for TypedAddress<M> {}
+impl<M> Hash for TypedAddress<M> {
+    fn hash<H: Hasher>(&self, state: &mut H) { self.address.hash(state); }
+}
+
+impl<M> AsRef<ActorAddress> for TypedAddress<M> {
+    fn as_ref(&self) -> &ActorAddress { &self.address }
+}
+
+impl<M> From<TypedAddress<M>> for ActorAddress {
+    fn from(t: TypedAddress<M>) -> ActorAddress { t.address }
+}
+
+impl<M> From<TypedAddress<M>> for MessageDatum {
+    fn from(t: TypedAddress<M>) -> MessageDatum { MessageDatum::Act(t.address) }
+}
+impl<'a, M> From<&'a TypedAddress<M>> for MessageDatum {
+    fn from(t: &'a TypedAddress<M>) -> MessageDatum { MessageDatum::Act(t.address.clone()) }
+}
+
+impl<ActorState: 'static + Sized + Default + Send> Actor<ActorState> {
+    /// Adds a match clause which matches the messages of type M, and an action
+    /// clause which handles them. The action clause takes the converted
+    /// message instead of the Message, and otherwise works like the ones added
+    /// with `with_action`. The message types of M are declared in the atom
+    /// table.
+    pub fn with_typed_handler<M, T>(self, ac: T) -> Self
+        where M: TypedMessage,
+              T: 'static + Fn(M, &mut ActorState, &ActorAddress) -> ActionResult + Send {
+        for mt in M::message_types() {
+            Atom::from_static(mt);
+        }
+        self.with_match(|msg, _| M::from_message(msg).is_some())
+            .with_action(move |msg, state, myself| {
+                match M::from_message(msg) {
+                    Some(m) => ac(m, state, myself),
+                    None => Ok(())
+                }
+            })
+    }
+
+    /// Consumes the Actor building blocks and spawns the actor process, like
+    /// `spawn`, returning a TypedAddress for sending messages of type M to it.
+    pub fn spawn_typed<M: TypedMessage>(self) -> TypedAddress<M> {
+        TypedAddress::new(self.spawn())
+    }
+
+    /// Consumes the Actor building blocks and spawns the actor process linked
+    /// to the provided actor, like `spawn_link`, returning a TypedAddress for
+    /// sending messages of type M to it.
+    pub fn spawn_link_typed<M: TypedMessage>(self, uplink: &ActorAddress) -> TypedAddress<M> {
+        TypedAddress::new(self.spawn_link(uplink))
+    }
+}
@@ -0,0 +1,598 @@
+// Copyright 2017 Dario Domizioli ("hhexo").
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A bridge between serde and MessageDatum, available with the `serde`
+//! feature.
+//!
+//! Any type implementing `Serialize` can be turned into a MessageDatum with
+//! `to_datum` (or sent directly with `MessageBuilder::with_value`), and any
+//! type implementing `Deserialize` can be read back from a MessageDatum with
+//! `from_datum` (or `MessageDatum::decode`).
+//!
+//! - Signed integers are I64, unsigned integers are U64 and floats are F64.
+//!   Booleans are Bool and byte buffers (with `serde_bytes`) are Bytes.
+//! - Strings and chars are Str. Unit variants of enums are the name of the
+//!   variant.
+//! - Structs and maps are Map. Map keys must be strings, chars or integers.
+//! - Sequences are List, and tuples and tuple structs are Tuple.
+//! - `None` is Nil and `()` is Void; `Some(x)` and newtype structs are just
+//!   `x`.
+//! - Other enum variants are a Map with the name of the variant as the only
+//!   key, and the contents of the variant as its value.
+//!
+//! ```text
+//! #[derive(Serialize, Deserialize)]
+//! struct Point { x: i64, y: i64 }
+//!
+//! mecha::Message::custom("move").with_value(&Point { x: 1, y: 2 })?.send_to(&actor);
+//! ...
+//! let p: Point = msg.get_datum().decode()?;
+//! ```
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use serde::de::{self, DeserializeOwned, Unexpected, Visitor};
+use serde::ser::{self, Serialize};
+
+use MessageBuilder;
+use MessageDatum;
+use datum_path;
+
+/// An error found while converting between a Rust value and a MessageDatum.
+/// It reports the path of the field where the error was found, such as
+/// `$.server.port`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ValueError {
+    message: String,
+    // The keys leading to the failing field, from the innermost.
+    keys: Vec<String>,
+}
+
+impl ValueError {
+    /// Gets the description of the error.
+    pub fn message(&self) -> &str { &self.message }
+
+    /// Gets the path of the field where the error was found. The root is `$`,
+    /// and each key is appended as `.key`, or `["key"]` if it is not a plain
+    /// identifier.
+    pub fn path(&self) -> String {
+        let keys: Vec<String> = self.keys.iter().rev().cloned().collect();
+        datum_path(&keys)
+    }
+
+    fn within(mut self, key: &str) -> ValueError {
+        self.keys.push(key.to_string());
+        self
+    }
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.path())
+    }
+}
+
+impl Error for ValueError {}
+
+impl ser::Error for ValueError {
+    fn custom<T: fmt::Display>(msg: T) -> ValueError {
+        ValueError { message: msg.to_string(), keys: vec![] }
+    }
+}
+
+impl de::Error for ValueError {
+    fn custom<T: fmt::Display>(msg: T) -> ValueError {
+        ValueError { message: msg.to_string(), keys: vec![] }
+    }
+}
+
+/// Converts a serializable value into a MessageDatum.
+pub fn to_datum<T: Serialize + ?Sized>(value: &T) -> Result<MessageDatum, ValueError> {
+    value.serialize(DatumSerializer)
+}
+
+/// Converts a MessageDatum into a deserializable value.
+pub fn from_datum<T: DeserializeOwned>(datum: &MessageDatum) -> Result<T, ValueError> {
+    T::deserialize(datum)
+}
+
+impl MessageDatum {
+    /// Converts the MessageDatum into a deserializable value.
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, ValueError> {
+        from_datum(self)
+    }
+}
+
+impl MessageBuilder {
+    /// Specifies a serializable value as the datum of the message.
+    pub fn with_value<T: Serialize + ?Sized>(&mut self, value: &T)
+        -> Result<&mut MessageBuilder, ValueError> {
+        Ok(self.with_datum(to_datum(value)?))
+    }
+}
+
+fn variant(name: &'static str, datum: MessageDatum) -> MessageDatum {
+    let mut m = HashMap::new();
+    m.insert(name.to_string(), datum);
+    MessageDatum::from(m)
+}
+
+fn within_variant(e: ValueError, variant: Option<&'static str>) -> ValueError {
+    match variant {
+        Some(name) => e.within(name),
+        None => e
+    }
+}
+
+/// A serde Serializer which produces a MessageDatum.
+pub struct DatumSerializer;
+
+impl ser::Serializer for DatumSerializer {
+    type Ok = MessageDatum;
+    type Error = ValueError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::Bool(v))
+    }
+    fn serialize_i8(self, v: i8) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::I64(v.into()))
+    }
+    fn serialize_i16(self, v: i16) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::I64(v.into()))
+    }
+    fn serialize_i32(self, v: i32) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::I64(v.into()))
+    }
+    fn serialize_i64(self, v: i64) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::I64(v))
+    }
+    fn serialize_u8(self, v: u8) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::U64(v.into()))
+    }
+    fn serialize_u16(self, v: u16) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::U64(v.into()))
+    }
+    fn serialize_u32(self, v: u32) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::U64(v.into()))
+    }
+    fn serialize_u64(self, v: u64) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::U64(v))
+    }
+    fn serialize_f32(self, v: f32) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::F64(v.into()))
+    }
+    fn serialize_f64(self, v: f64) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::F64(v))
+    }
+    fn serialize_char(self, v: char) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::from(v.to_string()))
+    }
+    fn serialize_str(self, v: &str) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::from(v.to_string()))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::from(v))
+    }
+    fn serialize_none(self) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::Nil)
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<MessageDatum, ValueError> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::Void)
+    }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::Void)
+    }
+    fn serialize_unit_variant(self, _: &'static str, _: u32, name: &'static str)
+        -> Result<MessageDatum, ValueError> {
+        Ok(MessageDatum::from(name))
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _: &'static str, value: &T)
+        -> Result<MessageDatum, ValueError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _: &'static str, _: u32,
+                                                        name: &'static str, value: &T)
+        -> Result<MessageDatum, ValueError> {
+        let datum = value.serialize(self).map_err(|e| e.within(name))?;
+        Ok(variant(name, datum))
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, ValueError> {
+        Ok(SeqSerializer { items: Vec::with_capacity(len.unwrap_or(0)), tuple: false,
+                           variant: None })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, ValueError> {
+        Ok(SeqSerializer { items: Vec::with_capacity(len), tuple: true, variant: None })
+    }
+    fn serialize_tuple_struct(self, _: &'static str, len: usize)
+        -> Result<SeqSerializer, ValueError> {
+        self.serialize_tuple(len)
+    }
+    fn serialize_tuple_variant(self, _: &'static str, _: u32, name: &'static str, len: usize)
+        -> Result<SeqSerializer, ValueError> {
+        Ok(SeqSerializer { items: Vec::with_capacity(len), tuple: true, variant: Some(name) })
+    }
+    fn serialize_map(self, _: Option<usize>) -> Result<MapSerializer, ValueError> {
+        Ok(MapSerializer { map: HashMap::new(), key: None, variant: None })
+    }
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<MapSerializer, ValueError> {
+        self.serialize_map(None)
+    }
+    fn serialize_struct_variant(self, _: &'static str, _: u32, name: &'static str, _: usize)
+        -> Result<MapSerializer, ValueError> {
+        Ok(MapSerializer { map: HashMap::new(), key: None, variant: Some(name) })
+    }
+}
+
+/// Collects the items of a sequence, tuple or tuple variant.
+pub struct SeqSerializer {
+    items: Vec<MessageDatum>,
+    tuple: bool,
+    variant: Option<&'static str>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ValueError> {
+        let index = self.items.len().to_string();
+        let datum = value.serialize(DatumSerializer)
+            .map_err(|e| within_variant(e.within(&index), self.variant))?;
+        self.items.push(datum);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<MessageDatum, ValueError> {
+        let datum = if self.tuple {
+            MessageDatum::Tuple(self.items.into())
+        } else {
+            MessageDatum::List(self.items.into())
+        };
+        Ok(match self.variant {
+            Some(name) => variant(name, datum),
+            None => datum
+        })
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = MessageDatum;
+    type Error = ValueError;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.push(value)
+    }
+    fn end(self) -> Result<MessageDatum, ValueError> { self.finish() }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = MessageDatum;
+    type Error = ValueError;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.push(value)
+    }
+    fn end(self) -> Result<MessageDatum, ValueError> { self.finish() }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = MessageDatum;
+    type Error = ValueError;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.push(value)
+    }
+    fn end(self) -> Result<MessageDatum, ValueError> { self.finish() }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = MessageDatum;
+    type Error = ValueError;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.push(value)
+    }
+    fn end(self) -> Result<MessageDatum, ValueError> { self.finish() }
+}
+
+/// Collects the entries of a map, struct or struct variant.
+pub struct MapSerializer {
+    map: HashMap<String, MessageDatum>,
+    key: Option<String>,
+    variant: Option<&'static str>,
+}
+
+impl MapSerializer {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T)
+        -> Result<(), ValueError> {
+        let datum = value.serialize(DatumSerializer)
+            .map_err(|e| within_variant(e.within(&key), self.variant))?;
+        self.map.insert(key, datum);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<MessageDatum, ValueError> {
+        let datum = MessageDatum::from(self.map);
+        Ok(match self.variant {
+            Some(name) => variant(name, datum),
+            None => datum
+        })
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = MessageDatum;
+    type Error = ValueError;
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), ValueError> {
+        match key.serialize(DatumSerializer)? {
+            MessageDatum::Str(s) => { self.key = Some(s.to_string()); },
+            MessageDatum::I64(x) => { self.key = Some(x.to_string()); },
+            MessageDatum::U64(x) => { self.key = Some(x.to_string()); },
+            _ => { return Err(ser::Error::custom("map keys must be strings or integers")); }
+        }
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ValueError> {
+        let key = self.key.take().unwrap_or_default();
+        self.insert(key, value)
+    }
+    fn end(self) -> Result<MessageDatum, ValueError> { self.finish() }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = MessageDatum;
+    type Error = ValueError;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T)
+        -> Result<(), ValueError> {
+        self.insert(key.to_string(), value)
+    }
+    fn end(self) -> Result<MessageDatum, ValueError> { self.finish() }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = MessageDatum;
+    type Error = ValueError;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T)
+        -> Result<(), ValueError> {
+        self.insert(key.to_string(), value)
+    }
+    fn end(self) -> Result<MessageDatum, ValueError> { self.finish() }
+}
+
+fn unexpected(datum: &MessageDatum) -> Unexpected<'_> {
+    match *datum {
+        MessageDatum::Void => Unexpected::Unit,
+        MessageDatum::I64(x) => Unexpected::Signed(x),
+        MessageDatum::U64(x) => Unexpected::Unsigned(x),
+        MessageDatum::F64(x) => Unexpected::Float(x),
+        MessageDatum::Str(ref s) => Unexpected::Str(s),
+        MessageDatum::Map(_) => Unexpected::Map,
+        MessageDatum::Act(_) => Unexpected::Other("actor address"),
+        MessageDatum::Bool(x) => Unexpected::Bool(x),
+        MessageDatum::Bytes(ref x) => Unexpected::Bytes(x),
+        MessageDatum::List(_) | MessageDatum::Tuple(_) => Unexpected::Seq,
+        MessageDatum::Nil => Unexpected::Option,
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &'de MessageDatum {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        match *self {
+            MessageDatum::Void => visitor.visit_unit(),
+            MessageDatum::I64(x) => visitor.visit_i64(x),
+            MessageDatum::U64(x) => visitor.visit_u64(x),
+            MessageDatum::F64(x) => visitor.visit_f64(x),
+            MessageDatum::Str(ref s) => visitor.visit_borrowed_str(s),
+            MessageDatum::Map(ref m) => {
+                visitor.visit_map(MapAccess { entries: m.iter(), value: None })
+            },
+            MessageDatum::Act(_) => Err(de::Error::invalid_type(unexpected(self), &visitor)),
+            MessageDatum::Bool(x) => visitor.visit_bool(x),
+            MessageDatum::Bytes(ref x) => visitor.visit_borrowed_bytes(x),
+            MessageDatum::List(ref items) | MessageDatum::Tuple(ref items) => {
+                visitor.visit_seq(SeqAccess { items: items.iter().enumerate() })
+            },
+            MessageDatum::Nil => visitor.visit_none(),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        match *self {
+            MessageDatum::Void | MessageDatum::Nil => visitor.visit_none(),
+            _ => visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V)
+        -> Result<V::Value, ValueError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _: usize, visitor: V)
+        -> Result<V::Value, ValueError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _: &'static str, _: usize, visitor: V)
+        -> Result<V::Value, ValueError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _: &'static str, _: &'static [&'static str],
+                                         visitor: V) -> Result<V::Value, ValueError> {
+        match *self {
+            MessageDatum::Str(ref s) => visitor.visit_enum(EnumAccess { name: s, value: None }),
+            MessageDatum::Map(ref m) if m.len() == 1 => {
+                let (name, value) = m.iter().next().unwrap();
+                visitor.visit_enum(EnumAccess { name, value: Some(value) })
+                       .map_err(|e| e.within(name))
+            },
+            _ => Err(de::Error::invalid_type(unexpected(self), &visitor))
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V)
+        -> Result<V::Value, ValueError> {
+        visitor.visit_unit()
+    }
+
+    ::serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+        seq unit unit_struct map struct identifier
+    }
+}
+
+struct MapAccess<'de> {
+    entries: ::std::collections::hash_map::Iter<'de, String, MessageDatum>,
+    value: Option<(&'de String, &'de MessageDatum)>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess<'de> {
+    type Error = ValueError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K)
+        -> Result<Option<K::Value>, ValueError> {
+        match self.entries.next() {
+            Some((k, v)) => {
+                self.value = Some((k, v));
+                seed.deserialize(KeyDeserializer(k)).map(Some).map_err(|e| e.within(k))
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V)
+        -> Result<V::Value, ValueError> {
+        match self.value.take() {
+            Some((k, v)) => seed.deserialize(v).map_err(|e| e.within(k)),
+            None => Err(de::Error::custom("value requested before key"))
+        }
+    }
+}
+
+struct SeqAccess<'de> {
+    items: ::std::iter::Enumerate<::std::slice::Iter<'de, MessageDatum>>,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess<'de> {
+    type Error = ValueError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T)
+        -> Result<Option<T::Value>, ValueError> {
+        match self.items.next() {
+            Some((index, item)) => {
+                seed.deserialize(item).map(Some).map_err(|e| e.within(&index.to_string()))
+            },
+            None => Ok(None)
+        }
+    }
+}
+
+struct EnumAccess<'de> {
+    name: &'de str,
+    value: Option<&'de MessageDatum>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess<'de> {
+    type Error = ValueError;
+    type Variant = VariantAccess<'de>;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V)
+        -> Result<(V::Value, Self::Variant), ValueError> {
+        let name = de::value::BorrowedStrDeserializer::new(self.name);
+        Ok((seed.deserialize(name)?, VariantAccess(self.value)))
+    }
+}
+
+struct VariantAccess<'de>(Option<&'de MessageDatum>);
+
+impl<'de> de::VariantAccess<'de> for VariantAccess<'de> {
+    type Error = ValueError;
+
+    fn unit_variant(self) -> Result<(), ValueError> {
+        match self.0 {
+            None | Some(&MessageDatum::Void) => Ok(()),
+            Some(d) => Err(de::Error::invalid_type(unexpected(d), &"unit variant"))
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T)
+        -> Result<T::Value, ValueError> {
+        match self.0 {
+            Some(d) => seed.deserialize(d),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"newtype variant"))
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, visitor: V)
+        -> Result<V::Value, ValueError> {
+        match self.0 {
+            Some(d) => de::Deserializer::deserialize_seq(d, visitor),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"tuple variant"))
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _: &'static [&'static str], visitor: V)
+        -> Result<V::Value, ValueError> {
+        match self.0 {
+            Some(d) => de::Deserializer::deserialize_any(d, visitor),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"struct variant"))
+        }
+    }
+}
+
+// Deserializes Map keys, which are strings but may stand for integers.
+struct KeyDeserializer<'de>(&'de str);
+
+macro_rules! deserialize_key_integer {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+                match self.0.parse() {
+                    Ok(x) => visitor.$visit(x),
+                    Err(_) => Err(de::Error::invalid_value(Unexpected::Str(self.0), &visitor))
+                }
+            }
+        )*
+    }
+}
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer<'de> {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    deserialize_key_integer! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+    }
+
+    ::serde::forward_to_deserialize_any! {
+        bool i128 u128 f32 f64 char str string bytes byte_buf option unit unit_struct
+        newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}